@@ -0,0 +1,376 @@
+// AV1 sequence and frame headers, as far as needed to follow the frame size
+// of every shown frame through key frames, reference scaling and superres.
+
+use super::bits::{leb128, BitReader};
+
+const OBU_SEQUENCE_HEADER: u8 = 1;
+const OBU_FRAME_HEADER: u8 = 3;
+const OBU_FRAME: u8 = 6;
+
+const KEY_FRAME: u32 = 0;
+const INTRA_ONLY_FRAME: u32 = 2;
+const SWITCH_FRAME: u32 = 3;
+
+// the sequence header fields the frame header syntax depends on
+#[derive(Clone, Default)]
+struct SequenceHeader {
+    reduced_still_picture_header: bool,
+    equal_picture_interval: bool,
+    decoder_model_info_present_flag: bool,
+    buffer_removal_time_length: u32,
+    frame_presentation_time_length: u32,
+    // (operating_point_idc, decoder_model_present_for_this_op)
+    operating_points: Vec<(u32, bool)>,
+    frame_width_bits: u32,
+    frame_height_bits: u32,
+    max_frame_width: u32,
+    max_frame_height: u32,
+    frame_id_numbers_present_flag: bool,
+    delta_frame_id_length: u32,
+    frame_id_length: u32,
+    enable_order_hint: bool,
+    order_hint_bits: u32,
+    seq_force_screen_content_tools: u32,
+    seq_force_integer_mv: u32,
+}
+
+impl SequenceHeader {
+    fn parse(data: &[u8]) -> Result<SequenceHeader, &'static str> {
+        let mut r = BitReader::new(data);
+        let mut seq = SequenceHeader::default();
+        let mut buffer_delay_length = 0;
+        r.skip(3)?; // seq_profile
+        r.skip(1)?; // still_picture
+        seq.reduced_still_picture_header = r.bit()?;
+        if seq.reduced_still_picture_header {
+            r.skip(5)?; // seq_level_idx[0]
+            seq.operating_points.push((0, false));
+        } else {
+            if r.bit()? {
+                // timing_info
+                r.skip(64)?; // num_units_in_display_tick, time_scale
+                seq.equal_picture_interval = r.bit()?;
+                if seq.equal_picture_interval {
+                    r.uvlc()?; // num_ticks_per_picture_minus_1
+                }
+                seq.decoder_model_info_present_flag = r.bit()?;
+                if seq.decoder_model_info_present_flag {
+                    buffer_delay_length = r.bits(5)? + 1;
+                    r.skip(32)?; // num_units_in_decoding_tick
+                    seq.buffer_removal_time_length = r.bits(5)? + 1;
+                    seq.frame_presentation_time_length = r.bits(5)? + 1;
+                }
+            }
+            let initial_display_delay_present_flag = r.bit()?;
+            let operating_points_cnt = r.bits(5)? + 1;
+            for _ in 0..operating_points_cnt {
+                let operating_point_idc = r.bits(12)?;
+                if r.bits(5)? > 7 {
+                    r.skip(1)?; // seq_tier
+                }
+                let mut decoder_model_present = false;
+                if seq.decoder_model_info_present_flag {
+                    decoder_model_present = r.bit()?;
+                    if decoder_model_present {
+                        // decoder_buffer_delay, encoder_buffer_delay, low_delay_mode_flag
+                        r.skip(2 * buffer_delay_length as usize + 1)?;
+                    }
+                }
+                if initial_display_delay_present_flag && r.bit()? {
+                    r.skip(4)?; // initial_display_delay_minus_1
+                }
+                seq.operating_points.push((operating_point_idc, decoder_model_present));
+            }
+        }
+
+        seq.frame_width_bits = r.bits(4)? + 1;
+        seq.frame_height_bits = r.bits(4)? + 1;
+        seq.max_frame_width = r.bits(seq.frame_width_bits)? + 1;
+        seq.max_frame_height = r.bits(seq.frame_height_bits)? + 1;
+        if !seq.reduced_still_picture_header {
+            seq.frame_id_numbers_present_flag = r.bit()?;
+        }
+        if seq.frame_id_numbers_present_flag {
+            seq.delta_frame_id_length = r.bits(4)? + 2;
+            seq.frame_id_length = seq.delta_frame_id_length + r.bits(3)? + 1;
+        }
+        r.skip(3)?; // use_128x128_superblock, enable_filter_intra, enable_intra_edge
+        seq.seq_force_screen_content_tools = 2;
+        seq.seq_force_integer_mv = 2;
+        if !seq.reduced_still_picture_header {
+            // enable_interintra_compound, enable_masked_compound,
+            // enable_warped_motion, enable_dual_filter
+            r.skip(4)?;
+            seq.enable_order_hint = r.bit()?;
+            if seq.enable_order_hint {
+                r.skip(2)?; // enable_jnt_comp, enable_ref_frame_mvs
+            }
+            if r.bit()? {
+                // seq_choose_screen_content_tools
+                seq.seq_force_screen_content_tools = 2;
+            } else {
+                seq.seq_force_screen_content_tools = r.bits(1)?;
+            }
+            if seq.seq_force_screen_content_tools > 0 {
+                if r.bit()? {
+                    // seq_choose_integer_mv
+                    seq.seq_force_integer_mv = 2;
+                } else {
+                    seq.seq_force_integer_mv = r.bits(1)?;
+                }
+            }
+            if seq.enable_order_hint {
+                seq.order_hint_bits = r.bits(3)? + 1;
+            }
+        }
+        Ok(seq)
+    }
+}
+
+// an OBU of a temporal unit, without its header and size field
+struct Obu<'a> {
+    obu_type: u8,
+    temporal_id: u32,
+    spatial_id: u32,
+    payload: &'a [u8],
+}
+
+fn obus(data: &[u8]) -> Result<Vec<Obu<'_>>, &'static str> {
+    let mut obus = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let header = data[offset];
+        let obu_type = (header >> 3) & 0xf;
+        let extension_flag = header & 0x4 != 0;
+        let has_size_field = header & 0x2 != 0;
+        let mut pos = offset + 1;
+        let (mut temporal_id, mut spatial_id) = (0, 0);
+        if extension_flag {
+            let extension = *data.get(pos).ok_or("av1 obu header ends early")?;
+            temporal_id = (extension >> 5) as u32;
+            spatial_id = ((extension >> 3) & 0x3) as u32;
+            pos += 1;
+        }
+        let size = if has_size_field {
+            let (size, bytes) = leb128(data.get(pos..).unwrap_or(&[]))?;
+            pos += bytes;
+            size as usize
+        } else {
+            data.len() - pos
+        };
+        if pos + size > data.len() {
+            return Err("av1 obu is larger than the temporal unit");
+        }
+        obus.push(Obu {
+            obu_type,
+            temporal_id,
+            spatial_id,
+            payload: &data[pos..pos + size],
+        });
+        offset = pos + size;
+    }
+    Ok(obus)
+}
+
+// whether the first frame of a temporal unit is a shown key frame, which
+// decoding can start from when a sequence header comes with it
+pub fn is_key_frame(data: &[u8]) -> bool {
+    let obus = match obus(data) {
+        Ok(obus) => obus,
+        Err(_) => return false,
+    };
+    let seq = match obus.iter().find(|obu| obu.obu_type == OBU_SEQUENCE_HEADER) {
+        Some(obu) => SequenceHeader::parse(obu.payload),
+        None => return false,
+    };
+    let frame = obus.iter()
+        .find(|obu| obu.obu_type == OBU_FRAME_HEADER || obu.obu_type == OBU_FRAME);
+    match (seq, frame) {
+        (Ok(seq), Some(_)) if seq.reduced_still_picture_header => true,
+        (Ok(_), Some(frame)) => {
+            // show_existing_frame, frame_type and show_frame
+            let mut r = BitReader::new(frame.payload);
+            r.bits(4).is_ok_and(|bits| bits == (KEY_FRAME << 1 | 1))
+        }
+        _ => false,
+    }
+}
+
+// (upscaled width, height, frame type) of a reference slot
+type Reference = (u32, u32, u32);
+
+// sizes of the 8 reference slots, which inter frames can take their size from
+#[derive(Default)]
+pub struct Av1Sizes {
+    seq: Option<SequenceHeader>,
+    refs: [Option<Reference>; 8],
+}
+
+impl Av1Sizes {
+    pub fn new() -> Av1Sizes {
+        Av1Sizes::default()
+    }
+
+    // sizes of the shown frames in a temporal unit of OBUs with size fields
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<(u32, u32)>, &'static str> {
+        let mut shown = Vec::new();
+        for obu in obus(data)? {
+            match obu.obu_type {
+                OBU_SEQUENCE_HEADER => self.seq = Some(SequenceHeader::parse(obu.payload)?),
+                OBU_FRAME_HEADER | OBU_FRAME => {
+                    if let Some(size) =
+                        self.frame_header(obu.payload, obu.temporal_id, obu.spatial_id)? {
+                        shown.push(size);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(shown)
+    }
+
+    // uncompressed_header() up to the frame size, then the reference update
+    fn frame_header(&mut self,
+                    data: &[u8],
+                    temporal_id: u32,
+                    spatial_id: u32)
+                    -> Result<Option<(u32, u32)>, &'static str> {
+        let seq = match self.seq {
+            Some(ref seq) => seq.clone(),
+            None => return Err("av1 frame header before the sequence header"),
+        };
+        let mut r = BitReader::new(data);
+
+        let (frame_type, show_frame, error_resilient_mode) = if seq.reduced_still_picture_header {
+            (KEY_FRAME, true, true)
+        } else {
+            if r.bit()? {
+                // show_existing_frame
+                let slot = r.bits(3)? as usize;
+                if seq.decoder_model_info_present_flag && !seq.equal_picture_interval {
+                    r.skip(seq.frame_presentation_time_length as usize)?;
+                }
+                let reference = self.refs[slot].ok_or("av1 frame shows an empty reference slot")?;
+                if reference.2 == KEY_FRAME {
+                    // showing a key frame resets all the slots to it
+                    self.refs = [Some(reference); 8];
+                }
+                return Ok(Some((reference.0, reference.1)));
+            }
+            let frame_type = r.bits(2)?;
+            let show_frame = r.bit()?;
+            if show_frame && seq.decoder_model_info_present_flag && !seq.equal_picture_interval {
+                r.skip(seq.frame_presentation_time_length as usize)?;
+            }
+            if !show_frame {
+                r.skip(1)?; // showable_frame
+            }
+            let error_resilient_mode = if frame_type == SWITCH_FRAME ||
+                                          (frame_type == KEY_FRAME && show_frame) {
+                true
+            } else {
+                r.bit()?
+            };
+            (frame_type, show_frame, error_resilient_mode)
+        };
+        let frame_is_intra = frame_type == KEY_FRAME || frame_type == INTRA_ONLY_FRAME;
+
+        r.skip(1)?; // disable_cdf_update
+        let allow_screen_content_tools = if seq.seq_force_screen_content_tools == 2 {
+            r.bits(1)?
+        } else {
+            seq.seq_force_screen_content_tools
+        };
+        if allow_screen_content_tools > 0 && seq.seq_force_integer_mv == 2 {
+            r.skip(1)?; // force_integer_mv
+        }
+        if seq.frame_id_numbers_present_flag {
+            r.skip(seq.frame_id_length as usize)?; // current_frame_id
+        }
+        let frame_size_override_flag = if frame_type == SWITCH_FRAME {
+            true
+        } else if seq.reduced_still_picture_header {
+            false
+        } else {
+            r.bit()?
+        };
+        r.skip(seq.order_hint_bits as usize)?; // order_hint
+        if !frame_is_intra && !error_resilient_mode {
+            r.skip(3)?; // primary_ref_frame
+        }
+        if seq.decoder_model_info_present_flag && r.bit()? {
+            // buffer_removal_time_present_flag
+            for &(idc, decoder_model_present) in seq.operating_points.iter() {
+                let in_temporal_layer = (idc >> temporal_id) & 1 == 1;
+                let in_spatial_layer = (idc >> (spatial_id + 8)) & 1 == 1;
+                if decoder_model_present && (idc == 0 || (in_temporal_layer && in_spatial_layer)) {
+                    r.skip(seq.buffer_removal_time_length as usize)?;
+                }
+            }
+        }
+        let refresh_frame_flags = if frame_type == SWITCH_FRAME ||
+                                     (frame_type == KEY_FRAME && show_frame) {
+            0xff
+        } else {
+            r.bits(8)?
+        };
+        if (!frame_is_intra || refresh_frame_flags != 0xff) && error_resilient_mode &&
+           seq.enable_order_hint {
+            r.skip(8 * seq.order_hint_bits as usize)?; // ref_order_hint[i]
+        }
+
+        let size = if frame_is_intra {
+            frame_size(&mut r, &seq, frame_size_override_flag)?
+        } else {
+            let frame_refs_short_signaling = seq.enable_order_hint && r.bit()?;
+            if frame_refs_short_signaling {
+                return Err("av1 frame_refs_short_signaling is not supported");
+            }
+            let mut ref_frame_idx = [0usize; 7];
+            for idx in ref_frame_idx.iter_mut() {
+                *idx = r.bits(3)? as usize;
+                if seq.frame_id_numbers_present_flag {
+                    r.skip(seq.delta_frame_id_length as usize)?; // delta_frame_id_minus_1
+                }
+            }
+            if frame_size_override_flag && !error_resilient_mode {
+                // frame_size_with_refs()
+                let mut size = None;
+                for idx in ref_frame_idx.iter() {
+                    if r.bit()? {
+                        let reference = self.refs[*idx]
+                            .ok_or("av1 frame refers to an empty reference slot")?;
+                        size = Some((reference.0, reference.1));
+                        break;
+                    }
+                }
+                match size {
+                    Some(size) => size,
+                    None => frame_size(&mut r, &seq, frame_size_override_flag)?,
+                }
+            } else {
+                frame_size(&mut r, &seq, frame_size_override_flag)?
+            }
+        };
+
+        for (slot, reference) in self.refs.iter_mut().enumerate() {
+            if refresh_frame_flags & (1 << slot) != 0 {
+                *reference = Some((size.0, size.1, frame_type));
+            }
+        }
+        Ok(if show_frame { Some(size) } else { None })
+    }
+}
+
+// frame_size() without the superres parameters, so the upscaled width the
+// decoder outputs
+fn frame_size(r: &mut BitReader,
+              seq: &SequenceHeader,
+              frame_size_override_flag: bool)
+              -> Result<(u32, u32), &'static str> {
+    if frame_size_override_flag {
+        Ok((r.bits(seq.frame_width_bits)? + 1, r.bits(seq.frame_height_bits)? + 1))
+    } else {
+        Ok((seq.max_frame_width, seq.max_frame_height))
+    }
+}
@@ -2,6 +2,11 @@ use std::fs::File;
 use std::io;
 use std::io::{Read, Write, Seek, SeekFrom};
 use std::io::{Error, ErrorKind};
+use super::bits::{escape, unescape};
+use super::cut::{parse_bytes, Cut, FrameInfo};
+use super::h264;
+use super::h265;
+use super::sei;
 use super::Bitstream;
 
 pub struct Bin {
@@ -9,14 +14,86 @@ pub struct Bin {
     pub output: String,
     pub frame_num: usize,
     pub h265: bool,
+    // print the SEI messages of every access unit
+    pub dump_sei: bool,
+    // SEI payload types to leave out of the segments, all of them if empty
+    pub strip_sei: Option<Vec<u32>>,
+    // the segments to write instead of splitting every frame_num frames
+    pub cut: Option<Cut>,
+    // also split at the next key frame once a segment holds this many bytes
+    pub max_bytes: Option<usize>,
+    // frame rate giving the times of --cut-at
+    pub fps: f64,
+}
+
+// what the SEI dump needs to know of the parameter sets
+#[derive(Default)]
+struct SeiDump {
+    h264: h264::ParameterSets,
+    h265: h265::ParameterSets,
 }
 
 impl Bin {
+    // a Bin that only scans the access units of the input, for the modes
+    // reading Annex-B streams
+    pub fn scanner(input: &str, h265: bool) -> Bin {
+        Bin {
+            input: input.to_string(),
+            output: String::new(),
+            frame_num: 0,
+            h265,
+            dump_sei: false,
+            strip_sei: None,
+            cut: None,
+            max_bytes: None,
+            fps: 30.0,
+        }
+    }
+
     pub fn helper() {
-        println!("Usage: rsplit bin input.bin output frame_num h264|h265")
+        println!("Usage: rsplit bin input.bin output frame_num h264|h265 [--dump-sei] \
+                  [--strip-sei[=5,6,...]] [--max-bytes=N[k|m]] {} [--fps=30]",
+                 Cut::usage())
     }
 
     pub fn new(args: &[String]) -> Result<Bin, &'static str> {
+        let mut dump_sei = false;
+        let mut strip_sei = None;
+        let mut cut = None;
+        let mut max_bytes = None;
+        let mut fps = 30.0;
+        let mut positional: Vec<String> = Vec::new();
+        for arg in args {
+            if arg == "--dump-sei" {
+                dump_sei = true;
+            } else if arg == "--strip-sei" {
+                strip_sei = Some(Vec::new());
+            } else if let Some(types) = arg.strip_prefix("--strip-sei=") {
+                let types = types.split(',')
+                    .map(|t| t.parse::<u32>())
+                    .collect::<Result<Vec<u32>, _>>()
+                    .map_err(|_| "can't parse SEI payload types as u32")?;
+                strip_sei = Some(types);
+            } else if let Some(parsed) = Cut::parse(arg) {
+                cut = Some(parsed?);
+            } else if let Some(size) = arg.strip_prefix("--max-bytes=") {
+                max_bytes = Some(parse_bytes(size)?);
+            } else if let Some(value) = arg.strip_prefix("--fps=") {
+                fps = match value.parse::<f64>() {
+                    Ok(fps) if fps > 0.0 => fps,
+                    _ => return Err("can't parse fps as a positive number"),
+                };
+            } else if arg.starts_with("--") {
+                return Err("unknown option for rsplit bin mode");
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        let args = &positional[..];
+        if cut.is_some() && max_bytes.is_some() {
+            return Err("--max-bytes doesn't go with a cut list");
+        }
+
         let l = args.len() as usize;
         if l < 6 {
             return Err("too less arguments for rsplit bin mode");
@@ -44,6 +121,11 @@ impl Bin {
             output: output,
             frame_num: frame_num,
             h265: h265,
+            dump_sei,
+            strip_sei,
+            cut,
+            max_bytes,
+            fps,
         })
     }
 
@@ -52,6 +134,11 @@ impl Bin {
                  4 + (self.h265 as i32),
                  self.input,
                  self.output);
+        // the segments of the cut list, from a first pass over the stream
+        let plan = match self.cut {
+            Some(ref cut) => Some(cut.plan(&self.frame_infos()?).map_err(Error::other)?),
+            None => None,
+        };
         let mut fi = try!(File::open(self.input.clone()));
 
         let mut bk_container: Vec<u8> = Vec::new();
@@ -59,6 +146,7 @@ impl Bin {
         let mut pre_frame_no = 0;
         let mut cur_frame_no = 0;
         let mut bak_byte_pos = 0;
+        let mut sei_dump = SeiDump::default();
         loop {
             let (eof, opt) = if self.h265 {
                 self.find_h265_nal_units(&mut fi)
@@ -72,6 +160,13 @@ impl Bin {
                     break;
                 }
             };
+            if self.dump_sei {
+                self.dump_sei_messages(cur_frame_no, &bs, &mut sei_dump);
+            }
+            if let Some(ref payload_types) = self.strip_sei {
+                bs.rewrite_nal_units(|nal| self.strip_sei_messages(nal, payload_types))
+                    .map_err(|e| Error::other(format!("frame {}: {}", cur_frame_no, e)))?;
+            }
             bs.buf_size = bs.frame_location[bs.nal_size] - bs.frame_location[0];
 
             if bs.idr_flag {
@@ -80,7 +175,34 @@ impl Bin {
                 print!(".");
             }
 
-            if bs.idr_flag && cur_frame_no - pre_frame_no >= (self.frame_num as i32) {
+            let frame = cur_frame_no as usize;
+            let (starts_segment, in_segment) = match plan {
+                Some(ref plan) => {
+                    (plan.iter().any(|&(first, _)| first == frame),
+                     plan.iter().any(|&(first, end)| first <= frame && frame < end))
+                }
+                None => {
+                    let bytes: usize = bs_container.iter().map(|b| b.buf_size as usize).sum();
+                    (bs.idr_flag &&
+                     (cur_frame_no - pre_frame_no >= (self.frame_num as i32) ||
+                      self.max_bytes.is_some_and(|max_bytes| bytes >= max_bytes)),
+                     true)
+                }
+            };
+            if plan.is_some() && (starts_segment || !in_segment) {
+                // the previous segment ends here, frames outside of the cut
+                // list are dropped
+                if !bs_container.is_empty() {
+                    self.write_to_file(&mut pre_frame_no,
+                                       cur_frame_no,
+                                       bak_byte_pos,
+                                       &mut bk_container,
+                                       &mut bs_container)?;
+                    bs_container.clear();
+                }
+                pre_frame_no = cur_frame_no;
+                bak_byte_pos = bk_container.len();
+            } else if starts_segment {
                 if let Err(e) = self.write_to_file(&mut pre_frame_no,
                                                    cur_frame_no,
                                                    bak_byte_pos,
@@ -112,7 +234,9 @@ impl Bin {
                 }
             }
 
-            bs_container.push(bs);
+            if in_segment {
+                bs_container.push(bs);
+            }
 
             if eof {
                 break;
@@ -121,6 +245,17 @@ impl Bin {
             cur_frame_no += 1;
         }
 
+        if plan.is_some() {
+            if !bs_container.is_empty() {
+                self.write_to_file(&mut pre_frame_no,
+                                   cur_frame_no + 1,
+                                   bak_byte_pos,
+                                   &mut bk_container,
+                                   &mut bs_container)?;
+            }
+            return Ok(());
+        }
+
         if let Err(e) = self.write_to_file(&mut pre_frame_no,
                                            cur_frame_no,
                                            bak_byte_pos,
@@ -132,7 +267,30 @@ impl Bin {
         Ok(())
     }
 
-    fn find_h265_nal_units(&self, fp_bs: &mut File) -> (bool, io::Result<Bitstream>) {
+    // the key frames, sizes and times of every access unit
+    fn frame_infos(&self) -> io::Result<Vec<FrameInfo>> {
+        let mut fi = File::open(&self.input)?;
+        let mut frames = Vec::new();
+        loop {
+            let (eof, bs) = if self.h265 {
+                self.find_h265_nal_units(&mut fi)
+            } else {
+                self.find_h264_nal_units(&mut fi)
+            };
+            let bs = bs?;
+            frames.push(FrameInfo {
+                key: bs.idr_flag,
+                size: (bs.frame_location[bs.nal_size] - bs.frame_location[0]) as usize,
+                time: frames.len() as f64 / self.fps,
+            });
+            if eof {
+                break;
+            }
+        }
+        Ok(frames)
+    }
+
+    pub fn find_h265_nal_units(&self, fp_bs: &mut File) -> (bool, io::Result<Bitstream>) {
         const START_CODE_SIZE: i32 = 3;
         const MAX_NAL_UNITS_PER_BS: usize = 600;
 
@@ -274,7 +432,7 @@ impl Bin {
         }
     }
 
-    fn find_h264_nal_units(&self, fp_bs: &mut File) -> (bool, io::Result<Bitstream>) {
+    pub fn find_h264_nal_units(&self, fp_bs: &mut File) -> (bool, io::Result<Bitstream>) {
         const START_CODE_SIZE: i32 = 3;
         const MAX_NAL_UNITS_PER_BS: usize = 600;
 
@@ -403,6 +561,118 @@ impl Bin {
         }
     }
 
+    fn is_sei(&self, nal: &[u8]) -> bool {
+        if self.h265 {
+            let nal_unit_type = h265::nal_unit_type(nal);
+            nal_unit_type == h265::NAL_PREFIX_SEI || nal_unit_type == h265::NAL_SUFFIX_SEI
+        } else {
+            h264::nal_unit_type(nal) == h264::NAL_SEI
+        }
+    }
+
+    // the SEI messages of the access unit, described with the SPS its first
+    // slice activates
+    fn dump_sei_messages(&self, frame_no: i32, bs: &Bitstream, dump: &mut SeiDump) {
+        let mut lines = Vec::new();
+        let mut sps_id = None;
+        for i in 0..bs.nal_size {
+            let nal = bs.nal_unit(i);
+            let result = if self.h265 {
+                dump.h265.update(nal).and_then(|_| {
+                    let nal_unit_type = h265::nal_unit_type(nal);
+                    if sps_id.is_none() && nal_unit_type <= 23 && h265::nuh_layer_id(nal) == 0 {
+                        let slice = dump.h265.slice_header(nal)?;
+                        let (pps, _) = dump.h265
+                            .parameter_sets_of(slice.slice_pic_parameter_set_id)?;
+                        sps_id = Some(pps.pps_seq_parameter_set_id);
+                    }
+                    Ok(())
+                })
+            } else {
+                dump.h264.update(nal).and_then(|_| {
+                    let nal_unit_type = h264::nal_unit_type(nal);
+                    if sps_id.is_none() &&
+                       (nal_unit_type == h264::NAL_SLICE || nal_unit_type == h264::NAL_SLICE_IDR) {
+                        let slice = dump.h264.slice_header(nal)?;
+                        sps_id = Some(dump.h264.sps_of(&slice)?.seq_parameter_set_id);
+                    }
+                    Ok(())
+                })
+            };
+            if let Err(e) = result {
+                lines.push(format!("  NAL unit {}: {}", i, e));
+            }
+        }
+
+        for i in 0..bs.nal_size {
+            let nal = bs.nal_unit(i);
+            if !self.is_sei(nal) {
+                continue;
+            }
+            let header_size = if self.h265 { 2 } else { 1 };
+            let messages = match sei::sei_messages(&unescape(&nal[header_size..])) {
+                Ok(messages) => messages,
+                Err(e) => {
+                    lines.push(format!("  NAL unit {}: {}", i, e));
+                    continue;
+                }
+            };
+            for message in messages {
+                let description = if self.h265 {
+                    let sps = sps_id.and_then(|id| dump.h265.sps.get(&id));
+                    h265::describe_sei(&message, &dump.h265, sps)
+                } else {
+                    let sps = sps_id.and_then(|id| dump.h264.sps.get(&id));
+                    h264::describe_sei(&message, &dump.h264, sps)
+                };
+                lines.push(format!("  SEI {} ({}) {} bytes{}",
+                                   sei::payload_type_name(message.payload_type),
+                                   message.payload_type,
+                                   message.payload.len(),
+                                   match description {
+                                       Ok(ref d) if d.is_empty() => String::new(),
+                                       Ok(d) => format!(": {}", d),
+                                       Err(e) => format!(": {}", e),
+                                   }));
+            }
+        }
+        if !lines.is_empty() {
+            println!("\nFrame {:04}:", frame_no);
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+    }
+
+    // the NAL unit without the SEI messages of the payload types, None when
+    // nothing is left of it
+    fn strip_sei_messages(&self,
+                          nal: &[u8],
+                          payload_types: &[u32])
+                          -> Result<Option<Vec<u8>>, &'static str> {
+        if !self.is_sei(nal) {
+            return Ok(Some(nal.to_vec()));
+        }
+        if payload_types.is_empty() {
+            return Ok(None);
+        }
+        let header_size = if self.h265 { 2 } else { 1 };
+        let messages = sei::sei_messages(&unescape(&nal[header_size..]))?;
+        let kept: Vec<sei::SeiMessage> = messages.iter()
+            .filter(|m| !payload_types.contains(&m.payload_type))
+            .cloned()
+            .collect();
+        if kept.is_empty() {
+            Ok(None)
+        } else if kept.len() == messages.len() {
+            Ok(Some(nal.to_vec()))
+        } else {
+            let mut rewritten = nal[..header_size].to_vec();
+            rewritten.extend(escape(&sei::sei_rbsp(&kept)));
+            Ok(Some(rewritten))
+        }
+    }
+
     fn write_to_file(&self,
                      pre_frame_no: &mut i32,
                      cur_frame_no: i32,
@@ -0,0 +1,135 @@
+// MSB first bit reader for the codec headers, with the exp-Golomb codes of
+// H.264/H.265 and the variable length codes of AV1.
+
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader { data, pos: 0 }
+    }
+
+    // bits read so far
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn remaining(&self) -> usize {
+        (self.data.len() * 8).saturating_sub(self.pos)
+    }
+
+    pub fn bit(&mut self) -> Result<bool, &'static str> {
+        if self.pos >= self.data.len() * 8 {
+            return Err("header ends early");
+        }
+        let bit = (self.data[self.pos / 8] >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Ok(bit == 1)
+    }
+
+    // up to 32 bits as an unsigned number
+    pub fn bits(&mut self, n: u32) -> Result<u32, &'static str> {
+        let mut value = 0u64;
+        for _ in 0..n {
+            value = (value << 1) | self.bit()? as u64;
+        }
+        Ok(value as u32)
+    }
+
+    pub fn skip(&mut self, n: usize) -> Result<(), &'static str> {
+        if n > self.remaining() {
+            return Err("header ends early");
+        }
+        self.pos += n;
+        Ok(())
+    }
+
+    // more_rbsp_data(), whether anything but the stop bit and zero bytes is left
+    pub fn more_rbsp_data(&self) -> bool {
+        match self.data.iter().rposition(|&b| b != 0) {
+            Some(last) => {
+                let stop_bit = last * 8 + 7 - self.data[last].trailing_zeros() as usize;
+                self.pos < stop_bit
+            }
+            None => false,
+        }
+    }
+
+    pub fn byte_align(&mut self) {
+        self.pos = self.pos.div_ceil(8) * 8;
+    }
+
+    // unsigned exp-Golomb, ue(v)
+    pub fn ue(&mut self) -> Result<u32, &'static str> {
+        let mut zeros = 0;
+        while !self.bit()? {
+            zeros += 1;
+            if zeros > 31 {
+                return Err("invalid exp-golomb code");
+            }
+        }
+        Ok(((1u64 << zeros) - 1 + self.bits(zeros)? as u64) as u32)
+    }
+
+    // signed exp-Golomb, se(v)
+    pub fn se(&mut self) -> Result<i32, &'static str> {
+        let k = self.ue()? as i64;
+        Ok(if k % 2 == 1 { (k + 1) / 2 } else { -k / 2 } as i32)
+    }
+
+    // AV1 uvlc()
+    pub fn uvlc(&mut self) -> Result<u32, &'static str> {
+        let mut zeros = 0;
+        while !self.bit()? {
+            zeros += 1;
+        }
+        if zeros >= 32 {
+            return Ok(u32::MAX);
+        }
+        Ok(((1u64 << zeros) - 1 + self.bits(zeros)? as u64) as u32)
+    }
+}
+
+// RBSP of a NAL unit, with the emulation prevention bytes (00 00 03) removed
+pub fn unescape(nal: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for &b in nal {
+        if zeros >= 2 && b == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        rbsp.push(b);
+    }
+    rbsp
+}
+
+// NAL unit payload of an RBSP, with emulation prevention bytes inserted
+pub fn escape(rbsp: &[u8]) -> Vec<u8> {
+    let mut nal = Vec::with_capacity(rbsp.len() + rbsp.len() / 64);
+    let mut zeros = 0;
+    for &b in rbsp {
+        if zeros >= 2 && b <= 3 {
+            nal.push(3);
+            zeros = 0;
+        }
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        nal.push(b);
+    }
+    nal
+}
+
+// AV1 leb128(), (value, bytes)
+pub fn leb128(data: &[u8]) -> Result<(u64, usize), &'static str> {
+    let mut value = 0u64;
+    for (i, &b) in data.iter().take(8).enumerate() {
+        value |= ((b & 0x7f) as u64) << (i * 7);
+        if b & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+    Err("invalid leb128 size")
+}
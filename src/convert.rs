@@ -0,0 +1,371 @@
+// Resizing and pixel format conversion of raw YUV, e.g. to bring a reference
+// to the decoder output resolution before comparing the two in psnr mode.
+
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use super::frame;
+use super::frame::{ChromaFormat, FrameReader};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Filter {
+    Bilinear,
+    // catmull-rom, a = -0.5
+    Bicubic,
+    // lanczos3
+    Lanczos,
+}
+
+impl Filter {
+    pub fn parse(name: &str) -> Result<Filter, &'static str> {
+        match name.to_lowercase().as_ref() {
+            "bilinear" => Ok(Filter::Bilinear),
+            "bicubic" => Ok(Filter::Bicubic),
+            "lanczos" => Ok(Filter::Lanczos),
+            _ => Err("only support bilinear, bicubic and lanczos filters"),
+        }
+    }
+
+    // kernel radius in input samples when upscaling
+    fn support(&self) -> f64 {
+        match *self {
+            Filter::Bilinear => 1.0,
+            Filter::Bicubic => 2.0,
+            Filter::Lanczos => 3.0,
+        }
+    }
+
+    fn kernel(&self, x: f64) -> f64 {
+        let x = x.abs();
+        match *self {
+            Filter::Bilinear => (1.0 - x).max(0.0),
+            Filter::Bicubic => {
+                const A: f64 = -0.5;
+                if x < 1.0 {
+                    ((A + 2.0) * x - (A + 3.0)) * x * x + 1.0
+                } else if x < 2.0 {
+                    ((A * x - 5.0 * A) * x + 8.0 * A) * x - 4.0 * A
+                } else {
+                    0.0
+                }
+            }
+            Filter::Lanczos => {
+                if x == 0.0 {
+                    1.0
+                } else if x < 3.0 {
+                    3.0 * (PI * x).sin() * (PI * x / 3.0).sin() / (PI * PI * x * x)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Layout {
+    // Y, U and V planes one after the other (I420, I422, I444)
+    Planar,
+    // Y plane followed by interleaved UV samples (NV12, NV16, NV24)
+    SemiPlanar,
+}
+
+impl Layout {
+    pub fn parse(name: &str) -> Result<Layout, &'static str> {
+        match name.to_lowercase().as_ref() {
+            "planar" => Ok(Layout::Planar),
+            "semi-planar" | "nv12" => Ok(Layout::SemiPlanar),
+            _ => Err("only support planar and semi-planar layouts"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PixelFormat {
+    pub chroma_format: ChromaFormat,
+    // samples above 8 bits take two bytes, little endian
+    pub bit_depth: u32,
+    pub layout: Layout,
+}
+
+impl PixelFormat {
+    fn bytes_per_sample(&self) -> usize {
+        if self.bit_depth > 8 { 2 } else { 1 }
+    }
+
+    pub fn frame_bytes(&self, width: usize, height: usize) -> usize {
+        self.chroma_format.frame_bytes(width, height) * self.bytes_per_sample()
+    }
+
+    // the Y, U and V planes of a frame in this format
+    fn unpack(&self, buf: &[u8], width: usize, height: usize) -> Vec<Plane> {
+        let sample = |i: usize| -> f64 {
+            if self.bytes_per_sample() == 1 {
+                buf[i] as f64
+            } else {
+                u16::from_le_bytes([buf[2 * i], buf[2 * i + 1]]) as f64
+            }
+        };
+        let planes = self.chroma_format.planes(width, height);
+        let (chroma_offset, _, _) = planes[1];
+        planes.iter()
+            .enumerate()
+            .map(|(p, &(offset, width, height))| {
+                let data = (0..width * height)
+                    .map(|k| match self.layout {
+                        Layout::SemiPlanar if p > 0 => sample(chroma_offset + 2 * k + p - 1),
+                        _ => sample(offset + k),
+                    })
+                    .collect();
+                Plane {
+                    width,
+                    height,
+                    data,
+                }
+            })
+            .collect()
+    }
+
+    // planes sampled at depth bits, rounded and clipped to this format
+    fn pack(&self, planes: &[Plane], depth: u32, out: &mut Vec<u8>) {
+        let scale = 2f64.powi(self.bit_depth as i32 - depth as i32);
+        let max = ((1u32 << self.bit_depth) - 1) as f64;
+        let mut samples: Vec<u16> = Vec::with_capacity(planes.iter().map(|p| p.data.len()).sum());
+        let quantize = |v: f64| (v * scale).round().clamp(0.0, max) as u16;
+        samples.extend(planes[0].data.iter().map(|&v| quantize(v)));
+        match self.layout {
+            Layout::Planar => {
+                samples.extend(planes[1].data.iter().map(|&v| quantize(v)));
+                samples.extend(planes[2].data.iter().map(|&v| quantize(v)));
+            }
+            Layout::SemiPlanar => {
+                for (&u, &v) in planes[1].data.iter().zip(planes[2].data.iter()) {
+                    samples.push(quantize(u));
+                    samples.push(quantize(v));
+                }
+            }
+        }
+
+        out.clear();
+        if self.bytes_per_sample() == 1 {
+            out.extend(samples.iter().map(|&s| s as u8));
+        } else {
+            for s in samples {
+                out.extend_from_slice(&s.to_le_bytes());
+            }
+        }
+    }
+}
+
+struct Plane {
+    width: usize,
+    height: usize,
+    data: Vec<f64>,
+}
+
+impl Plane {
+    // separable resampling, horizontally then vertically
+    fn resize(&self, width: usize, height: usize, filter: Filter) -> Plane {
+        if (width, height) == (self.width, self.height) {
+            return Plane {
+                width,
+                height,
+                data: self.data.clone(),
+            };
+        }
+
+        let taps = resample_taps(filter, self.width, width);
+        let mut rows = vec![0.0f64; width * self.height];
+        for y in 0..self.height {
+            let src = &self.data[y * self.width..(y + 1) * self.width];
+            for (x, &(first, ref weights)) in taps.iter().enumerate() {
+                rows[y * width + x] =
+                    weights.iter().enumerate().map(|(k, w)| w * src[first + k]).sum();
+            }
+        }
+
+        let taps = resample_taps(filter, self.height, height);
+        let mut data = vec![0.0f64; width * height];
+        for (y, &(first, ref weights)) in taps.iter().enumerate() {
+            for (k, w) in weights.iter().enumerate() {
+                let src = &rows[(first + k) * width..(first + k + 1) * width];
+                for (d, s) in data[y * width..(y + 1) * width].iter_mut().zip(src.iter()) {
+                    *d += w * s;
+                }
+            }
+        }
+
+        Plane {
+            width,
+            height,
+            data,
+        }
+    }
+}
+
+// (first input sample, normalized weights) for every output sample, with the
+// kernel stretched when downscaling so it also acts as the low-pass filter
+fn resample_taps(filter: Filter, input: usize, output: usize) -> Vec<(usize, Vec<f64>)> {
+    let scale = input as f64 / output as f64;
+    let stretch = scale.max(1.0);
+    let support = filter.support() * stretch;
+    (0..output)
+        .map(|o| {
+            let center = (o as f64 + 0.5) * scale;
+            let first = ((center - support).floor().max(0.0)) as usize;
+            let last = ((center + support).ceil() as usize).min(input);
+            let mut weights: Vec<f64> = (first..last)
+                .map(|i| filter.kernel((i as f64 + 0.5 - center) / stretch))
+                .collect();
+            let sum: f64 = weights.iter().sum();
+            if sum != 0.0 {
+                for w in weights.iter_mut() {
+                    *w /= sum;
+                }
+            }
+            (first, weights)
+        })
+        .collect()
+}
+
+pub struct Convert {
+    pub input_yuv: String,
+    pub output_yuv: String,
+    pub frame_num: usize,
+    pub frame_size: Vec<(i32, i32)>,
+    // None keeps the size of every input frame
+    pub output_size: Option<(usize, usize)>,
+    pub filter: Filter,
+    pub input_format: PixelFormat,
+    pub output_format: PixelFormat,
+}
+
+impl Convert {
+    pub fn helper() {
+        println!("Usage: rsplit convert input.yuv|- output.yuv frame_num frame_size1 \
+                  [...|frame_size2 ...]|@sizes.txt [--size=WxH] \
+                  [--filter=bilinear|bicubic|lanczos] [--chroma=420|422|444] [--bit-depth=8] \
+                  [--layout=planar|semi-planar] \
+                  [--out-chroma=420|422|444] [--out-bit-depth=8] \
+                  [--out-layout=planar|semi-planar]")
+    }
+
+    pub fn new(args: &[String]) -> Result<Convert, &'static str> {
+        let mut output_size = None;
+        let mut filter = Filter::Bicubic;
+        let mut chroma_format = ChromaFormat::Yuv420;
+        let mut bit_depth = 8;
+        let mut layout = Layout::Planar;
+        let mut out_chroma_format = None;
+        let mut out_bit_depth = None;
+        let mut out_layout = None;
+        let mut positional: Vec<String> = Vec::new();
+        for arg in args {
+            if let Some(size) = arg.strip_prefix("--size=") {
+                output_size = match frame::parse_frame_sizes(&[size.to_string()], 0, 1)?[0] {
+                    (width, height) if width > 0 && height > 0 => {
+                        Some((width as usize, height as usize))
+                    }
+                    _ => {
+                        return Err("output size must be positive");
+                    }
+                };
+            } else if let Some(name) = arg.strip_prefix("--filter=") {
+                filter = Filter::parse(name)?;
+            } else if let Some(chroma) = arg.strip_prefix("--chroma=") {
+                chroma_format = ChromaFormat::parse(chroma)?;
+            } else if let Some(n) = arg.strip_prefix("--bit-depth=") {
+                bit_depth = parse_bit_depth(n)?;
+            } else if let Some(name) = arg.strip_prefix("--layout=") {
+                layout = Layout::parse(name)?;
+            } else if let Some(chroma) = arg.strip_prefix("--out-chroma=") {
+                out_chroma_format = Some(ChromaFormat::parse(chroma)?);
+            } else if let Some(n) = arg.strip_prefix("--out-bit-depth=") {
+                out_bit_depth = Some(parse_bit_depth(n)?);
+            } else if let Some(name) = arg.strip_prefix("--out-layout=") {
+                out_layout = Some(Layout::parse(name)?);
+            } else if arg.starts_with("--") {
+                return Err("unknown option for rsplit convert mode");
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        let args = &positional[..];
+
+        if args.len() < 6 {
+            return Err("too less arguments for rsplit convert mode");
+        }
+
+        let input_yuv = args[2].clone();
+        let output_yuv = args[3].clone();
+        let frame_num = match args[4].parse::<usize>() {
+            Ok(frame_num) => frame_num,
+            Err(_) => {
+                return Err("can't parse frame_num as usize");
+            }
+        };
+        let frame_size = frame::parse_frame_sizes(args, 5, frame_num)?;
+
+        let input_format = PixelFormat {
+            chroma_format,
+            bit_depth,
+            layout,
+        };
+        Ok(Convert {
+            input_yuv,
+            output_yuv,
+            frame_num,
+            frame_size,
+            output_size,
+            filter,
+            input_format,
+            output_format: PixelFormat {
+                chroma_format: out_chroma_format.unwrap_or(chroma_format),
+                bit_depth: out_bit_depth.unwrap_or(bit_depth),
+                layout: out_layout.unwrap_or(layout),
+            },
+        })
+    }
+
+    pub fn run(&self) -> io::Result<()> {
+        println!("convert {} into {}", self.input_yuv, self.output_yuv);
+        let mut fi = FrameReader::open(&self.input_yuv)?;
+        let mut fo = BufWriter::new(File::create(&self.output_yuv)?);
+        let mut input_buf = Vec::new();
+        let mut output_buf = Vec::new();
+
+        for i in 0..self.frame_num {
+            let (width, height) = (self.frame_size[i].0 as usize, self.frame_size[i].1 as usize);
+            if !fi.read_frame(&mut input_buf, self.input_format.frame_bytes(width, height))? {
+                return Err(fi.end_of_input());
+            }
+
+            let (output_width, output_height) = self.output_size.unwrap_or((width, height));
+            let planes = self.input_format.unpack(&input_buf, width, height);
+            let sizes = self.output_format.chroma_format.planes(output_width, output_height);
+            let resized: Vec<Plane> = planes.iter()
+                .zip(sizes.iter())
+                .map(|(plane, &(_, w, h))| plane.resize(w, h, self.filter))
+                .collect();
+            self.output_format.pack(&resized, self.input_format.bit_depth, &mut output_buf);
+
+            println!("Frame {:04}: {}x{} => {}x{}",
+                     i,
+                     width,
+                     height,
+                     output_width,
+                     output_height);
+            fo.write_all(&output_buf)?;
+        }
+
+        fo.flush()
+    }
+}
+
+fn parse_bit_depth(n: &str) -> Result<u32, &'static str> {
+    match n.parse::<u32>() {
+        Ok(depth) if (8..=16).contains(&depth) => Ok(depth),
+        _ => Err("bit depth must be between 8 and 16"),
+    }
+}
@@ -0,0 +1,169 @@
+// Cut lists of the splitting modes: the segments to write when they aren't
+// "from a key frame, at least frame_num frames long".
+
+#[derive(Clone, Debug)]
+pub enum Cut {
+    // frames first..=last, to the end of the stream without last
+    Range(usize, Option<usize>),
+    // a new segment at each time, in seconds
+    Times(Vec<f64>),
+    // this many segments of about equal duration, or equal size in bytes
+    Segments(usize, bool),
+}
+
+// what the cut list needs to know of a frame
+pub struct FrameInfo {
+    pub key: bool,
+    pub size: usize,
+    // presentation time in seconds
+    pub time: f64,
+}
+
+impl Cut {
+    pub fn usage() -> &'static str {
+        "[--range=A-B|--cut-at=t1,t2,...|--segments=K[:size]]"
+    }
+
+    // the cut list of a --range, --cut-at or --segments option, None for any
+    // other argument
+    pub fn parse(arg: &str) -> Option<Result<Cut, &'static str>> {
+        if let Some(range) = arg.strip_prefix("--range=") {
+            Some(parse_range(range))
+        } else if let Some(times) = arg.strip_prefix("--cut-at=") {
+            Some(times.split(',').map(parse_time).collect::<Result<Vec<f64>, _>>().map(Cut::Times))
+        } else if let Some(segments) = arg.strip_prefix("--segments=") {
+            let (count, by_size) = match segments.split_once(':') {
+                Some((count, "size")) => (count, true),
+                Some((count, "duration")) => (count, false),
+                Some(_) => return Some(Err("segments are balanced by size or duration")),
+                None => (segments, false),
+            };
+            Some(match count.parse::<usize>() {
+                Ok(count) if count > 0 => Ok(Cut::Segments(count, by_size)),
+                _ => Err("can't parse the segment count as a positive integer"),
+            })
+        } else {
+            None
+        }
+    }
+
+    // the first and one past the last frame of every segment, each starting
+    // at a key frame
+    pub fn plan(&self, frames: &[FrameInfo]) -> Result<Vec<(usize, usize)>, &'static str> {
+        if frames.is_empty() {
+            return Err("no frames to cut");
+        }
+        // the random access point a cut at frame i snaps back to
+        let key_before = |i: usize| (0..i + 1).rev().find(|&k| frames[k].key).unwrap_or(0);
+        match *self {
+            Cut::Range(first, last) => {
+                if first >= frames.len() {
+                    return Err("range starts after the last frame");
+                }
+                let end = last.map_or(frames.len(), |last| (last + 1).min(frames.len()));
+                Ok(vec![(key_before(first), end)])
+            }
+            Cut::Times(ref times) => {
+                let mut starts = vec![0];
+                for &time in times {
+                    if let Some(i) = frames.iter().rposition(|f| f.time <= time) {
+                        starts.push(key_before(i));
+                    }
+                }
+                starts.sort();
+                starts.dedup();
+                Ok(segments(&starts, frames.len()))
+            }
+            Cut::Segments(count, by_size) => {
+                let keys: Vec<usize> = (1..frames.len()).filter(|&i| frames[i].key).collect();
+                if keys.len() + 1 < count {
+                    return Err("fewer key frames than segments");
+                }
+                // where each frame starts, in bytes or seconds
+                let mut position = Vec::with_capacity(frames.len() + 1);
+                let mut bytes = 0;
+                for frame in frames {
+                    position.push(if by_size { bytes as f64 } else { frame.time - frames[0].time });
+                    bytes += frame.size;
+                }
+                let total = if by_size {
+                    bytes as f64
+                } else {
+                    // one more frame duration past the start of the last frame
+                    let last = position[frames.len() - 1];
+                    last + last / (frames.len() - 1).max(1) as f64
+                };
+
+                let mut starts = vec![0];
+                let mut next = 0;
+                for j in 1..count {
+                    let target = total * j as f64 / count as f64;
+                    // leaving a key frame for each of the later segments
+                    let candidates = next..keys.len() - (count - 1 - j);
+                    let k = candidates.min_by(|&a, &b| {
+                            let da = (position[keys[a]] - target).abs();
+                            let db = (position[keys[b]] - target).abs();
+                            da.partial_cmp(&db).unwrap()
+                        })
+                        .unwrap();
+                    starts.push(keys[k]);
+                    next = k + 1;
+                }
+                Ok(segments(&starts, frames.len()))
+            }
+        }
+    }
+}
+
+fn segments(starts: &[usize], frame_count: usize) -> Vec<(usize, usize)> {
+    let mut ends = starts[1..].to_vec();
+    ends.push(frame_count);
+    starts.iter().cloned().zip(ends).collect()
+}
+
+fn parse_range(range: &str) -> Result<Cut, &'static str> {
+    let frame = |s: &str| s.parse::<usize>().map_err(|_| "can't parse the range as frames A-B");
+    match range.split_once('-') {
+        Some((first, "")) => Ok(Cut::Range(frame(first)?, None)),
+        Some((first, last)) => {
+            let (first, last) = (frame(first)?, frame(last)?);
+            if last < first {
+                return Err("range ends before it starts");
+            }
+            Ok(Cut::Range(first, Some(last)))
+        }
+        None => {
+            let first = frame(range)?;
+            Ok(Cut::Range(first, Some(first)))
+        }
+    }
+}
+
+// a byte count with an optional k or m (1024-based) suffix
+pub fn parse_bytes(size: &str) -> Result<usize, &'static str> {
+    let lower = size.to_lowercase();
+    let (number, unit) = if let Some(number) = lower.strip_suffix('k') {
+        (number, 1 << 10)
+    } else if let Some(number) = lower.strip_suffix('m') {
+        (number, 1 << 20)
+    } else {
+        (&lower[..], 1)
+    };
+    match number.parse::<usize>() {
+        Ok(number) if number > 0 => number.checked_mul(unit).ok_or("byte size is too large"),
+        _ => Err("can't parse the byte size as a positive integer"),
+    }
+}
+
+// seconds, or [hh:]mm:ss with a fraction
+fn parse_time(time: &str) -> Result<f64, &'static str> {
+    let mut seconds = 0.0;
+    for part in time.split(':') {
+        let value = part.parse::<f64>().map_err(|_| "can't parse the cut time")?;
+        if value < 0.0 {
+            return Err("cut times can't be negative");
+        }
+        seconds = seconds * 60.0 + value;
+    }
+    Ok(seconds)
+}
@@ -0,0 +1,189 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use super::frame;
+use super::frame::{ChromaFormat, FrameReader};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DiffMode {
+    // amplified absolute luma difference on neutral chroma
+    Difference,
+    // input1 on the left and input2 on the right, twice as wide
+    SideBySide,
+    // left part from input1 and right part from input2, split at a luma column
+    // or in the middle of the frame
+    Wipe(Option<usize>),
+}
+
+pub struct Diff {
+    pub input1_yuv: String,
+    pub input2_yuv: String,
+    pub output_yuv: String,
+    pub frame_num: usize,
+    pub frame_size: Vec<(i32, i32)>,
+    pub mode: DiffMode,
+    pub gain: u32,
+    pub chroma_format: ChromaFormat,
+}
+
+impl Diff {
+    pub fn helper() {
+        println!("Usage: rsplit diff input1.yuv|- input2.yuv|- output.yuv frame_num frame_size1 \
+                  [...|frame_size2 ...]|@sizes.txt [--mode=diff|sbs|wipe[:X]] [--gain=4] \
+                  [--chroma=420|422|444]")
+    }
+
+    pub fn new(args: &[String]) -> Result<Diff, &'static str> {
+        let mut mode = DiffMode::Difference;
+        let mut gain = 4;
+        let mut chroma_format = ChromaFormat::Yuv420;
+        let mut positional: Vec<String> = Vec::new();
+        for arg in args {
+            if let Some(name) = arg.strip_prefix("--mode=") {
+                mode = match name.to_lowercase().as_ref() {
+                    "diff" => DiffMode::Difference,
+                    "sbs" | "side-by-side" => DiffMode::SideBySide,
+                    "wipe" => DiffMode::Wipe(None),
+                    wipe if wipe.starts_with("wipe:") => {
+                        DiffMode::Wipe(Some(wipe["wipe:".len()..]
+                            .parse::<usize>()
+                            .map_err(|_| "can't parse wipe position as usize")?))
+                    }
+                    _ => {
+                        return Err("only support diff, sbs and wipe modes");
+                    }
+                };
+            } else if let Some(n) = arg.strip_prefix("--gain=") {
+                gain = n.parse::<u32>().map_err(|_| "can't parse gain as u32")?;
+            } else if let Some(chroma) = arg.strip_prefix("--chroma=") {
+                chroma_format = ChromaFormat::parse(chroma)?;
+            } else if arg.starts_with("--") {
+                return Err("unknown option for rsplit diff mode");
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        let args = &positional[..];
+
+        if args.len() < 7 {
+            return Err("too less arguments for rsplit diff mode");
+        }
+
+        let input1_yuv = args[2].clone();
+        let input2_yuv = args[3].clone();
+        let output_yuv = args[4].clone();
+        if input1_yuv == "-" && input2_yuv == "-" {
+            return Err("only one input can be read from stdin");
+        }
+        let frame_num = match args[5].parse::<usize>() {
+            Ok(frame_num) => frame_num,
+            Err(_) => {
+                return Err("can't parse frame_num as usize");
+            }
+        };
+        let frame_size = frame::parse_frame_sizes(args, 6, frame_num)?;
+
+        Ok(Diff {
+            input1_yuv,
+            input2_yuv,
+            output_yuv,
+            frame_num,
+            frame_size,
+            mode,
+            gain,
+            chroma_format,
+        })
+    }
+
+    pub fn run(&self) -> io::Result<()> {
+        println!("diff {} vs {} into {}",
+                 self.input1_yuv,
+                 self.input2_yuv,
+                 self.output_yuv);
+        let mut f1 = FrameReader::open(&self.input1_yuv)?;
+        let mut f2 = FrameReader::open(&self.input2_yuv)?;
+        let mut fo = BufWriter::new(File::create(&self.output_yuv)?);
+        let mut input1_buf = Vec::new();
+        let mut input2_buf = Vec::new();
+
+        for i in 0..self.frame_num {
+            let (width, height) = (self.frame_size[i].0 as usize, self.frame_size[i].1 as usize);
+            let buf_size = self.chroma_format.frame_bytes(width, height);
+            if !f1.read_frame(&mut input1_buf, buf_size)? {
+                return Err(f1.end_of_input());
+            }
+            if !f2.read_frame(&mut input2_buf, buf_size)? {
+                return Err(f2.end_of_input());
+            }
+
+            let planes = self.chroma_format.planes(width, height);
+            let output_buf = match self.mode {
+                DiffMode::Difference => self.difference(&input1_buf, &input2_buf, &planes),
+                DiffMode::SideBySide => side_by_side(&input1_buf, &input2_buf, &planes),
+                DiffMode::Wipe(x) => {
+                    let (sub_x, _) = self.chroma_format.subsampling();
+                    // keep the split on a chroma sample so all planes switch at the same place
+                    let x = x.unwrap_or(width / 2).min(width);
+                    wipe(&input1_buf, &input2_buf, &planes, x / sub_x * sub_x, sub_x)
+                }
+            };
+
+            let output_width = match self.mode {
+                DiffMode::SideBySide => width * 2,
+                _ => width,
+            };
+            println!("Frame {:04}: {}x{} => {}x{}", i, width, height, output_width, height);
+            fo.write_all(&output_buf)?;
+        }
+
+        fo.flush()
+    }
+
+    fn difference(&self,
+                  input1_buf: &[u8],
+                  input2_buf: &[u8],
+                  planes: &[(usize, usize, usize); 3])
+                  -> Vec<u8> {
+        let (offset, width, height) = planes[0];
+        let mut out: Vec<u8> = input1_buf[offset..offset + width * height]
+            .iter()
+            .zip(input2_buf[offset..].iter())
+            .map(|(a, b)| ((*a as i32 - *b as i32).unsigned_abs() * self.gain).min(255) as u8)
+            .collect();
+        out.resize(input1_buf.len(), 128);
+        out
+    }
+}
+
+fn side_by_side(input1_buf: &[u8],
+                input2_buf: &[u8],
+                planes: &[(usize, usize, usize); 3])
+                -> Vec<u8> {
+    let mut out = Vec::with_capacity(input1_buf.len() * 2);
+    for &(offset, width, height) in planes.iter() {
+        for y in 0..height {
+            let start = offset + y * width;
+            out.extend_from_slice(&input1_buf[start..start + width]);
+            out.extend_from_slice(&input2_buf[start..start + width]);
+        }
+    }
+    out
+}
+
+fn wipe(input1_buf: &[u8],
+        input2_buf: &[u8],
+        planes: &[(usize, usize, usize); 3],
+        x: usize,
+        sub_x: usize)
+        -> Vec<u8> {
+    let mut out = Vec::with_capacity(input1_buf.len());
+    for (p, &(offset, width, height)) in planes.iter().enumerate() {
+        let split = if p == 0 { x } else { x / sub_x };
+        for y in 0..height {
+            let start = offset + y * width;
+            out.extend_from_slice(&input1_buf[start..start + split]);
+            out.extend_from_slice(&input2_buf[start + split..start + width]);
+        }
+    }
+    out
+}
@@ -0,0 +1,266 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Error, Read, Seek, Write};
+use std::slice;
+use super::av1;
+use super::bin::Bin;
+use super::ivf::{is_key_frame, Ivf};
+use super::report::ReportFormat;
+use super::sizes::Codec;
+use super::webm::{vpx_destroy, vpx_open, vpx_read, vpx_tstamp};
+
+pub struct Explode {
+    pub input: String,
+    pub prefix: String,
+    pub codec: Codec,
+    pub manifest: ReportFormat,
+    // frame rate for the times of Annex-B access units
+    pub fps: f64,
+}
+
+// a line of the manifest
+struct Entry {
+    file: String,
+    // where the access unit starts in the input, unknown for WebM
+    offset: Option<u64>,
+    size: usize,
+    // an IDR access unit for H.264 and H.265, where bin mode cuts
+    key: bool,
+    pts: u64,
+    time: f64,
+}
+
+impl Explode {
+    pub fn helper() {
+        println!("Usage: rsplit explode input.bin|ivf|webm prefix vp8|vp9|av1|h264|h265 \
+                  [--manifest=csv|json] [--fps=30]")
+    }
+
+    pub fn new(args: &[String]) -> Result<Explode, &'static str> {
+        let mut manifest = ReportFormat::Csv;
+        let mut fps = 30.0;
+        let mut positional: Vec<String> = Vec::new();
+        for arg in args {
+            if let Some(format) = arg.strip_prefix("--manifest=") {
+                manifest = ReportFormat::parse(format)?;
+            } else if let Some(n) = arg.strip_prefix("--fps=") {
+                fps = n.parse::<f64>().map_err(|_| "can't parse fps as f64")?;
+                if fps <= 0.0 {
+                    return Err("fps must be positive");
+                }
+            } else if arg.starts_with("--") {
+                return Err("unknown option for rsplit explode mode");
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        let args = &positional[..];
+
+        if args.len() < 5 {
+            return Err("too less arguments for rsplit explode mode");
+        }
+
+        let codec = Codec::parse(&args[4])?;
+
+        Ok(Explode {
+            input: args[2].clone(),
+            prefix: args[3].clone(),
+            codec,
+            manifest,
+            fps,
+        })
+    }
+
+    pub fn run(&self) -> io::Result<()> {
+        println!("rsplit explode {} into {}_NNNNN.bin", self.input, self.prefix);
+        let entries = match self.codec {
+            Codec::H264 | Codec::H265 => self.explode_bin()?,
+            _ if self.input.to_lowercase().ends_with(".webm") => self.explode_webm()?,
+            _ => self.explode_ivf()?,
+        };
+
+        let manifest = match self.manifest {
+            ReportFormat::Csv => format!("{}.csv", self.prefix),
+            ReportFormat::Json => format!("{}.json", self.prefix),
+        };
+        let mut out = BufWriter::new(File::create(&manifest)?);
+        self.write_manifest(&mut out, &entries)?;
+        out.flush()?;
+
+        let keys = entries.iter().filter(|e| e.key).count();
+        println!("{} access units, {} key frames, manifest {}",
+                 entries.len(),
+                 keys,
+                 manifest);
+        Ok(())
+    }
+
+    // writes access unit i to its own file and describes it
+    fn write_unit(&self, i: usize, data: &[u8]) -> io::Result<String> {
+        let file = format!("{}_{:05}.bin", self.prefix, i);
+        File::create(&file)?.write_all(data)?;
+        Ok(file)
+    }
+
+    fn explode_bin(&self) -> io::Result<Vec<Entry>> {
+        let h265 = self.codec == Codec::H265;
+        let bin = Bin::scanner(&self.input, h265);
+        let mut fi = File::open(&self.input)?;
+        let mut entries = Vec::new();
+        loop {
+            let offset = fi.stream_position()?;
+            let (eof, bs) = if h265 {
+                bin.find_h265_nal_units(&mut fi)
+            } else {
+                bin.find_h264_nal_units(&mut fi)
+            };
+            let bs = bs?;
+            if bs.nal_size > 0 {
+                let start = bs.frame_location[0] as usize;
+                let data = &bs.frame_data[start..bs.frame_location[bs.nal_size] as usize];
+                let i = entries.len();
+                entries.push(Entry {
+                    file: self.write_unit(i, data)?,
+                    offset: Some(offset + start as u64),
+                    size: data.len(),
+                    key: bs.idr_flag,
+                    pts: i as u64,
+                    time: i as f64 / self.fps,
+                });
+            }
+            if eof {
+                break;
+            }
+        }
+        Ok(entries)
+    }
+
+    fn explode_ivf(&self) -> io::Result<Vec<Entry>> {
+        let mut fi = File::open(&self.input)?;
+        let mut header = [0u8; 32];
+        fi.read_exact(&mut header)?;
+        if &header[0..4] != b"DKIF" {
+            return Err(Error::other("Not supported IVF format ..."));
+        }
+        //bytes 16-23  time base of the frame timestamps, rate and scale
+        let rate = u32::from_le_bytes([header[16], header[17], header[18], header[19]]);
+        let scale = u32::from_le_bytes([header[20], header[21], header[22], header[23]]);
+        let time_base = if rate == 0 { 1.0 / self.fps } else { scale as f64 / rate as f64 };
+
+        let ivf = Ivf::reader(self.codec == Codec::Vp9);
+        let mut entries = Vec::new();
+        // a missing or truncated frame ends the stream, as in ivf mode
+        let mut offset = fi.stream_position()?;
+        while let Ok(bs) = ivf.find_au_nal_units(&mut fi) {
+            //bytes 4-11   timestamp of the frame
+            let mut pts = [0u8; 8];
+            pts.copy_from_slice(&bs.frame_header[4..12]);
+            let pts = u64::from_le_bytes(pts);
+            let key = if self.codec == Codec::Av1 {
+                av1::is_key_frame(&bs.frame_data)
+            } else {
+                bs.idr_flag
+            };
+            entries.push(Entry {
+                file: self.write_unit(entries.len(), &bs.frame_data)?,
+                offset: Some(offset + bs.frame_header.len() as u64),
+                size: bs.frame_data.len(),
+                key,
+                pts,
+                time: pts as f64 * time_base,
+            });
+            offset = fi.stream_position()?;
+        }
+        Ok(entries)
+    }
+
+    fn explode_webm(&self) -> io::Result<Vec<Entry>> {
+        if self.codec == Codec::Av1 {
+            return Err(Error::other("only vp8 and vp9 webm inputs are supported"));
+        }
+        let input_ctx = vpx_open(&self.input)?;
+
+        let mut entries = Vec::new();
+        let mut result = Ok(());
+        loop {
+            let mut len = 0;
+            let ptr = unsafe { vpx_read(input_ctx, &mut len) };
+            if len == 0 {
+                break;
+            }
+            let buffer = unsafe { slice::from_raw_parts(ptr, len as usize) };
+            // in nanoseconds, the frames of a superframe share the one of
+            // their packet
+            let tstamp = unsafe { vpx_tstamp(input_ctx) };
+            match self.write_unit(entries.len(), buffer) {
+                Ok(file) => {
+                    entries.push(Entry {
+                        file,
+                        offset: None,
+                        size: buffer.len(),
+                        key: is_key_frame(self.codec == Codec::Vp9, buffer),
+                        pts: tstamp,
+                        time: tstamp as f64 / 1e9,
+                    })
+                }
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        unsafe { vpx_destroy(input_ctx) };
+        result.map(|_| entries)
+    }
+
+    fn write_manifest(&self, out: &mut dyn Write, entries: &[Entry]) -> io::Result<()> {
+        // the key column says which frames it marks
+        let key = match self.codec {
+            Codec::H264 | Codec::H265 => "idr",
+            _ => "key",
+        };
+        match self.manifest {
+            ReportFormat::Csv => {
+                writeln!(out, "frame,file,offset,size,{},pts,time", key)?;
+                for (i, e) in entries.iter().enumerate() {
+                    writeln!(out,
+                             "{},{},{},{},{},{},{:.6}",
+                             i,
+                             e.file,
+                             e.offset.map(|o| o.to_string()).unwrap_or_default(),
+                             e.size,
+                             e.key as u8,
+                             e.pts,
+                             e.time)?;
+                }
+            }
+            ReportFormat::Json => {
+                writeln!(out, "{{")?;
+                writeln!(out, "  \"input\": \"{}\",", json_escape(&self.input))?;
+                writeln!(out, "  \"frames\": [")?;
+                for (i, e) in entries.iter().enumerate() {
+                    let comma = if i + 1 < entries.len() { "," } else { "" };
+                    writeln!(out,
+                             "    {{\"frame\": {}, \"file\": \"{}\", \"offset\": {}, \
+                              \"size\": {}, \"{}\": {}, \"pts\": {}, \"time\": {:.6}}}{}",
+                             i,
+                             json_escape(&e.file),
+                             e.offset.map_or("null".to_string(), |o| o.to_string()),
+                             e.size,
+                             key,
+                             e.key,
+                             e.pts,
+                             e.time,
+                             comma)?;
+                }
+                writeln!(out, "  ]")?;
+                writeln!(out, "}}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn json_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
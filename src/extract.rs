@@ -0,0 +1,173 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Error, Write};
+use super::bin::Bin;
+use super::bits::{escape, unescape};
+use super::h265;
+use super::sei;
+
+pub struct Extract {
+    pub input: String,
+    pub output: String,
+    // the highest TemporalId and nuh_layer_id of the sub-bitstream
+    pub temporal_id: u8,
+    pub layer_id: u8,
+}
+
+// NAL units kept and dropped by TemporalId
+#[derive(Default)]
+struct Counts {
+    kept: [usize; 7],
+    dropped: [usize; 7],
+    hrd_sei: usize,
+}
+
+impl Extract {
+    pub fn helper() {
+        println!("Usage: rsplit extract input.bin output.bin [--tid=N] [--layer=N]")
+    }
+
+    pub fn new(args: &[String]) -> Result<Extract, &'static str> {
+        let mut temporal_id = 6;
+        let mut layer_id = 62;
+        let mut positional: Vec<String> = Vec::new();
+        for arg in args {
+            if let Some(value) = arg.strip_prefix("--tid=") {
+                temporal_id = match value.parse::<u8>() {
+                    Ok(tid) if tid <= 6 => tid,
+                    _ => return Err("can't parse tid as a TemporalId from 0 to 6"),
+                };
+            } else if let Some(value) = arg.strip_prefix("--layer=") {
+                layer_id = match value.parse::<u8>() {
+                    Ok(layer) if layer <= 62 => layer,
+                    _ => return Err("can't parse layer as a nuh_layer_id from 0 to 62"),
+                };
+            } else if arg.starts_with("--") {
+                return Err("unknown option for rsplit extract mode");
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        let args = &positional[..];
+
+        if args.len() < 4 {
+            return Err("too less arguments for rsplit extract mode");
+        }
+
+        Ok(Extract {
+            input: args[2].clone(),
+            output: args[3].clone(),
+            temporal_id,
+            layer_id,
+        })
+    }
+
+    pub fn run(&self) -> io::Result<()> {
+        println!("rsplit extract H265 {} into {}, TemporalId <= {}, nuh_layer_id <= {}",
+                 self.input,
+                 self.output,
+                 self.temporal_id,
+                 self.layer_id);
+        let bin = Bin::scanner(&self.input, true);
+        let mut fi = File::open(&self.input)?;
+        let mut fo = BufWriter::new(File::create(&self.output)?);
+        let mut counts = Counts::default();
+        // whether the sub-bitstream leaves out sub-layers or layers of the
+        // latest VPS
+        let mut partial = false;
+        let (mut au, mut kept_au) = (0, 0);
+        loop {
+            let (eof, bs) = bin.find_h265_nal_units(&mut fi);
+            let mut bs = bs?;
+            if bs.nal_size == 0 {
+                break;
+            }
+            let mut pictures = 0;
+            bs.rewrite_nal_units(|nal| {
+                    let kept = self.extract(nal, &mut partial, &mut counts)?;
+                    if kept.is_some() && h265::nal_unit_type(nal) <= 31 {
+                        pictures += 1;
+                    }
+                    Ok(kept)
+                })
+                .map_err(|e| Error::other(format!("access unit {}: {}", au, e)))?;
+            let start = bs.frame_location[0] as usize;
+            fo.write_all(&bs.frame_data[start..bs.frame_location[bs.nal_size] as usize])?;
+            au += 1;
+            if pictures > 0 {
+                kept_au += 1;
+            }
+            if eof {
+                break;
+            }
+        }
+        fo.flush()?;
+
+        println!("{} of {} access units kept", kept_au, au);
+        for tid in 0..7 {
+            if counts.kept[tid] + counts.dropped[tid] > 0 {
+                println!("TemporalId {}: {} NAL units kept, {} dropped",
+                         tid,
+                         counts.kept[tid],
+                         counts.dropped[tid]);
+            }
+        }
+        if counts.hrd_sei > 0 {
+            println!("{} buffering period, picture timing or decoding unit info SEI messages \
+                      dropped",
+                     counts.hrd_sei);
+        }
+        Ok(())
+    }
+
+    // the NAL unit in the sub-bitstream, None when the extraction process
+    // removes it
+    fn extract(&self,
+               nal: &[u8],
+               partial: &mut bool,
+               counts: &mut Counts)
+               -> Result<Option<Vec<u8>>, &'static str> {
+        if nal.len() < 2 {
+            return Ok(Some(nal.to_vec()));
+        }
+        let tid = h265::temporal_id(nal);
+        let layer_id = h265::nuh_layer_id(nal);
+        if tid > self.temporal_id || layer_id > self.layer_id {
+            counts.dropped[tid as usize] += 1;
+            return Ok(None);
+        }
+        counts.kept[tid as usize] += 1;
+
+        let nal_unit_type = h265::nal_unit_type(nal);
+        if nal_unit_type == h265::NAL_VPS && layer_id == 0 {
+            let vps = h265::Vps::parse(&unescape(&nal[2..]))?;
+            *partial = (self.temporal_id as u32) + 1 < vps.vps_max_sub_layers ||
+                       (self.layer_id as u32) < vps.vps_max_layer_id;
+        }
+        // the HRD messages that aren't scalable nested describe the whole
+        // bitstream, not what is left of it
+        if !*partial || nal_unit_type != h265::NAL_PREFIX_SEI || layer_id != 0 {
+            return Ok(Some(nal.to_vec()));
+        }
+        let messages = sei::sei_messages(&unescape(&nal[2..]))?;
+        let kept: Vec<sei::SeiMessage> = messages.iter()
+            .filter(|m| {
+                m.payload_type != sei::BUFFERING_PERIOD && m.payload_type != sei::PIC_TIMING &&
+                m.payload_type != sei::DECODING_UNIT_INFO
+            })
+            .cloned()
+            .collect();
+        counts.hrd_sei += messages.len() - kept.len();
+        if kept.is_empty() {
+            counts.kept[tid as usize] -= 1;
+            counts.dropped[tid as usize] += 1;
+            Ok(None)
+        } else if kept.len() == messages.len() {
+            Ok(Some(nal.to_vec()))
+        } else {
+            let mut rewritten = nal[..2].to_vec();
+            rewritten.extend(escape(&sei::sei_rbsp(&kept)));
+            Ok(Some(rewritten))
+        }
+    }
+}
@@ -0,0 +1,264 @@
+// Planar YUV frame geometry and reading shared by the yuv, psnr and diff modes.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io;
+use std::io::{Error, ErrorKind, Read};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ChromaFormat {
+    Yuv420,
+    Yuv422,
+    Yuv444,
+}
+
+impl ChromaFormat {
+    pub fn parse(name: &str) -> Result<ChromaFormat, &'static str> {
+        match name {
+            "420" => Ok(ChromaFormat::Yuv420),
+            "422" => Ok(ChromaFormat::Yuv422),
+            "444" => Ok(ChromaFormat::Yuv444),
+            _ => Err("only support 420, 422 and 444 chroma formats"),
+        }
+    }
+
+    // horizontal and vertical luma samples per chroma sample
+    pub fn subsampling(&self) -> (usize, usize) {
+        match *self {
+            ChromaFormat::Yuv420 => (2, 2),
+            ChromaFormat::Yuv422 => (2, 1),
+            ChromaFormat::Yuv444 => (1, 1),
+        }
+    }
+
+    pub fn chroma_size(&self, width: usize, height: usize) -> (usize, usize) {
+        match *self {
+            ChromaFormat::Yuv420 => (width.div_ceil(2), height.div_ceil(2)),
+            ChromaFormat::Yuv422 => (width.div_ceil(2), height),
+            ChromaFormat::Yuv444 => (width, height),
+        }
+    }
+
+    // (offset, width, height) of the Y, U and V planes
+    pub fn planes(&self, width: usize, height: usize) -> [(usize, usize, usize); 3] {
+        let (chroma_width, chroma_height) = self.chroma_size(width, height);
+        let luma_size = width * height;
+        let chroma_size = chroma_width * chroma_height;
+        [(0, width, height),
+         (luma_size, chroma_width, chroma_height),
+         (luma_size + chroma_size, chroma_width, chroma_height)]
+    }
+
+    pub fn frame_bytes(&self, width: usize, height: usize) -> usize {
+        let (chroma_width, chroma_height) = self.chroma_size(width, height);
+        width * height + chroma_width * chroma_height * 2
+    }
+}
+
+// frame_num sizes as WxH starting at args[first], where "..." repeats the
+// previous size for the remaining frames, or @file for a size schedule
+pub fn parse_frame_sizes(args: &[String],
+                         first: usize,
+                         frame_num: usize)
+                         -> Result<Vec<(i32, i32)>, &'static str> {
+    let l = args.len();
+    if let Some(path) = args.get(first).and_then(|arg| arg.strip_prefix('@')) {
+        return read_frame_schedule(path, frame_num);
+    }
+    let mut frame_size: Vec<(i32, i32)> = Vec::new();
+    for i in 0..frame_num {
+        if first + i >= l {
+            return Err("too less frame sizes for frame_num");
+        } else if args[first + i] == "..." {
+            if i == 0 {
+                return Err("... can't be the first frame size");
+            } else {
+                let (width, height) = frame_size[i - 1];
+                for _ in i..frame_num {
+                    frame_size.push((width, height));
+                }
+                break;
+            }
+        } else {
+            frame_size.push(parse_frame_size(&args[first + i])?);
+        }
+    }
+    Ok(frame_size)
+}
+
+fn parse_frame_size(size: &str) -> Result<(i32, i32), &'static str> {
+    let nums: Vec<&str> = size.split('x').collect();
+    if nums.len() != 2 {
+        return Err("invalid frame size");
+    }
+    if let Ok(width) = nums[0].parse::<i32>() {
+        if let Ok(height) = nums[1].parse::<i32>() {
+            Ok((width, height))
+        } else {
+            Err("invalid frame height")
+        }
+    } else {
+        Err("invalid frame width")
+    }
+}
+
+// A schedule has a "start_frame WxH" line for every size change, the size
+// holds until the next line. A line with only WxH starts on the frame after
+// the previous line, so per-frame size dumps can be used as they are. Empty
+// lines and lines starting with # are skipped. The sizes cover frame_num
+// frames and every frame the schedule names, whichever is more.
+pub fn read_frame_schedule(path: &str, frame_num: usize) -> Result<Vec<(i32, i32)>, &'static str> {
+    let mut text = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut text))
+        .map_err(|_| "can't read frame size schedule")?;
+
+    let mut changes: Vec<(usize, (i32, i32))> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (start, size) = match fields.len() {
+            1 => (changes.last().map_or(0, |c| c.0 + 1), fields[0]),
+            2 => {
+                (fields[0].parse::<usize>().map_err(|_| "invalid start frame in schedule")?,
+                 fields[1])
+            }
+            _ => return Err("schedule lines must be start_frame WxH or WxH"),
+        };
+        match changes.last() {
+            None if start != 0 => return Err("frame size schedule must start at frame 0"),
+            Some(last) if start <= last.0 => {
+                return Err("frame size schedule must be in increasing frame order")
+            }
+            _ => {}
+        }
+        changes.push((start, parse_frame_size(size)?));
+    }
+    if changes.is_empty() && frame_num > 0 {
+        return Err("frame size schedule is empty");
+    }
+
+    let frame_count = changes.last().map_or(frame_num, |last| frame_num.max(last.0 + 1));
+    let mut frame_size = Vec::with_capacity(frame_count);
+    for (c, &(start, size)) in changes.iter().enumerate() {
+        let end = changes.get(c + 1).map_or(frame_count, |next| next.0);
+        for _ in start..end {
+            frame_size.push(size);
+        }
+    }
+    Ok(frame_size)
+}
+
+// Whole frames from a file or, for "-", from stdin. Short reads from pipes are
+// retried, and an input that ends between frames is told apart from one that
+// ends within a frame.
+pub struct FrameReader {
+    name: String,
+    input: Box<dyn Read + Send>,
+    // inputs read one after the other once the current one ends
+    pending: VecDeque<String>,
+    frames: usize,
+}
+
+impl FrameReader {
+    pub fn open(path: &str) -> io::Result<FrameReader> {
+        FrameReader::open_all(&[path.to_string()])
+    }
+
+    // the frames of all inputs in turn, as if they were one; a frame can't
+    // span two inputs
+    pub fn open_all(paths: &[String]) -> io::Result<FrameReader> {
+        let mut pending: VecDeque<String> = paths.iter().cloned().collect();
+        let path = pending.pop_front().unwrap_or_else(|| "-".to_string());
+        Ok(FrameReader {
+            name: input_name(&path),
+            input: open_input(&path)?,
+            pending,
+            frames: 0,
+        })
+    }
+
+    // frames read or skipped so far
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    // the next frame into buf, which keeps its allocation from frame to frame;
+    // false at the end of the input
+    pub fn read_frame(&mut self, buf: &mut Vec<u8>, bytes: usize) -> io::Result<bool> {
+        buf.resize(bytes, 0);
+        loop {
+            let mut filled = 0;
+            while filled < bytes {
+                match self.input.read(&mut buf[filled..]) {
+                    Ok(0) => break,
+                    Ok(n) => filled += n,
+                    Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            if filled > 0 || bytes == 0 || !self.next_input()? {
+                return self.advance(filled, bytes);
+            }
+        }
+    }
+
+    pub fn skip_frame(&mut self, bytes: usize) -> io::Result<bool> {
+        loop {
+            let skipped = io::copy(&mut Read::by_ref(&mut self.input).take(bytes as u64),
+                                   &mut io::sink())? as usize;
+            if skipped > 0 || bytes == 0 || !self.next_input()? {
+                return self.advance(skipped, bytes);
+            }
+        }
+    }
+
+    // error for a frame that was needed after the end of the input
+    pub fn end_of_input(&self) -> Error {
+        Error::new(ErrorKind::UnexpectedEof,
+                   format!("{} ends after {} frames", self.name, self.frames))
+    }
+
+    // false once there are no more inputs
+    fn next_input(&mut self) -> io::Result<bool> {
+        match self.pending.pop_front() {
+            Some(path) => {
+                self.input = open_input(&path)?;
+                self.name = input_name(&path);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn advance(&mut self, bytes_read: usize, bytes: usize) -> io::Result<bool> {
+        if bytes_read == bytes {
+            self.frames += 1;
+            Ok(true)
+        } else if bytes_read == 0 {
+            Ok(false)
+        } else {
+            Err(Error::new(ErrorKind::UnexpectedEof,
+                           format!("{} is truncated, frame {} has {} of {} bytes",
+                                   self.name,
+                                   self.frames,
+                                   bytes_read,
+                                   bytes)))
+        }
+    }
+}
+
+fn open_input(path: &str) -> io::Result<Box<dyn Read + Send>> {
+    if path == "-" {
+        Ok(Box::new(io::stdin()))
+    } else {
+        Ok(Box::new(File::open(path)?))
+    }
+}
+
+fn input_name(path: &str) -> String {
+    if path == "-" { "stdin".to_string() } else { path.to_string() }
+}
@@ -0,0 +1,960 @@
+// H.264 parameter sets, slice headers and SEI messages, parsed from the RBSP
+// of the NAL units found by the bin mode scanner.
+
+use std::collections::HashMap;
+use std::fmt;
+use super::bits::{unescape, BitReader};
+use super::sei;
+use super::sei::{ContentLightLevel, MasteringDisplayColourVolume, SeiMessage,
+                 UserDataUnregistered};
+use super::vui;
+use super::vui::VideoSignal;
+
+pub const NAL_SLICE: u8 = 1;
+pub const NAL_SLICE_IDR: u8 = 5;
+pub const NAL_SEI: u8 = 6;
+pub const NAL_SPS: u8 = 7;
+pub const NAL_PPS: u8 = 8;
+pub const NAL_PREFIX: u8 = 14;
+
+pub fn nal_unit_type(nal: &[u8]) -> u8 {
+    nal.first().map_or(0xff, |b| b & 0x1f)
+}
+
+pub fn nal_ref_idc(nal: &[u8]) -> u8 {
+    nal.first().map_or(0, |b| (b >> 5) & 0x3)
+}
+
+pub fn nal_unit_type_name(nal_unit_type: u8) -> &'static str {
+    match nal_unit_type {
+        1 => "SLICE",
+        2 => "DPA",
+        3 => "DPB",
+        4 => "DPC",
+        5 => "IDR",
+        6 => "SEI",
+        7 => "SPS",
+        8 => "PPS",
+        9 => "AUD",
+        10 => "EOSEQ",
+        11 => "EOS",
+        12 => "FD",
+        13 => "SPS_EXT",
+        14 => "PREFIX",
+        15 => "SUBSET_SPS",
+        19 => "AUX",
+        20 => "SLICE_EXT",
+        _ => "UNSPEC",
+    }
+}
+
+pub fn slice_type_name(slice_type: u32) -> &'static str {
+    ["P", "B", "I", "SP", "SI"][slice_type as usize % 5]
+}
+
+// temporal_id from the SVC or MVC header extension of a prefix NAL unit
+pub fn prefix_temporal_id(nal: &[u8]) -> Option<u8> {
+    if nal_unit_type(nal) != NAL_PREFIX || nal.len() < 4 {
+        return None;
+    }
+    if nal[1] & 0x80 != 0 {
+        Some(nal[3] >> 5)
+    } else {
+        Some((nal[3] >> 3) & 0x7)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Sps {
+    pub profile_idc: u32,
+    // constraint_set0_flag to constraint_set5_flag and the reserved bits
+    pub constraint_set_flags: u32,
+    pub level_idc: u32,
+    pub seq_parameter_set_id: u32,
+    pub chroma_format_idc: u32,
+    pub separate_colour_plane_flag: bool,
+    pub bit_depth_luma: u32,
+    pub bit_depth_chroma: u32,
+    pub qpprime_y_zero_transform_bypass_flag: bool,
+    // empty without seq_scaling_matrix_present_flag
+    pub seq_scaling_lists: Vec<ScalingList>,
+    pub log2_max_frame_num: u32,
+    pub pic_order_cnt_type: u32,
+    pub log2_max_pic_order_cnt_lsb: u32,
+    pub delta_pic_order_always_zero_flag: bool,
+    pub offset_for_non_ref_pic: i32,
+    pub offset_for_top_to_bottom_field: i32,
+    pub offset_for_ref_frame: Vec<i32>,
+    pub max_num_ref_frames: u32,
+    pub gaps_in_frame_num_value_allowed_flag: bool,
+    pub pic_width_in_mbs: u32,
+    pub pic_height_in_map_units: u32,
+    pub frame_mbs_only_flag: bool,
+    pub mb_adaptive_frame_field_flag: bool,
+    pub direct_8x8_inference_flag: bool,
+    // left, right, top and bottom in crop units
+    pub frame_crop: (u32, u32, u32, u32),
+    pub vui: Option<Vui>,
+}
+
+impl Sps {
+    pub fn parse(rbsp: &[u8]) -> Result<Sps, &'static str> {
+        let mut r = BitReader::new(rbsp);
+        let mut sps = Sps {
+            profile_idc: r.bits(8)?,
+            constraint_set_flags: r.bits(8)?,
+            level_idc: r.bits(8)?,
+            seq_parameter_set_id: r.ue()?,
+            ..Sps::default()
+        };
+
+        sps.chroma_format_idc = 1;
+        sps.bit_depth_luma = 8;
+        sps.bit_depth_chroma = 8;
+        if [100, 110, 122, 244, 44, 83, 86, 118, 128, 138, 139, 134, 135]
+            .contains(&sps.profile_idc) {
+            sps.chroma_format_idc = r.ue()?;
+            if sps.chroma_format_idc == 3 {
+                sps.separate_colour_plane_flag = r.bit()?;
+            }
+            sps.bit_depth_luma = r.ue()? + 8;
+            sps.bit_depth_chroma = r.ue()? + 8;
+            sps.qpprime_y_zero_transform_bypass_flag = r.bit()?;
+            if r.bit()? {
+                // seq_scaling_matrix_present_flag
+                let lists = if sps.chroma_format_idc != 3 { 8 } else { 12 };
+                for i in 0..lists {
+                    sps.seq_scaling_lists.push(ScalingList::parse(&mut r, i)?);
+                }
+            }
+        }
+
+        sps.log2_max_frame_num = r.ue()? + 4;
+        sps.pic_order_cnt_type = r.ue()?;
+        if sps.pic_order_cnt_type == 0 {
+            sps.log2_max_pic_order_cnt_lsb = r.ue()? + 4;
+        } else if sps.pic_order_cnt_type == 1 {
+            sps.delta_pic_order_always_zero_flag = r.bit()?;
+            sps.offset_for_non_ref_pic = r.se()?;
+            sps.offset_for_top_to_bottom_field = r.se()?;
+            for _ in 0..r.ue()? {
+                sps.offset_for_ref_frame.push(r.se()?);
+            }
+        }
+        sps.max_num_ref_frames = r.ue()?;
+        sps.gaps_in_frame_num_value_allowed_flag = r.bit()?;
+        sps.pic_width_in_mbs = r.ue()? + 1;
+        sps.pic_height_in_map_units = r.ue()? + 1;
+        sps.frame_mbs_only_flag = r.bit()?;
+        if !sps.frame_mbs_only_flag {
+            sps.mb_adaptive_frame_field_flag = r.bit()?;
+        }
+        sps.direct_8x8_inference_flag = r.bit()?;
+        if r.bit()? {
+            sps.frame_crop = (r.ue()?, r.ue()?, r.ue()?, r.ue()?);
+        }
+        if r.bit()? {
+            sps.vui = Some(Vui::parse(&mut r)?);
+        }
+        Ok(sps)
+    }
+
+    // decoded frame size, after cropping
+    pub fn size(&self) -> (u32, u32) {
+        let (width, height) = self.coded_size();
+        let field_factor = if self.frame_mbs_only_flag { 1 } else { 2 };
+        let (crop_x, crop_y) = match self.chroma_format_idc {
+            _ if self.separate_colour_plane_flag => (1, field_factor),
+            1 => (2, 2 * field_factor),
+            2 => (2, field_factor),
+            _ => (1, field_factor),
+        };
+        let (left, right, top, bottom) = self.frame_crop;
+        (width.saturating_sub(crop_x * (left + right)),
+         height.saturating_sub(crop_y * (top + bottom)))
+    }
+
+    // frame size in whole macroblocks
+    pub fn coded_size(&self) -> (u32, u32) {
+        let field_factor = if self.frame_mbs_only_flag { 1 } else { 2 };
+        (self.pic_width_in_mbs * 16, field_factor * self.pic_height_in_map_units * 16)
+    }
+
+    pub fn profile_name(&self) -> &'static str {
+        let constrained = self.constraint_set_flags & 0x40 != 0;
+        match self.profile_idc {
+            66 if constrained => "Constrained Baseline",
+            66 => "Baseline",
+            77 => "Main",
+            88 => "Extended",
+            100 => "High",
+            110 => "High 10",
+            122 => "High 4:2:2",
+            244 => "High 4:4:4 Predictive",
+            44 => "CAVLC 4:4:4 Intra",
+            83 => "Scalable Baseline",
+            86 => "Scalable High",
+            118 => "Multiview High",
+            128 => "Stereo High",
+            _ => "unknown",
+        }
+    }
+
+    pub fn level_name(&self) -> String {
+        // level 1b is signalled as 11 with constraint_set3_flag, or as 9
+        let constraint_set3 = self.constraint_set_flags & 0x10 != 0;
+        if self.level_idc == 9 ||
+           (self.level_idc == 11 && constraint_set3 && [66, 77].contains(&self.profile_idc)) {
+            return "1b".to_string();
+        }
+        format!("{}.{}", self.level_idc / 10, self.level_idc % 10)
+    }
+}
+
+impl fmt::Display for Sps {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (width, height) = self.size();
+        let (coded_width, coded_height) = self.coded_size();
+        writeln!(f,
+                 "SPS {}: {} profile, level {}, {}x{} ({}x{} coded), {} {} bit{}",
+                 self.seq_parameter_set_id,
+                 self.profile_name(),
+                 self.level_name(),
+                 width,
+                 height,
+                 coded_width,
+                 coded_height,
+                 vui::chroma_format_name(self.chroma_format_idc),
+                 self.bit_depth_luma,
+                 if self.bit_depth_chroma != self.bit_depth_luma {
+                     format!(" ({} bit chroma)", self.bit_depth_chroma)
+                 } else {
+                     String::new()
+                 })?;
+        writeln!(f,
+                 "  {}, {} reference frames, frame_num {} bits, poc type {}{}{}",
+                 if self.frame_mbs_only_flag {
+                     "progressive"
+                 } else if self.mb_adaptive_frame_field_flag {
+                     "interlaced (MBAFF)"
+                 } else {
+                     "interlaced"
+                 },
+                 self.max_num_ref_frames,
+                 self.log2_max_frame_num,
+                 self.pic_order_cnt_type,
+                 if self.pic_order_cnt_type == 0 {
+                     format!(" (lsb {} bits)", self.log2_max_pic_order_cnt_lsb)
+                 } else {
+                     String::new()
+                 },
+                 if self.seq_scaling_lists.is_empty() {
+                     ""
+                 } else {
+                     ", scaling matrix"
+                 })?;
+        if self.frame_crop != (0, 0, 0, 0) {
+            let (left, right, top, bottom) = self.frame_crop;
+            writeln!(f,
+                     "  crop left {} right {} top {} bottom {}",
+                     left,
+                     right,
+                     top,
+                     bottom)?;
+        }
+        if let Some(ref vui) = self.vui {
+            write!(f, "{}", vui)?;
+        }
+        Ok(())
+    }
+}
+
+// a scaling list of the SPS or PPS, 4x4 for the first 6 and 8x8 after that
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScalingList {
+    // falls back to the previous list or the one of the SPS
+    NotPresent,
+    // useDefaultScalingMatrixFlag
+    Default,
+    Explicit(Vec<u32>),
+}
+
+impl ScalingList {
+    fn parse(r: &mut BitReader, i: usize) -> Result<ScalingList, &'static str> {
+        if !r.bit()? {
+            return Ok(ScalingList::NotPresent);
+        }
+        let size = if i < 6 { 16 } else { 64 };
+        let mut list = Vec::with_capacity(size);
+        let mut last_scale = 8;
+        let mut next_scale = 8;
+        for j in 0..size {
+            if next_scale != 0 {
+                let delta_scale = r.se()?;
+                next_scale = (last_scale + delta_scale + 256) % 256;
+                if j == 0 && next_scale == 0 {
+                    return Ok(ScalingList::Default);
+                }
+            }
+            last_scale = if next_scale == 0 { last_scale } else { next_scale };
+            list.push(last_scale as u32);
+        }
+        Ok(ScalingList::Explicit(list))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Vui {
+    pub aspect_ratio_idc: Option<u32>,
+    // sample aspect ratio, (0, 0) when unspecified
+    pub sar: (u32, u32),
+    pub overscan_appropriate_flag: Option<bool>,
+    pub video_signal: Option<VideoSignal>,
+    // chroma_sample_loc_type_top_field, chroma_sample_loc_type_bottom_field
+    pub chroma_sample_loc_type: Option<(u32, u32)>,
+    // num_units_in_tick, time_scale, fixed_frame_rate_flag
+    pub timing_info: Option<(u32, u32, bool)>,
+    pub nal_hrd: Option<Hrd>,
+    pub vcl_hrd: Option<Hrd>,
+    pub low_delay_hrd_flag: bool,
+    pub pic_struct_present_flag: bool,
+    pub bitstream_restriction: Option<BitstreamRestriction>,
+}
+
+impl Vui {
+    fn parse(r: &mut BitReader) -> Result<Vui, &'static str> {
+        let mut vui = Vui::default();
+        if r.bit()? {
+            let (aspect_ratio_idc, sar) = vui::aspect_ratio(r)?;
+            vui.aspect_ratio_idc = Some(aspect_ratio_idc);
+            vui.sar = sar;
+        }
+        if r.bit()? {
+            vui.overscan_appropriate_flag = Some(r.bit()?);
+        }
+        if r.bit()? {
+            vui.video_signal = Some(VideoSignal::parse(r)?);
+        }
+        if r.bit()? {
+            vui.chroma_sample_loc_type = Some((r.ue()?, r.ue()?));
+        }
+        if r.bit()? {
+            vui.timing_info = Some((r.bits(32)?, r.bits(32)?, r.bit()?));
+        }
+        if r.bit()? {
+            vui.nal_hrd = Some(Hrd::parse(r)?);
+        }
+        if r.bit()? {
+            vui.vcl_hrd = Some(Hrd::parse(r)?);
+        }
+        if vui.nal_hrd.is_some() || vui.vcl_hrd.is_some() {
+            vui.low_delay_hrd_flag = r.bit()?;
+        }
+        vui.pic_struct_present_flag = r.bit()?;
+        if r.bit()? {
+            vui.bitstream_restriction = Some(BitstreamRestriction {
+                motion_vectors_over_pic_boundaries_flag: r.bit()?,
+                max_bytes_per_pic_denom: r.ue()?,
+                max_bits_per_mb_denom: r.ue()?,
+                log2_max_mv_length_horizontal: r.ue()?,
+                log2_max_mv_length_vertical: r.ue()?,
+                max_num_reorder_frames: r.ue()?,
+                max_dec_frame_buffering: r.ue()?,
+            });
+        }
+        Ok(vui)
+    }
+
+    // frames per second, two ticks make a frame
+    pub fn frame_rate(&self) -> Option<f64> {
+        match self.timing_info {
+            Some((num_units_in_tick, time_scale, _)) if num_units_in_tick > 0 => {
+                Some(time_scale as f64 / (2.0 * num_units_in_tick as f64))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Vui {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.aspect_ratio_idc.is_some() {
+            writeln!(f, "  sample aspect ratio {}:{}", self.sar.0, self.sar.1)?;
+        }
+        if let Some(ref video_signal) = self.video_signal {
+            writeln!(f, "  {}", video_signal.describe())?;
+        }
+        if let (Some(fps), Some((_, _, fixed))) = (self.frame_rate(), self.timing_info) {
+            writeln!(f,
+                     "  {:.3} fps{}{}",
+                     fps,
+                     if fixed { " fixed" } else { "" },
+                     if self.pic_struct_present_flag {
+                         ", pic_struct present"
+                     } else {
+                         ""
+                     })?;
+        }
+        for (name, hrd) in [("NAL", &self.nal_hrd), ("VCL", &self.vcl_hrd)].iter() {
+            if let Some(ref hrd) = **hrd {
+                for &(bit_rate, cpb_size, cbr) in hrd.schedules.iter() {
+                    writeln!(f,
+                             "  {} HRD {} bit/s, cpb {} bits{}",
+                             name,
+                             bit_rate,
+                             cpb_size,
+                             if cbr { ", cbr" } else { "" })?;
+                }
+            }
+        }
+        if let Some(ref restriction) = self.bitstream_restriction {
+            writeln!(f,
+                     "  max {} reorder frames, {} frame buffers",
+                     restriction.max_num_reorder_frames,
+                     restriction.max_dec_frame_buffering)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Hrd {
+    pub bit_rate_scale: u32,
+    pub cpb_size_scale: u32,
+    // BitRate, CpbSize and cbr_flag of each schedule
+    pub schedules: Vec<(u64, u64, bool)>,
+    pub initial_cpb_removal_delay_length: u32,
+    pub cpb_removal_delay_length: u32,
+    pub dpb_output_delay_length: u32,
+    pub time_offset_length: u32,
+}
+
+impl Hrd {
+    fn parse(r: &mut BitReader) -> Result<Hrd, &'static str> {
+        let cpb_cnt = r.ue()? + 1;
+        let mut hrd = Hrd {
+            bit_rate_scale: r.bits(4)?,
+            cpb_size_scale: r.bits(4)?,
+            ..Hrd::default()
+        };
+        for _ in 0..cpb_cnt {
+            let bit_rate = (r.ue()? as u64 + 1) << (6 + hrd.bit_rate_scale);
+            let cpb_size = (r.ue()? as u64 + 1) << (4 + hrd.cpb_size_scale);
+            hrd.schedules.push((bit_rate, cpb_size, r.bit()?));
+        }
+        hrd.initial_cpb_removal_delay_length = r.bits(5)? + 1;
+        hrd.cpb_removal_delay_length = r.bits(5)? + 1;
+        hrd.dpb_output_delay_length = r.bits(5)? + 1;
+        hrd.time_offset_length = r.bits(5)?;
+        Ok(hrd)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BitstreamRestriction {
+    pub motion_vectors_over_pic_boundaries_flag: bool,
+    pub max_bytes_per_pic_denom: u32,
+    pub max_bits_per_mb_denom: u32,
+    pub log2_max_mv_length_horizontal: u32,
+    pub log2_max_mv_length_vertical: u32,
+    pub max_num_reorder_frames: u32,
+    pub max_dec_frame_buffering: u32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Pps {
+    pub pic_parameter_set_id: u32,
+    pub seq_parameter_set_id: u32,
+    pub entropy_coding_mode_flag: bool,
+    pub bottom_field_pic_order_in_frame_present_flag: bool,
+    pub num_slice_groups: u32,
+    pub slice_group_map_type: u32,
+    pub num_ref_idx_l0_default_active: u32,
+    pub num_ref_idx_l1_default_active: u32,
+    pub weighted_pred_flag: bool,
+    pub weighted_bipred_idc: u32,
+    pub pic_init_qp: i32,
+    pub pic_init_qs: i32,
+    pub chroma_qp_index_offset: i32,
+    pub deblocking_filter_control_present_flag: bool,
+    pub constrained_intra_pred_flag: bool,
+    pub redundant_pic_cnt_present_flag: bool,
+    pub transform_8x8_mode_flag: bool,
+    // empty without pic_scaling_matrix_present_flag
+    pub pic_scaling_lists: Vec<ScalingList>,
+    pub second_chroma_qp_index_offset: i32,
+}
+
+impl Pps {
+    // the SPS are needed for the number of scaling lists in 4:4:4
+    pub fn parse(rbsp: &[u8], sps: &HashMap<u32, Sps>) -> Result<Pps, &'static str> {
+        let mut r = BitReader::new(rbsp);
+        let mut pps = Pps {
+            pic_parameter_set_id: r.ue()?,
+            seq_parameter_set_id: r.ue()?,
+            entropy_coding_mode_flag: r.bit()?,
+            bottom_field_pic_order_in_frame_present_flag: r.bit()?,
+            num_slice_groups: r.ue()? + 1,
+            ..Pps::default()
+        };
+        if pps.num_slice_groups > 1 {
+            pps.slice_group_map_type = r.ue()?;
+            match pps.slice_group_map_type {
+                0 => {
+                    for _ in 0..pps.num_slice_groups {
+                        r.ue()?; // run_length_minus1
+                    }
+                }
+                2 => {
+                    for _ in 1..pps.num_slice_groups {
+                        r.ue()?; // top_left
+                        r.ue()?; // bottom_right
+                    }
+                }
+                3..=5 => {
+                    r.skip(1)?; // slice_group_change_direction_flag
+                    r.ue()?; // slice_group_change_rate_minus1
+                }
+                6 => {
+                    let bits = 32 - (pps.num_slice_groups - 1).leading_zeros();
+                    for _ in 0..r.ue()? + 1 {
+                        r.skip(bits as usize)?; // slice_group_id
+                    }
+                }
+                _ => {}
+            }
+        }
+        pps.num_ref_idx_l0_default_active = r.ue()? + 1;
+        pps.num_ref_idx_l1_default_active = r.ue()? + 1;
+        pps.weighted_pred_flag = r.bit()?;
+        pps.weighted_bipred_idc = r.bits(2)?;
+        pps.pic_init_qp = r.se()? + 26;
+        pps.pic_init_qs = r.se()? + 26;
+        pps.chroma_qp_index_offset = r.se()?;
+        pps.deblocking_filter_control_present_flag = r.bit()?;
+        pps.constrained_intra_pred_flag = r.bit()?;
+        pps.redundant_pic_cnt_present_flag = r.bit()?;
+        pps.second_chroma_qp_index_offset = pps.chroma_qp_index_offset;
+        if r.more_rbsp_data() {
+            pps.transform_8x8_mode_flag = r.bit()?;
+            if r.bit()? {
+                // pic_scaling_matrix_present_flag
+                let chroma_format_idc = sps.get(&pps.seq_parameter_set_id)
+                    .map_or(1, |sps| sps.chroma_format_idc);
+                let lists_8x8 = if chroma_format_idc != 3 { 2 } else { 6 };
+                for i in 0..6 + lists_8x8 * pps.transform_8x8_mode_flag as usize {
+                    pps.pic_scaling_lists.push(ScalingList::parse(&mut r, i)?);
+                }
+            }
+            pps.second_chroma_qp_index_offset = r.se()?;
+        }
+        Ok(pps)
+    }
+}
+
+impl fmt::Display for Pps {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f,
+                 "PPS {} (SPS {}): {}, {} slice group{}, {} + {} default references",
+                 self.pic_parameter_set_id,
+                 self.seq_parameter_set_id,
+                 if self.entropy_coding_mode_flag { "CABAC" } else { "CAVLC" },
+                 self.num_slice_groups,
+                 if self.num_slice_groups > 1 { "s" } else { "" },
+                 self.num_ref_idx_l0_default_active,
+                 self.num_ref_idx_l1_default_active)?;
+        writeln!(f,
+                 "  init qp {}, chroma qp offset {} {}, weighted pred {} bipred {}{}{}",
+                 self.pic_init_qp,
+                 self.chroma_qp_index_offset,
+                 self.second_chroma_qp_index_offset,
+                 self.weighted_pred_flag as u32,
+                 self.weighted_bipred_idc,
+                 if self.transform_8x8_mode_flag { ", 8x8 transform" } else { "" },
+                 if self.constrained_intra_pred_flag {
+                     ", constrained intra"
+                 } else {
+                     ""
+                 })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SliceHeader {
+    pub first_mb_in_slice: u32,
+    pub slice_type: u32,
+    pub pic_parameter_set_id: u32,
+    pub frame_num: u32,
+    pub field_pic_flag: bool,
+    pub bottom_field_flag: bool,
+    pub idr_pic_id: u32,
+    pub pic_order_cnt_lsb: u32,
+    pub delta_pic_order_cnt_bottom: i32,
+    pub delta_pic_order_cnt: [i32; 2],
+}
+
+// the SPS and PPS seen so far, by id
+#[derive(Default)]
+pub struct ParameterSets {
+    pub sps: HashMap<u32, Sps>,
+    pub pps: HashMap<u32, Pps>,
+}
+
+impl ParameterSets {
+    pub fn new() -> ParameterSets {
+        ParameterSets::default()
+    }
+
+    // takes the NAL unit without its start code, other types than SPS and
+    // PPS are ignored
+    pub fn update(&mut self, nal: &[u8]) -> Result<(), &'static str> {
+        match nal_unit_type(nal) {
+            NAL_SPS => {
+                let sps = Sps::parse(&unescape(&nal[1..]))?;
+                self.sps.insert(sps.seq_parameter_set_id, sps);
+            }
+            NAL_PPS => {
+                let pps = Pps::parse(&unescape(&nal[1..]), &self.sps)?;
+                self.pps.insert(pps.pic_parameter_set_id, pps);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // the SPS a slice refers to through its PPS
+    pub fn sps_of(&self, slice: &SliceHeader) -> Result<&Sps, &'static str> {
+        self.pps
+            .get(&slice.pic_parameter_set_id)
+            .and_then(|pps| self.sps.get(&pps.seq_parameter_set_id))
+            .ok_or("slice refers to a missing parameter set")
+    }
+
+    pub fn slice_header(&self, nal: &[u8]) -> Result<SliceHeader, &'static str> {
+        let rbsp = unescape(&nal[1..]);
+        let mut r = BitReader::new(&rbsp);
+        let mut slice = SliceHeader {
+            first_mb_in_slice: r.ue()?,
+            slice_type: r.ue()?,
+            pic_parameter_set_id: r.ue()?,
+            ..SliceHeader::default()
+        };
+        let sps = self.sps_of(&slice)?;
+        let pps = &self.pps[&slice.pic_parameter_set_id];
+        if sps.separate_colour_plane_flag {
+            r.skip(2)?; // colour_plane_id
+        }
+        slice.frame_num = r.bits(sps.log2_max_frame_num)?;
+        if !sps.frame_mbs_only_flag {
+            slice.field_pic_flag = r.bit()?;
+            if slice.field_pic_flag {
+                slice.bottom_field_flag = r.bit()?;
+            }
+        }
+        if nal_unit_type(nal) == NAL_SLICE_IDR {
+            slice.idr_pic_id = r.ue()?;
+        }
+        let bottom_present = pps.bottom_field_pic_order_in_frame_present_flag &&
+                             !slice.field_pic_flag;
+        if sps.pic_order_cnt_type == 0 {
+            slice.pic_order_cnt_lsb = r.bits(sps.log2_max_pic_order_cnt_lsb)?;
+            if bottom_present {
+                slice.delta_pic_order_cnt_bottom = r.se()?;
+            }
+        }
+        if sps.pic_order_cnt_type == 1 && !sps.delta_pic_order_always_zero_flag {
+            slice.delta_pic_order_cnt[0] = r.se()?;
+            if bottom_present {
+                slice.delta_pic_order_cnt[1] = r.se()?;
+            }
+        }
+        Ok(slice)
+    }
+}
+
+// PicOrderCnt() of the pictures in decoding order (8.2.1), without following
+// memory_management_control_operation 5
+#[derive(Default)]
+pub struct PicOrderCount {
+    prev_pic_order_cnt_msb: i32,
+    prev_pic_order_cnt_lsb: i32,
+    prev_frame_num: u32,
+    prev_frame_num_offset: i32,
+}
+
+impl PicOrderCount {
+    pub fn new() -> PicOrderCount {
+        PicOrderCount::default()
+    }
+
+    // takes the first slice of the picture and nal_ref_idc of its NAL unit
+    pub fn next(&mut self, sps: &Sps, slice: &SliceHeader, nal_ref_idc: u8, idr: bool) -> i32 {
+        let max_frame_num = 1i32 << sps.log2_max_frame_num;
+        let frame_num = slice.frame_num as i32;
+        let frame_num_offset = if idr {
+            0
+        } else if self.prev_frame_num > slice.frame_num {
+            self.prev_frame_num_offset + max_frame_num
+        } else {
+            self.prev_frame_num_offset
+        };
+        self.prev_frame_num = slice.frame_num;
+        self.prev_frame_num_offset = frame_num_offset;
+
+        // (TopFieldOrderCnt, BottomFieldOrderCnt)
+        let (top, bottom) = match sps.pic_order_cnt_type {
+            0 => {
+                if idr {
+                    self.prev_pic_order_cnt_msb = 0;
+                    self.prev_pic_order_cnt_lsb = 0;
+                }
+                let max_lsb = 1i32 << sps.log2_max_pic_order_cnt_lsb;
+                let lsb = slice.pic_order_cnt_lsb as i32;
+                let prev_lsb = self.prev_pic_order_cnt_lsb;
+                let msb = if lsb < prev_lsb && prev_lsb - lsb >= max_lsb / 2 {
+                    self.prev_pic_order_cnt_msb + max_lsb
+                } else if lsb > prev_lsb && lsb - prev_lsb > max_lsb / 2 {
+                    self.prev_pic_order_cnt_msb - max_lsb
+                } else {
+                    self.prev_pic_order_cnt_msb
+                };
+                if nal_ref_idc != 0 {
+                    self.prev_pic_order_cnt_msb = msb;
+                    self.prev_pic_order_cnt_lsb = lsb;
+                }
+                if slice.field_pic_flag {
+                    (msb + lsb, msb + lsb)
+                } else {
+                    (msb + lsb, msb + lsb + slice.delta_pic_order_cnt_bottom)
+                }
+            }
+            1 => {
+                let cycle = &sps.offset_for_ref_frame;
+                let mut abs_frame_num = if cycle.is_empty() {
+                    0
+                } else {
+                    frame_num_offset + frame_num
+                };
+                if nal_ref_idc == 0 && abs_frame_num > 0 {
+                    abs_frame_num -= 1;
+                }
+                let mut expected = 0;
+                if abs_frame_num > 0 {
+                    let delta_per_cycle: i32 = cycle.iter().sum();
+                    let count = (abs_frame_num - 1) / cycle.len() as i32;
+                    let in_cycle = ((abs_frame_num - 1) % cycle.len() as i32) as usize;
+                    expected = count * delta_per_cycle + cycle[..=in_cycle].iter().sum::<i32>();
+                }
+                if nal_ref_idc == 0 {
+                    expected += sps.offset_for_non_ref_pic;
+                }
+                let top = expected + slice.delta_pic_order_cnt[0];
+                if slice.field_pic_flag {
+                    (top, top + sps.offset_for_top_to_bottom_field)
+                } else {
+                    (top,
+                     top + sps.offset_for_top_to_bottom_field + slice.delta_pic_order_cnt[1])
+                }
+            }
+            _ => {
+                let poc = if idr {
+                    0
+                } else if nal_ref_idc == 0 {
+                    2 * (frame_num_offset + frame_num) - 1
+                } else {
+                    2 * (frame_num_offset + frame_num)
+                };
+                (poc, poc)
+            }
+        };
+
+        if !slice.field_pic_flag {
+            top.min(bottom)
+        } else if slice.bottom_field_flag {
+            bottom
+        } else {
+            top
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RecoveryPoint {
+    pub recovery_frame_cnt: u32,
+    pub exact_match_flag: bool,
+    pub broken_link_flag: bool,
+    pub changing_slice_group_idc: u32,
+}
+
+impl RecoveryPoint {
+    pub fn parse(payload: &[u8]) -> Result<RecoveryPoint, &'static str> {
+        let mut r = BitReader::new(payload);
+        Ok(RecoveryPoint {
+            recovery_frame_cnt: r.ue()?,
+            exact_match_flag: r.bit()?,
+            broken_link_flag: r.bit()?,
+            changing_slice_group_idc: r.bits(2)?,
+        })
+    }
+}
+
+impl fmt::Display for RecoveryPoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "recovery_frame_cnt {}{}{}",
+               self.recovery_frame_cnt,
+               if self.exact_match_flag { ", exact match" } else { "" },
+               if self.broken_link_flag { ", broken link" } else { "" })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BufferingPeriod {
+    pub seq_parameter_set_id: u32,
+    // initial_cpb_removal_delay and initial_cpb_removal_delay_offset of each
+    // schedule
+    pub nal_initial_cpb_removal: Vec<(u32, u32)>,
+    pub vcl_initial_cpb_removal: Vec<(u32, u32)>,
+}
+
+impl BufferingPeriod {
+    pub fn parse(payload: &[u8], sps: &HashMap<u32, Sps>) -> Result<BufferingPeriod, &'static str> {
+        let mut r = BitReader::new(payload);
+        let mut bp = BufferingPeriod {
+            seq_parameter_set_id: r.ue()?,
+            ..BufferingPeriod::default()
+        };
+        let vui = sps.get(&bp.seq_parameter_set_id)
+            .ok_or("buffering period refers to a missing SPS")?
+            .vui
+            .as_ref()
+            .ok_or("buffering period without HRD parameters")?;
+        for (hrd, delays) in [(&vui.nal_hrd, &mut bp.nal_initial_cpb_removal),
+                              (&vui.vcl_hrd, &mut bp.vcl_initial_cpb_removal)] {
+            if let Some(ref hrd) = *hrd {
+                for _ in 0..hrd.schedules.len() {
+                    delays.push((r.bits(hrd.initial_cpb_removal_delay_length)?,
+                                 r.bits(hrd.initial_cpb_removal_delay_length)?));
+                }
+            }
+        }
+        Ok(bp)
+    }
+}
+
+impl fmt::Display for BufferingPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SPS {}", self.seq_parameter_set_id)?;
+        for (name, delays) in [("NAL", &self.nal_initial_cpb_removal),
+                               ("VCL", &self.vcl_initial_cpb_removal)] {
+            for &(delay, offset) in delays.iter() {
+                write!(f, ", {} initial cpb removal delay {} offset {}", name, delay, offset)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PicTiming {
+    pub cpb_removal_delay: Option<u32>,
+    pub dpb_output_delay: Option<u32>,
+    pub pic_struct: Option<u32>,
+    // hours, minutes, seconds and n_frames of the clock timestamps sent, the
+    // parts left out are 0
+    pub clock_timestamps: Vec<(u32, u32, u32, u32)>,
+}
+
+impl PicTiming {
+    // takes the active SPS
+    pub fn parse(payload: &[u8], sps: &Sps) -> Result<PicTiming, &'static str> {
+        let mut r = BitReader::new(payload);
+        let mut pt = PicTiming::default();
+        let vui = sps.vui.as_ref().ok_or("picture timing without VUI")?;
+        let hrd = vui.nal_hrd.as_ref().or(vui.vcl_hrd.as_ref());
+        if let Some(hrd) = hrd {
+            pt.cpb_removal_delay = Some(r.bits(hrd.cpb_removal_delay_length)?);
+            pt.dpb_output_delay = Some(r.bits(hrd.dpb_output_delay_length)?);
+        }
+        if !vui.pic_struct_present_flag {
+            return Ok(pt);
+        }
+        let pic_struct = r.bits(4)?;
+        pt.pic_struct = Some(pic_struct);
+        let num_clock_ts = match pic_struct {
+            0..=2 => 1,
+            3 | 4 | 7 => 2,
+            5 | 6 | 8 => 3,
+            _ => return Err("reserved pic_struct"),
+        };
+        for _ in 0..num_clock_ts {
+            if !r.bit()? {
+                continue;
+            }
+            // ct_type, nuit_field_based_flag, counting_type
+            r.skip(8)?;
+            let full_timestamp_flag = r.bit()?;
+            // discontinuity_flag, cnt_dropped_flag
+            r.skip(2)?;
+            let n_frames = r.bits(8)?;
+            let (mut hours, mut minutes, mut seconds) = (0, 0, 0);
+            if full_timestamp_flag {
+                seconds = r.bits(6)?;
+                minutes = r.bits(6)?;
+                hours = r.bits(5)?;
+            } else if r.bit()? {
+                seconds = r.bits(6)?;
+                if r.bit()? {
+                    minutes = r.bits(6)?;
+                    if r.bit()? {
+                        hours = r.bits(5)?;
+                    }
+                }
+            }
+            if let Some(hrd) = hrd {
+                r.skip(hrd.time_offset_length as usize)?; // time_offset
+            }
+            pt.clock_timestamps.push((hours, minutes, seconds, n_frames));
+        }
+        Ok(pt)
+    }
+}
+
+impl fmt::Display for PicTiming {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let (Some(cpb), Some(dpb)) = (self.cpb_removal_delay, self.dpb_output_delay) {
+            parts.push(format!("cpb removal delay {}, dpb output delay {}", cpb, dpb));
+        }
+        if let Some(pic_struct) = self.pic_struct {
+            parts.push(format!("pic_struct {}", pic_struct));
+        }
+        for &(hours, minutes, seconds, n_frames) in self.clock_timestamps.iter() {
+            parts.push(format!("{:02}:{:02}:{:02}:{:02}", hours, minutes, seconds, n_frames));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+// one line description of an SEI message, given the parameter sets and the
+// SPS active for the access unit
+pub fn describe_sei(message: &SeiMessage,
+                    ps: &ParameterSets,
+                    sps: Option<&Sps>)
+                    -> Result<String, &'static str> {
+    let payload = &message.payload;
+    Ok(match message.payload_type {
+        sei::BUFFERING_PERIOD => BufferingPeriod::parse(payload, &ps.sps)?.to_string(),
+        sei::PIC_TIMING => {
+            PicTiming::parse(payload, sps.ok_or("picture timing without an active SPS")?)?
+                .to_string()
+        }
+        sei::USER_DATA_UNREGISTERED => UserDataUnregistered::parse(payload)?.to_string(),
+        sei::RECOVERY_POINT => RecoveryPoint::parse(payload)?.to_string(),
+        sei::MASTERING_DISPLAY_COLOUR_VOLUME => {
+            MasteringDisplayColourVolume::parse(payload)?.to_string()
+        }
+        sei::CONTENT_LIGHT_LEVEL_INFO => ContentLightLevel::parse(payload)?.to_string(),
+        _ => String::new(),
+    })
+}
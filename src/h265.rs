@@ -0,0 +1,1443 @@
+// H.265 parameter sets, slice segment headers and SEI messages, parsed from
+// the RBSP of the NAL units found by the bin mode scanner.
+
+use std::collections::HashMap;
+use std::fmt;
+use super::bits::{unescape, BitReader};
+use super::md5::Md5;
+use super::sei;
+use super::sei::{ContentLightLevel, MasteringDisplayColourVolume, SeiMessage,
+                 UserDataUnregistered};
+use super::vui;
+use super::vui::VideoSignal;
+
+pub const NAL_RADL_N: u8 = 6;
+pub const NAL_RASL_N: u8 = 8;
+pub const NAL_RASL_R: u8 = 9;
+pub const NAL_BLA_W_LP: u8 = 16;
+pub const NAL_IDR_W_RADL: u8 = 19;
+pub const NAL_IDR_N_LP: u8 = 20;
+pub const NAL_CRA: u8 = 21;
+pub const NAL_VPS: u8 = 32;
+pub const NAL_SPS: u8 = 33;
+pub const NAL_PPS: u8 = 34;
+pub const NAL_EOS: u8 = 36;
+pub const NAL_PREFIX_SEI: u8 = 39;
+pub const NAL_SUFFIX_SEI: u8 = 40;
+
+pub fn nal_unit_type(nal: &[u8]) -> u8 {
+    nal.first().map_or(0xff, |b| (b & 0x7e) >> 1)
+}
+
+pub fn nuh_layer_id(nal: &[u8]) -> u8 {
+    if nal.len() < 2 {
+        return 0;
+    }
+    ((nal[0] & 0x1) << 5) | (nal[1] >> 3)
+}
+
+pub fn temporal_id(nal: &[u8]) -> u8 {
+    nal.get(1).map_or(0, |b| (b & 0x7).saturating_sub(1))
+}
+
+pub fn nal_unit_type_name(nal_unit_type: u8) -> &'static str {
+    match nal_unit_type {
+        0 => "TRAIL_N",
+        1 => "TRAIL_R",
+        2 => "TSA_N",
+        3 => "TSA_R",
+        4 => "STSA_N",
+        5 => "STSA_R",
+        6 => "RADL_N",
+        7 => "RADL_R",
+        8 => "RASL_N",
+        9 => "RASL_R",
+        16 => "BLA_W_LP",
+        17 => "BLA_W_RADL",
+        18 => "BLA_N_LP",
+        19 => "IDR_W_RADL",
+        20 => "IDR_N_LP",
+        21 => "CRA",
+        32 => "VPS",
+        33 => "SPS",
+        34 => "PPS",
+        35 => "AUD",
+        36 => "EOS",
+        37 => "EOB",
+        38 => "FD",
+        39 => "SEI_PREFIX",
+        40 => "SEI_SUFFIX",
+        _ => "RSV",
+    }
+}
+
+pub fn slice_type_name(slice_type: u32) -> &'static str {
+    match slice_type {
+        0 => "B",
+        1 => "P",
+        2 => "I",
+        _ => "?",
+    }
+}
+
+// IRAP pictures are BLA, IDR and CRA, types 16 to 23
+pub fn is_irap(nal_unit_type: u8) -> bool {
+    (NAL_BLA_W_LP..=23).contains(&nal_unit_type)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    pub profile_space: u32,
+    pub tier_flag: bool,
+    pub profile_idc: u32,
+    pub profile_compatibility_flags: u32,
+    pub progressive_source_flag: bool,
+    pub interlaced_source_flag: bool,
+    pub non_packed_constraint_flag: bool,
+    pub frame_only_constraint_flag: bool,
+    // the 43 constraint bits and the inbld or reserved bit after them
+    pub constraint_flags: u64,
+}
+
+impl Profile {
+    fn parse(r: &mut BitReader) -> Result<Profile, &'static str> {
+        Ok(Profile {
+            profile_space: r.bits(2)?,
+            tier_flag: r.bit()?,
+            profile_idc: r.bits(5)?,
+            profile_compatibility_flags: r.bits(32)?,
+            progressive_source_flag: r.bit()?,
+            interlaced_source_flag: r.bit()?,
+            non_packed_constraint_flag: r.bit()?,
+            frame_only_constraint_flag: r.bit()?,
+            constraint_flags: ((r.bits(32)? as u64) << 12) | r.bits(12)? as u64,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        // a profile_idc of 0 is resolved through the compatibility flags
+        let mut profile_idc = self.profile_idc;
+        if profile_idc == 0 {
+            profile_idc = (1..32)
+                .find(|j| self.profile_compatibility_flags & (1 << (31 - j)) != 0)
+                .unwrap_or(0);
+        }
+        match profile_idc {
+            1 => "Main",
+            2 => "Main 10",
+            3 => "Main Still Picture",
+            4 => "Range Extensions",
+            5 => "High Throughput",
+            6 => "Multiview Main",
+            7 => "Scalable Main",
+            8 => "3D Main",
+            9 => "Screen Content",
+            10 => "Scalable Range Extensions",
+            11 => "High Throughput Screen Content",
+            _ => "unknown",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ProfileTierLevel {
+    pub general_profile: Profile,
+    pub general_level_idc: u32,
+    // sub_layer_profile and sub_layer_level_idc when present
+    pub sub_layers: Vec<(Option<Profile>, Option<u32>)>,
+}
+
+impl ProfileTierLevel {
+    fn parse(r: &mut BitReader, max_sub_layers: u32) -> Result<ProfileTierLevel, &'static str> {
+        let mut ptl = ProfileTierLevel {
+            general_profile: Profile::parse(r)?,
+            general_level_idc: r.bits(8)?,
+            sub_layers: Vec::new(),
+        };
+        let mut present = Vec::new();
+        for _ in 1..max_sub_layers {
+            present.push((r.bit()?, r.bit()?));
+        }
+        if max_sub_layers > 1 {
+            for _ in max_sub_layers - 1..8 {
+                r.skip(2)?; // reserved_zero_2bits
+            }
+        }
+        for (profile_present, level_present) in present {
+            let profile = if profile_present {
+                Some(Profile::parse(r)?)
+            } else {
+                None
+            };
+            let level = if level_present { Some(r.bits(8)?) } else { None };
+            ptl.sub_layers.push((profile, level));
+        }
+        Ok(ptl)
+    }
+
+    pub fn describe(&self) -> String {
+        let level = self.general_level_idc;
+        format!("{} profile, {} tier, level {}{}",
+                self.general_profile.name(),
+                if self.general_profile.tier_flag { "High" } else { "Main" },
+                level / 30,
+                if !level.is_multiple_of(30) {
+                    format!(".{}", level % 30 / 3)
+                } else {
+                    String::new()
+                })
+    }
+}
+
+// sps_max_dec_pic_buffering_minus1 + 1, sps_max_num_reorder_pics and
+// sps_max_latency_increase_plus1 of each sub-layer
+pub type SubLayerOrdering = (u32, u32, u32);
+
+fn sub_layer_ordering_info(r: &mut BitReader,
+                           max_sub_layers: u32)
+                           -> Result<Vec<SubLayerOrdering>, &'static str> {
+    let present_flag = r.bit()?;
+    let first = if present_flag { 0 } else { max_sub_layers - 1 };
+    let mut info = Vec::new();
+    for _ in first..max_sub_layers {
+        info.push((r.ue()? + 1, r.ue()?, r.ue()?));
+    }
+    // without the flag the values of the highest sub-layer hold for all
+    while info.len() < max_sub_layers as usize {
+        let highest = info[info.len() - 1];
+        info.insert(0, highest);
+    }
+    Ok(info)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Vps {
+    pub vps_video_parameter_set_id: u32,
+    pub vps_base_layer_internal_flag: bool,
+    pub vps_base_layer_available_flag: bool,
+    pub vps_max_layers: u32,
+    pub vps_max_sub_layers: u32,
+    pub vps_temporal_id_nesting_flag: bool,
+    pub profile_tier_level: ProfileTierLevel,
+    pub sub_layer_ordering: Vec<SubLayerOrdering>,
+    pub vps_max_layer_id: u32,
+    // layer_id_included_flag of the layer sets after the first
+    pub layer_sets: Vec<Vec<bool>>,
+    pub timing_info: Option<TimingInfo>,
+    // hrd_layer_set_idx and the HRD parameters
+    pub hrd_parameters: Vec<(u32, Hrd)>,
+    pub vps_extension_flag: bool,
+}
+
+impl Vps {
+    pub fn parse(rbsp: &[u8]) -> Result<Vps, &'static str> {
+        let mut r = BitReader::new(rbsp);
+        let mut vps = Vps {
+            vps_video_parameter_set_id: r.bits(4)?,
+            vps_base_layer_internal_flag: r.bit()?,
+            vps_base_layer_available_flag: r.bit()?,
+            vps_max_layers: r.bits(6)? + 1,
+            vps_max_sub_layers: r.bits(3)? + 1,
+            vps_temporal_id_nesting_flag: r.bit()?,
+            ..Vps::default()
+        };
+        r.skip(16)?; // vps_reserved_0xffff_16bits
+        vps.profile_tier_level = ProfileTierLevel::parse(&mut r, vps.vps_max_sub_layers)?;
+        vps.sub_layer_ordering = sub_layer_ordering_info(&mut r, vps.vps_max_sub_layers)?;
+        vps.vps_max_layer_id = r.bits(6)?;
+        let num_layer_sets = r.ue()? + 1;
+        for _ in 1..num_layer_sets {
+            let mut included = Vec::new();
+            for _ in 0..=vps.vps_max_layer_id {
+                included.push(r.bit()?);
+            }
+            vps.layer_sets.push(included);
+        }
+        if r.bit()? {
+            let timing_info = TimingInfo::parse(&mut r)?;
+            vps.timing_info = Some(timing_info);
+            let vps_num_hrd_parameters = r.ue()?;
+            for i in 0..vps_num_hrd_parameters {
+                let hrd_layer_set_idx = r.ue()?;
+                let cprms_present_flag = i == 0 || r.bit()?;
+                let hrd = Hrd::parse(&mut r, cprms_present_flag, vps.vps_max_sub_layers)?;
+                vps.hrd_parameters.push((hrd_layer_set_idx, hrd));
+            }
+        }
+        vps.vps_extension_flag = r.bit()?;
+        Ok(vps)
+    }
+}
+
+impl fmt::Display for Vps {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f,
+                 "VPS {}: {}, {} layer{}, {} sub-layer{}, {} layer sets",
+                 self.vps_video_parameter_set_id,
+                 self.profile_tier_level.describe(),
+                 self.vps_max_layers,
+                 if self.vps_max_layers > 1 { "s" } else { "" },
+                 self.vps_max_sub_layers,
+                 if self.vps_max_sub_layers > 1 { "s" } else { "" },
+                 self.layer_sets.len() + 1)?;
+        if let Some(ref timing_info) = self.timing_info {
+            writeln!(f, "  {:.3} fps", timing_info.frame_rate())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TimingInfo {
+    pub num_units_in_tick: u32,
+    pub time_scale: u32,
+    pub num_ticks_poc_diff_one: Option<u32>,
+}
+
+impl TimingInfo {
+    fn parse(r: &mut BitReader) -> Result<TimingInfo, &'static str> {
+        let num_units_in_tick = r.bits(32)?;
+        let time_scale = r.bits(32)?;
+        let num_ticks_poc_diff_one = if r.bit()? { Some(r.ue()? + 1) } else { None };
+        Ok(TimingInfo {
+            num_units_in_tick,
+            time_scale,
+            num_ticks_poc_diff_one,
+        })
+    }
+
+    pub fn frame_rate(&self) -> f64 {
+        if self.num_units_in_tick == 0 {
+            return 0.0;
+        }
+        self.time_scale as f64 / self.num_units_in_tick as f64
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Hrd {
+    pub nal_hrd_parameters_present_flag: bool,
+    pub vcl_hrd_parameters_present_flag: bool,
+    pub sub_pic_hrd_params_present_flag: bool,
+    pub tick_divisor: u32,
+    pub du_cpb_removal_delay_increment_length: u32,
+    pub sub_pic_cpb_params_in_pic_timing_sei_flag: bool,
+    pub dpb_output_delay_du_length: u32,
+    pub bit_rate_scale: u32,
+    pub cpb_size_scale: u32,
+    pub cpb_size_du_scale: u32,
+    pub initial_cpb_removal_delay_length: u32,
+    pub au_cpb_removal_delay_length: u32,
+    pub dpb_output_delay_length: u32,
+    pub sub_layers: Vec<SubLayerHrd>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SubLayerHrd {
+    pub fixed_pic_rate_general_flag: bool,
+    pub fixed_pic_rate_within_cvs_flag: bool,
+    pub elemental_duration_in_tc: u32,
+    pub low_delay_hrd_flag: bool,
+    // BitRate, CpbSize and cbr_flag of each schedule
+    pub nal_schedules: Vec<(u64, u64, bool)>,
+    pub vcl_schedules: Vec<(u64, u64, bool)>,
+}
+
+impl Hrd {
+    fn parse(r: &mut BitReader,
+             common_inf_present_flag: bool,
+             max_sub_layers: u32)
+             -> Result<Hrd, &'static str> {
+        let mut hrd = Hrd {
+            // lengths of 23 + 1 when they aren't sent
+            initial_cpb_removal_delay_length: 24,
+            au_cpb_removal_delay_length: 24,
+            dpb_output_delay_length: 24,
+            ..Hrd::default()
+        };
+        if common_inf_present_flag {
+            hrd.nal_hrd_parameters_present_flag = r.bit()?;
+            hrd.vcl_hrd_parameters_present_flag = r.bit()?;
+            if hrd.nal_hrd_parameters_present_flag || hrd.vcl_hrd_parameters_present_flag {
+                hrd.sub_pic_hrd_params_present_flag = r.bit()?;
+                if hrd.sub_pic_hrd_params_present_flag {
+                    hrd.tick_divisor = r.bits(8)? + 2;
+                    hrd.du_cpb_removal_delay_increment_length = r.bits(5)? + 1;
+                    hrd.sub_pic_cpb_params_in_pic_timing_sei_flag = r.bit()?;
+                    hrd.dpb_output_delay_du_length = r.bits(5)? + 1;
+                }
+                hrd.bit_rate_scale = r.bits(4)?;
+                hrd.cpb_size_scale = r.bits(4)?;
+                if hrd.sub_pic_hrd_params_present_flag {
+                    hrd.cpb_size_du_scale = r.bits(4)?;
+                }
+                hrd.initial_cpb_removal_delay_length = r.bits(5)? + 1;
+                hrd.au_cpb_removal_delay_length = r.bits(5)? + 1;
+                hrd.dpb_output_delay_length = r.bits(5)? + 1;
+            }
+        }
+        for _ in 0..max_sub_layers {
+            let mut sub_layer = SubLayerHrd {
+                fixed_pic_rate_general_flag: r.bit()?,
+                ..SubLayerHrd::default()
+            };
+            sub_layer.fixed_pic_rate_within_cvs_flag = sub_layer.fixed_pic_rate_general_flag ||
+                                                       r.bit()?;
+            if sub_layer.fixed_pic_rate_within_cvs_flag {
+                sub_layer.elemental_duration_in_tc = r.ue()? + 1;
+            } else {
+                sub_layer.low_delay_hrd_flag = r.bit()?;
+            }
+            let cpb_cnt = if sub_layer.low_delay_hrd_flag { 1 } else { r.ue()? + 1 };
+            if hrd.nal_hrd_parameters_present_flag {
+                sub_layer.nal_schedules = hrd.sub_layer_schedules(r, cpb_cnt)?;
+            }
+            if hrd.vcl_hrd_parameters_present_flag {
+                sub_layer.vcl_schedules = hrd.sub_layer_schedules(r, cpb_cnt)?;
+            }
+            hrd.sub_layers.push(sub_layer);
+        }
+        Ok(hrd)
+    }
+
+    // sub_layer_hrd_parameters()
+    fn sub_layer_schedules(&self,
+                           r: &mut BitReader,
+                           cpb_cnt: u32)
+                           -> Result<Vec<(u64, u64, bool)>, &'static str> {
+        let mut schedules = Vec::new();
+        for _ in 0..cpb_cnt {
+            let bit_rate = (r.ue()? as u64 + 1) << (6 + self.bit_rate_scale);
+            let cpb_size = (r.ue()? as u64 + 1) << (4 + self.cpb_size_scale);
+            if self.sub_pic_hrd_params_present_flag {
+                r.ue()?; // cpb_size_du_value_minus1
+                r.ue()?; // bit_rate_du_value_minus1
+            }
+            schedules.push((bit_rate, cpb_size, r.bit()?));
+        }
+        Ok(schedules)
+    }
+}
+
+// a short-term reference picture set, as the POC deltas it ends up with
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StRefPicSet {
+    // DeltaPocS0 and UsedByCurrPicS0, nearest first
+    pub negative: Vec<(i32, bool)>,
+    // DeltaPocS1 and UsedByCurrPicS1, nearest first
+    pub positive: Vec<(i32, bool)>,
+}
+
+impl StRefPicSet {
+    // st_ref_pic_set(stRpsIdx), stRpsIdx being sets.len() with the sets of
+    // the SPS before it, or num_short_term_ref_pic_sets in a slice header
+    pub fn parse(r: &mut BitReader,
+                 sets: &[StRefPicSet],
+                 slice_header: bool)
+                 -> Result<StRefPicSet, &'static str> {
+        let idx = sets.len();
+        let inter_ref_pic_set_prediction_flag = idx != 0 && r.bit()?;
+        let mut rps = StRefPicSet::default();
+        if !inter_ref_pic_set_prediction_flag {
+            let num_negative_pics = r.ue()?;
+            let num_positive_pics = r.ue()?;
+            if num_negative_pics > 16 || num_positive_pics > 16 {
+                return Err("too many pictures in short-term reference picture set");
+            }
+            let mut poc = 0;
+            for _ in 0..num_negative_pics {
+                poc -= r.ue()? as i32 + 1;
+                rps.negative.push((poc, r.bit()?));
+            }
+            poc = 0;
+            for _ in 0..num_positive_pics {
+                poc += r.ue()? as i32 + 1;
+                rps.positive.push((poc, r.bit()?));
+            }
+            return Ok(rps);
+        }
+
+        let delta_idx = if slice_header { r.ue()? as usize + 1 } else { 1 };
+        let reference = sets.get(idx.wrapping_sub(delta_idx))
+            .ok_or("reference picture set predicted from a missing set")?;
+        let delta_rps_sign = r.bit()?;
+        let abs_delta_rps = r.ue()? as i32 + 1;
+        let delta_rps = if delta_rps_sign { -abs_delta_rps } else { abs_delta_rps };
+        // used_by_curr_pic_flag and use_delta_flag for the negative, then the
+        // positive pictures of the reference set and last the set itself
+        let num_delta_pocs = reference.negative.len() + reference.positive.len();
+        let mut flags = Vec::with_capacity(num_delta_pocs + 1);
+        for _ in 0..=num_delta_pocs {
+            let used = r.bit()?;
+            let use_delta = used || r.bit()?;
+            flags.push((used, use_delta));
+        }
+        let negatives = reference.negative.len();
+        let (used, use_delta) = flags[num_delta_pocs];
+
+        for (j, &(delta, _)) in reference.positive.iter().enumerate().rev() {
+            let d_poc = delta + delta_rps;
+            if d_poc < 0 && flags[negatives + j].1 {
+                rps.negative.push((d_poc, flags[negatives + j].0));
+            }
+        }
+        if delta_rps < 0 && use_delta {
+            rps.negative.push((delta_rps, used));
+        }
+        for (j, &(delta, _)) in reference.negative.iter().enumerate() {
+            let d_poc = delta + delta_rps;
+            if d_poc < 0 && flags[j].1 {
+                rps.negative.push((d_poc, flags[j].0));
+            }
+        }
+
+        for (j, &(delta, _)) in reference.negative.iter().enumerate().rev() {
+            let d_poc = delta + delta_rps;
+            if d_poc > 0 && flags[j].1 {
+                rps.positive.push((d_poc, flags[j].0));
+            }
+        }
+        if delta_rps > 0 && use_delta {
+            rps.positive.push((delta_rps, used));
+        }
+        for (j, &(delta, _)) in reference.positive.iter().enumerate() {
+            let d_poc = delta + delta_rps;
+            if d_poc > 0 && flags[negatives + j].1 {
+                rps.positive.push((d_poc, flags[negatives + j].0));
+            }
+        }
+        Ok(rps)
+    }
+}
+
+// one entry of scaling_list_data() in sizeId, matrixId order
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScalingList {
+    // copied from the list scaling_list_pred_matrix_id_delta before, or the
+    // default list for a delta of 0
+    Predicted(u32),
+    // scaling_list_dc_coef for 16x16 and 32x32, and the coefficients
+    Explicit(Option<i32>, Vec<u32>),
+}
+
+fn scaling_list_data(r: &mut BitReader) -> Result<Vec<ScalingList>, &'static str> {
+    let mut lists = Vec::new();
+    for size_id in 0..4 {
+        let step = if size_id == 3 { 3 } else { 1 };
+        for _ in (0..6).step_by(step) {
+            if !r.bit()? {
+                lists.push(ScalingList::Predicted(r.ue()?));
+                continue;
+            }
+            let coef_num = 64.min(1 << (4 + (size_id << 1)));
+            let mut next_coef = 8;
+            let mut dc_coef = None;
+            if size_id > 1 {
+                next_coef = r.se()? + 8;
+                dc_coef = Some(next_coef);
+            }
+            let mut coefficients = Vec::with_capacity(coef_num);
+            for _ in 0..coef_num {
+                next_coef = (next_coef + r.se()? + 256) % 256;
+                coefficients.push(next_coef as u32);
+            }
+            lists.push(ScalingList::Explicit(dc_coef, coefficients));
+        }
+    }
+    Ok(lists)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Sps {
+    pub sps_video_parameter_set_id: u32,
+    pub sps_max_sub_layers: u32,
+    pub sps_temporal_id_nesting_flag: bool,
+    pub profile_tier_level: ProfileTierLevel,
+    pub sps_seq_parameter_set_id: u32,
+    pub chroma_format_idc: u32,
+    pub separate_colour_plane_flag: bool,
+    pub pic_width_in_luma_samples: u32,
+    pub pic_height_in_luma_samples: u32,
+    // left, right, top and bottom in chroma samples
+    pub conformance_window: (u32, u32, u32, u32),
+    pub bit_depth_luma: u32,
+    pub bit_depth_chroma: u32,
+    pub log2_max_pic_order_cnt_lsb: u32,
+    pub sub_layer_ordering: Vec<SubLayerOrdering>,
+    pub log2_min_luma_coding_block_size: u32,
+    pub log2_ctb_size: u32,
+    pub log2_min_luma_transform_block_size: u32,
+    pub log2_max_luma_transform_block_size: u32,
+    pub max_transform_hierarchy_depth_inter: u32,
+    pub max_transform_hierarchy_depth_intra: u32,
+    pub scaling_list_enabled_flag: bool,
+    // empty when the default lists are used
+    pub sps_scaling_lists: Vec<ScalingList>,
+    pub amp_enabled_flag: bool,
+    pub sample_adaptive_offset_enabled_flag: bool,
+    // PCM luma and chroma bit depths, log2 of the min and max coding block
+    // size and pcm_loop_filter_disabled_flag
+    pub pcm: Option<(u32, u32, u32, u32, bool)>,
+    pub short_term_ref_pic_sets: Vec<StRefPicSet>,
+    pub long_term_ref_pics_present_flag: bool,
+    // lt_ref_pic_poc_lsb_sps and used_by_curr_pic_lt_sps_flag
+    pub long_term_ref_pics: Vec<(u32, bool)>,
+    pub sps_temporal_mvp_enabled_flag: bool,
+    pub strong_intra_smoothing_enabled_flag: bool,
+    pub vui: Option<Vui>,
+    // sps_range_extension_flag, sps_multilayer_extension_flag,
+    // sps_3d_extension_flag, sps_scc_extension_flag and sps_extension_4bits
+    pub sps_extension_flags: u32,
+}
+
+impl Sps {
+    pub fn parse(rbsp: &[u8]) -> Result<Sps, &'static str> {
+        let mut r = BitReader::new(rbsp);
+        let mut sps = Sps {
+            sps_video_parameter_set_id: r.bits(4)?,
+            sps_max_sub_layers: r.bits(3)? + 1,
+            sps_temporal_id_nesting_flag: r.bit()?,
+            ..Sps::default()
+        };
+        sps.profile_tier_level = ProfileTierLevel::parse(&mut r, sps.sps_max_sub_layers)?;
+        sps.sps_seq_parameter_set_id = r.ue()?;
+        sps.chroma_format_idc = r.ue()?;
+        if sps.chroma_format_idc == 3 {
+            sps.separate_colour_plane_flag = r.bit()?;
+        }
+        sps.pic_width_in_luma_samples = r.ue()?;
+        sps.pic_height_in_luma_samples = r.ue()?;
+        if r.bit()? {
+            sps.conformance_window = (r.ue()?, r.ue()?, r.ue()?, r.ue()?);
+        }
+        sps.bit_depth_luma = r.ue()? + 8;
+        sps.bit_depth_chroma = r.ue()? + 8;
+        sps.log2_max_pic_order_cnt_lsb = r.ue()? + 4;
+        sps.sub_layer_ordering = sub_layer_ordering_info(&mut r, sps.sps_max_sub_layers)?;
+        sps.log2_min_luma_coding_block_size = r.ue()? + 3;
+        sps.log2_ctb_size = sps.log2_min_luma_coding_block_size + r.ue()?;
+        sps.log2_min_luma_transform_block_size = r.ue()? + 2;
+        sps.log2_max_luma_transform_block_size = sps.log2_min_luma_transform_block_size +
+                                                 r.ue()?;
+        sps.max_transform_hierarchy_depth_inter = r.ue()?;
+        sps.max_transform_hierarchy_depth_intra = r.ue()?;
+        sps.scaling_list_enabled_flag = r.bit()?;
+        if sps.scaling_list_enabled_flag && r.bit()? {
+            sps.sps_scaling_lists = scaling_list_data(&mut r)?;
+        }
+        sps.amp_enabled_flag = r.bit()?;
+        sps.sample_adaptive_offset_enabled_flag = r.bit()?;
+        if r.bit()? {
+            let bit_depth_luma = r.bits(4)? + 1;
+            let bit_depth_chroma = r.bits(4)? + 1;
+            let log2_min_size = r.ue()? + 3;
+            let log2_max_size = log2_min_size + r.ue()?;
+            sps.pcm = Some((bit_depth_luma, bit_depth_chroma, log2_min_size, log2_max_size,
+                            r.bit()?));
+        }
+        let num_short_term_ref_pic_sets = r.ue()? as usize;
+        if num_short_term_ref_pic_sets > 64 {
+            return Err("too many short-term reference picture sets");
+        }
+        for _ in 0..num_short_term_ref_pic_sets {
+            let rps = StRefPicSet::parse(&mut r, &sps.short_term_ref_pic_sets, false)?;
+            sps.short_term_ref_pic_sets.push(rps);
+        }
+        sps.long_term_ref_pics_present_flag = r.bit()?;
+        if sps.long_term_ref_pics_present_flag {
+            for _ in 0..r.ue()? {
+                let lsb = r.bits(sps.log2_max_pic_order_cnt_lsb)?;
+                sps.long_term_ref_pics.push((lsb, r.bit()?));
+            }
+        }
+        sps.sps_temporal_mvp_enabled_flag = r.bit()?;
+        sps.strong_intra_smoothing_enabled_flag = r.bit()?;
+        if r.bit()? {
+            sps.vui = Some(Vui::parse(&mut r, sps.sps_max_sub_layers)?);
+        }
+        if r.bit()? {
+            sps.sps_extension_flags = r.bits(8)?;
+        }
+        Ok(sps)
+    }
+
+    // decoded picture size, inside the conformance window
+    pub fn size(&self) -> (u32, u32) {
+        let (sub_width, sub_height) = match self.chroma_format_idc {
+            _ if self.separate_colour_plane_flag => (1, 1),
+            1 => (2, 2),
+            2 => (2, 1),
+            _ => (1, 1),
+        };
+        let (left, right, top, bottom) = self.conformance_window;
+        (self.pic_width_in_luma_samples.saturating_sub(sub_width * (left + right)),
+         self.pic_height_in_luma_samples.saturating_sub(sub_height * (top + bottom)))
+    }
+
+    pub fn pic_size_in_ctbs(&self) -> u32 {
+        let ctb = 1 << self.log2_ctb_size;
+        self.pic_width_in_luma_samples.div_ceil(ctb) * self.pic_height_in_luma_samples.div_ceil(ctb)
+    }
+}
+
+impl fmt::Display for Sps {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (width, height) = self.size();
+        writeln!(f,
+                 "SPS {} (VPS {}): {}, {}x{} ({}x{} coded), {} {} bit{}",
+                 self.sps_seq_parameter_set_id,
+                 self.sps_video_parameter_set_id,
+                 self.profile_tier_level.describe(),
+                 width,
+                 height,
+                 self.pic_width_in_luma_samples,
+                 self.pic_height_in_luma_samples,
+                 vui::chroma_format_name(self.chroma_format_idc),
+                 self.bit_depth_luma,
+                 if self.bit_depth_chroma != self.bit_depth_luma {
+                     format!(" ({} bit chroma)", self.bit_depth_chroma)
+                 } else {
+                     String::new()
+                 })?;
+        let highest = self.sub_layer_ordering.last().cloned().unwrap_or_default();
+        writeln!(f,
+                 "  ctb {}, {} sub-layer{}, {} picture buffers, {} reorder, poc lsb {} bits, \
+                  {} short-term rps",
+                 1 << self.log2_ctb_size,
+                 self.sps_max_sub_layers,
+                 if self.sps_max_sub_layers > 1 { "s" } else { "" },
+                 highest.0,
+                 highest.1,
+                 self.log2_max_pic_order_cnt_lsb,
+                 self.short_term_ref_pic_sets.len())?;
+        let tools = [(self.amp_enabled_flag, "amp"),
+                     (self.sample_adaptive_offset_enabled_flag, "sao"),
+                     (self.pcm.is_some(), "pcm"),
+                     (self.scaling_list_enabled_flag, "scaling lists"),
+                     (self.long_term_ref_pics_present_flag, "long-term refs"),
+                     (self.sps_temporal_mvp_enabled_flag, "tmvp"),
+                     (self.strong_intra_smoothing_enabled_flag, "strong intra smoothing")];
+        let tools: Vec<&str> = tools.iter().filter(|t| t.0).map(|t| t.1).collect();
+        if !tools.is_empty() {
+            writeln!(f, "  {}", tools.join(", "))?;
+        }
+        if self.conformance_window != (0, 0, 0, 0) {
+            let (left, right, top, bottom) = self.conformance_window;
+            writeln!(f,
+                     "  conformance window left {} right {} top {} bottom {}",
+                     left,
+                     right,
+                     top,
+                     bottom)?;
+        }
+        if let Some(ref vui) = self.vui {
+            write!(f, "{}", vui)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Vui {
+    pub aspect_ratio_idc: Option<u32>,
+    // sample aspect ratio, (0, 0) when unspecified
+    pub sar: (u32, u32),
+    pub overscan_appropriate_flag: Option<bool>,
+    pub video_signal: Option<VideoSignal>,
+    // chroma_sample_loc_type_top_field, chroma_sample_loc_type_bottom_field
+    pub chroma_sample_loc_type: Option<(u32, u32)>,
+    pub neutral_chroma_indication_flag: bool,
+    pub field_seq_flag: bool,
+    pub frame_field_info_present_flag: bool,
+    // left, right, top and bottom offsets
+    pub default_display_window: Option<(u32, u32, u32, u32)>,
+    pub timing_info: Option<TimingInfo>,
+    pub hrd: Option<Hrd>,
+    pub bitstream_restriction: Option<BitstreamRestriction>,
+}
+
+impl Vui {
+    fn parse(r: &mut BitReader, max_sub_layers: u32) -> Result<Vui, &'static str> {
+        let mut vui = Vui::default();
+        if r.bit()? {
+            let (aspect_ratio_idc, sar) = vui::aspect_ratio(r)?;
+            vui.aspect_ratio_idc = Some(aspect_ratio_idc);
+            vui.sar = sar;
+        }
+        if r.bit()? {
+            vui.overscan_appropriate_flag = Some(r.bit()?);
+        }
+        if r.bit()? {
+            vui.video_signal = Some(VideoSignal::parse(r)?);
+        }
+        if r.bit()? {
+            vui.chroma_sample_loc_type = Some((r.ue()?, r.ue()?));
+        }
+        vui.neutral_chroma_indication_flag = r.bit()?;
+        vui.field_seq_flag = r.bit()?;
+        vui.frame_field_info_present_flag = r.bit()?;
+        if r.bit()? {
+            vui.default_display_window = Some((r.ue()?, r.ue()?, r.ue()?, r.ue()?));
+        }
+        if r.bit()? {
+            vui.timing_info = Some(TimingInfo::parse(r)?);
+            if r.bit()? {
+                vui.hrd = Some(Hrd::parse(r, true, max_sub_layers)?);
+            }
+        }
+        if r.bit()? {
+            vui.bitstream_restriction = Some(BitstreamRestriction {
+                tiles_fixed_structure_flag: r.bit()?,
+                motion_vectors_over_pic_boundaries_flag: r.bit()?,
+                restricted_ref_pic_lists_flag: r.bit()?,
+                min_spatial_segmentation_idc: r.ue()?,
+                max_bytes_per_pic_denom: r.ue()?,
+                max_bits_per_min_cu_denom: r.ue()?,
+                log2_max_mv_length_horizontal: r.ue()?,
+                log2_max_mv_length_vertical: r.ue()?,
+            });
+        }
+        Ok(vui)
+    }
+}
+
+impl fmt::Display for Vui {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.aspect_ratio_idc.is_some() {
+            writeln!(f, "  sample aspect ratio {}:{}", self.sar.0, self.sar.1)?;
+        }
+        if let Some(ref video_signal) = self.video_signal {
+            writeln!(f, "  {}", video_signal.describe())?;
+        }
+        if self.field_seq_flag {
+            writeln!(f, "  field coded")?;
+        }
+        if let Some((left, right, top, bottom)) = self.default_display_window {
+            writeln!(f,
+                     "  display window left {} right {} top {} bottom {}",
+                     left,
+                     right,
+                     top,
+                     bottom)?;
+        }
+        if let Some(ref timing_info) = self.timing_info {
+            writeln!(f, "  {:.3} fps", timing_info.frame_rate())?;
+        }
+        if let Some(ref hrd) = self.hrd {
+            if let Some(sub_layer) = hrd.sub_layers.last() {
+                let schedules = [("NAL", &sub_layer.nal_schedules),
+                                 ("VCL", &sub_layer.vcl_schedules)];
+                for &(name, schedules) in schedules.iter() {
+                    for &(bit_rate, cpb_size, cbr) in schedules.iter() {
+                        writeln!(f,
+                                 "  {} HRD {} bit/s, cpb {} bits{}",
+                                 name,
+                                 bit_rate,
+                                 cpb_size,
+                                 if cbr { ", cbr" } else { "" })?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BitstreamRestriction {
+    pub tiles_fixed_structure_flag: bool,
+    pub motion_vectors_over_pic_boundaries_flag: bool,
+    pub restricted_ref_pic_lists_flag: bool,
+    pub min_spatial_segmentation_idc: u32,
+    pub max_bytes_per_pic_denom: u32,
+    pub max_bits_per_min_cu_denom: u32,
+    pub log2_max_mv_length_horizontal: u32,
+    pub log2_max_mv_length_vertical: u32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Pps {
+    pub pps_pic_parameter_set_id: u32,
+    pub pps_seq_parameter_set_id: u32,
+    pub dependent_slice_segments_enabled_flag: bool,
+    pub output_flag_present_flag: bool,
+    pub num_extra_slice_header_bits: u32,
+    pub sign_data_hiding_enabled_flag: bool,
+    pub cabac_init_present_flag: bool,
+    pub num_ref_idx_l0_default_active: u32,
+    pub num_ref_idx_l1_default_active: u32,
+    pub init_qp: i32,
+    pub constrained_intra_pred_flag: bool,
+    pub transform_skip_enabled_flag: bool,
+    // diff_cu_qp_delta_depth when cu_qp_delta_enabled_flag is set
+    pub diff_cu_qp_delta_depth: Option<u32>,
+    pub pps_cb_qp_offset: i32,
+    pub pps_cr_qp_offset: i32,
+    pub pps_slice_chroma_qp_offsets_present_flag: bool,
+    pub weighted_pred_flag: bool,
+    pub weighted_bipred_flag: bool,
+    pub transquant_bypass_enabled_flag: bool,
+    pub tiles_enabled_flag: bool,
+    pub entropy_coding_sync_enabled_flag: bool,
+    pub tiles: Option<Tiles>,
+    pub pps_loop_filter_across_slices_enabled_flag: bool,
+    pub deblocking_filter_control_present_flag: bool,
+    pub deblocking_filter_override_enabled_flag: bool,
+    pub pps_deblocking_filter_disabled_flag: bool,
+    pub pps_beta_offset_div2: i32,
+    pub pps_tc_offset_div2: i32,
+    // empty without pps_scaling_list_data_present_flag
+    pub pps_scaling_lists: Vec<ScalingList>,
+    pub lists_modification_present_flag: bool,
+    pub log2_parallel_merge_level: u32,
+    pub slice_segment_header_extension_present_flag: bool,
+    // pps_range_extension_flag, pps_multilayer_extension_flag,
+    // pps_3d_extension_flag, pps_scc_extension_flag and pps_extension_4bits
+    pub pps_extension_flags: u32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Tiles {
+    pub num_tile_columns: u32,
+    pub num_tile_rows: u32,
+    pub uniform_spacing_flag: bool,
+    // column_width_minus1 + 1 and row_height_minus1 + 1 in CTBs, without the
+    // last column and row
+    pub column_widths: Vec<u32>,
+    pub row_heights: Vec<u32>,
+    pub loop_filter_across_tiles_enabled_flag: bool,
+}
+
+impl Pps {
+    pub fn parse(rbsp: &[u8]) -> Result<Pps, &'static str> {
+        let mut r = BitReader::new(rbsp);
+        let mut pps = Pps {
+            pps_pic_parameter_set_id: r.ue()?,
+            pps_seq_parameter_set_id: r.ue()?,
+            dependent_slice_segments_enabled_flag: r.bit()?,
+            output_flag_present_flag: r.bit()?,
+            num_extra_slice_header_bits: r.bits(3)?,
+            sign_data_hiding_enabled_flag: r.bit()?,
+            cabac_init_present_flag: r.bit()?,
+            num_ref_idx_l0_default_active: r.ue()? + 1,
+            num_ref_idx_l1_default_active: r.ue()? + 1,
+            init_qp: r.se()? + 26,
+            constrained_intra_pred_flag: r.bit()?,
+            transform_skip_enabled_flag: r.bit()?,
+            ..Pps::default()
+        };
+        if r.bit()? {
+            pps.diff_cu_qp_delta_depth = Some(r.ue()?);
+        }
+        pps.pps_cb_qp_offset = r.se()?;
+        pps.pps_cr_qp_offset = r.se()?;
+        pps.pps_slice_chroma_qp_offsets_present_flag = r.bit()?;
+        pps.weighted_pred_flag = r.bit()?;
+        pps.weighted_bipred_flag = r.bit()?;
+        pps.transquant_bypass_enabled_flag = r.bit()?;
+        pps.tiles_enabled_flag = r.bit()?;
+        pps.entropy_coding_sync_enabled_flag = r.bit()?;
+        if pps.tiles_enabled_flag {
+            let mut tiles = Tiles {
+                num_tile_columns: r.ue()? + 1,
+                num_tile_rows: r.ue()? + 1,
+                uniform_spacing_flag: r.bit()?,
+                ..Tiles::default()
+            };
+            if !tiles.uniform_spacing_flag {
+                for _ in 1..tiles.num_tile_columns {
+                    tiles.column_widths.push(r.ue()? + 1);
+                }
+                for _ in 1..tiles.num_tile_rows {
+                    tiles.row_heights.push(r.ue()? + 1);
+                }
+            }
+            tiles.loop_filter_across_tiles_enabled_flag = r.bit()?;
+            pps.tiles = Some(tiles);
+        }
+        pps.pps_loop_filter_across_slices_enabled_flag = r.bit()?;
+        pps.deblocking_filter_control_present_flag = r.bit()?;
+        if pps.deblocking_filter_control_present_flag {
+            pps.deblocking_filter_override_enabled_flag = r.bit()?;
+            pps.pps_deblocking_filter_disabled_flag = r.bit()?;
+            if !pps.pps_deblocking_filter_disabled_flag {
+                pps.pps_beta_offset_div2 = r.se()?;
+                pps.pps_tc_offset_div2 = r.se()?;
+            }
+        }
+        if r.bit()? {
+            pps.pps_scaling_lists = scaling_list_data(&mut r)?;
+        }
+        pps.lists_modification_present_flag = r.bit()?;
+        pps.log2_parallel_merge_level = r.ue()? + 2;
+        pps.slice_segment_header_extension_present_flag = r.bit()?;
+        if r.bit()? {
+            pps.pps_extension_flags = r.bits(8)?;
+        }
+        Ok(pps)
+    }
+}
+
+impl fmt::Display for Pps {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f,
+                 "PPS {} (SPS {}): init qp {}, cb/cr qp offset {} {}, {} + {} default references",
+                 self.pps_pic_parameter_set_id,
+                 self.pps_seq_parameter_set_id,
+                 self.init_qp,
+                 self.pps_cb_qp_offset,
+                 self.pps_cr_qp_offset,
+                 self.num_ref_idx_l0_default_active,
+                 self.num_ref_idx_l1_default_active)?;
+        let tools = [(self.dependent_slice_segments_enabled_flag, "dependent slices"),
+                     (self.sign_data_hiding_enabled_flag, "sign data hiding"),
+                     (self.transform_skip_enabled_flag, "transform skip"),
+                     (self.diff_cu_qp_delta_depth.is_some(), "cu qp delta"),
+                     (self.weighted_pred_flag, "weighted pred"),
+                     (self.weighted_bipred_flag, "weighted bipred"),
+                     (self.transquant_bypass_enabled_flag, "transquant bypass"),
+                     (self.entropy_coding_sync_enabled_flag, "wavefronts"),
+                     (self.pps_deblocking_filter_disabled_flag, "deblocking disabled"),
+                     (!self.pps_scaling_lists.is_empty(), "scaling lists")];
+        let tools: Vec<&str> = tools.iter().filter(|t| t.0).map(|t| t.1).collect();
+        if !tools.is_empty() {
+            writeln!(f, "  {}", tools.join(", "))?;
+        }
+        if let Some(ref tiles) = self.tiles {
+            writeln!(f,
+                     "  {}x{} tiles{}",
+                     tiles.num_tile_columns,
+                     tiles.num_tile_rows,
+                     if tiles.uniform_spacing_flag { " uniform" } else { "" })?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SliceHeader {
+    pub first_slice_segment_in_pic_flag: bool,
+    pub slice_pic_parameter_set_id: u32,
+    pub dependent_slice_segment_flag: bool,
+    pub slice_segment_address: u32,
+    // only set on independent slice segments
+    pub slice_type: Option<u32>,
+    pub pic_output_flag: bool,
+    pub slice_pic_order_cnt_lsb: u32,
+}
+
+// the VPS, SPS and PPS seen so far, by id
+#[derive(Default)]
+pub struct ParameterSets {
+    pub vps: HashMap<u32, Vps>,
+    pub sps: HashMap<u32, Sps>,
+    pub pps: HashMap<u32, Pps>,
+}
+
+impl ParameterSets {
+    pub fn new() -> ParameterSets {
+        ParameterSets::default()
+    }
+
+    // takes the NAL unit without its start code, other types than VPS, SPS
+    // and PPS are ignored
+    pub fn update(&mut self, nal: &[u8]) -> Result<(), &'static str> {
+        if nal.len() < 2 {
+            return Ok(());
+        }
+        match nal_unit_type(nal) {
+            NAL_VPS => {
+                let vps = Vps::parse(&unescape(&nal[2..]))?;
+                self.vps.insert(vps.vps_video_parameter_set_id, vps);
+            }
+            NAL_SPS => {
+                let sps = Sps::parse(&unescape(&nal[2..]))?;
+                self.sps.insert(sps.sps_seq_parameter_set_id, sps);
+            }
+            NAL_PPS => {
+                let pps = Pps::parse(&unescape(&nal[2..]))?;
+                self.pps.insert(pps.pps_pic_parameter_set_id, pps);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // the PPS and SPS a slice segment refers to
+    pub fn parameter_sets_of(&self, slice_pic_parameter_set_id: u32)
+                             -> Result<(&Pps, &Sps), &'static str> {
+        let pps = self.pps
+            .get(&slice_pic_parameter_set_id)
+            .ok_or("slice refers to a missing parameter set")?;
+        let sps = self.sps
+            .get(&pps.pps_seq_parameter_set_id)
+            .ok_or("slice refers to a missing parameter set")?;
+        Ok((pps, sps))
+    }
+
+    pub fn slice_header(&self, nal: &[u8]) -> Result<SliceHeader, &'static str> {
+        let nal_unit_type = nal_unit_type(nal);
+        let rbsp = unescape(nal.get(2..).unwrap_or(&[]));
+        let mut r = BitReader::new(&rbsp);
+        let mut slice = SliceHeader {
+            first_slice_segment_in_pic_flag: r.bit()?,
+            pic_output_flag: true,
+            ..SliceHeader::default()
+        };
+        if is_irap(nal_unit_type) {
+            r.skip(1)?; // no_output_of_prior_pics_flag
+        }
+        slice.slice_pic_parameter_set_id = r.ue()?;
+        let (pps, sps) = self.parameter_sets_of(slice.slice_pic_parameter_set_id)?;
+        if !slice.first_slice_segment_in_pic_flag {
+            if pps.dependent_slice_segments_enabled_flag {
+                slice.dependent_slice_segment_flag = r.bit()?;
+            }
+            let bits = 32 - (sps.pic_size_in_ctbs().max(1) - 1).leading_zeros();
+            slice.slice_segment_address = r.bits(bits)?;
+        }
+        if !slice.dependent_slice_segment_flag {
+            r.skip(pps.num_extra_slice_header_bits as usize)?;
+            slice.slice_type = Some(r.ue()?);
+            if pps.output_flag_present_flag {
+                slice.pic_output_flag = r.bit()?;
+            }
+            if sps.separate_colour_plane_flag {
+                r.skip(2)?; // colour_plane_id
+            }
+            if nal_unit_type != NAL_IDR_W_RADL && nal_unit_type != NAL_IDR_N_LP {
+                slice.slice_pic_order_cnt_lsb = r.bits(sps.log2_max_pic_order_cnt_lsb)?;
+            }
+        }
+        Ok(slice)
+    }
+}
+
+// PicOrderCntVal of the pictures in decoding order (8.3.1), along with the
+// NoRaslOutputFlag of the last IRAP picture
+pub struct PicOrderCount {
+    prev_tid0_pic_order_cnt: i32,
+    // the next picture starts a coded video sequence
+    first_picture: bool,
+    pub no_rasl_output_flag: bool,
+}
+
+impl Default for PicOrderCount {
+    fn default() -> PicOrderCount {
+        PicOrderCount {
+            prev_tid0_pic_order_cnt: 0,
+            first_picture: true,
+            no_rasl_output_flag: true,
+        }
+    }
+}
+
+impl PicOrderCount {
+    pub fn new() -> PicOrderCount {
+        PicOrderCount::default()
+    }
+
+    // after an end of sequence NAL unit
+    pub fn end_of_sequence(&mut self) {
+        self.first_picture = true;
+    }
+
+    // takes the first slice segment of the picture
+    pub fn next(&mut self,
+                sps: &Sps,
+                nal_unit_type: u8,
+                temporal_id: u8,
+                slice: &SliceHeader)
+                -> i32 {
+        if is_irap(nal_unit_type) {
+            self.no_rasl_output_flag = nal_unit_type != NAL_CRA || self.first_picture;
+        }
+        self.first_picture = false;
+
+        let max_lsb = 1i32 << sps.log2_max_pic_order_cnt_lsb;
+        let lsb = slice.slice_pic_order_cnt_lsb as i32;
+        let msb = if is_irap(nal_unit_type) && self.no_rasl_output_flag {
+            0
+        } else {
+            let prev_lsb = self.prev_tid0_pic_order_cnt & (max_lsb - 1);
+            let prev_msb = self.prev_tid0_pic_order_cnt - prev_lsb;
+            if lsb < prev_lsb && prev_lsb - lsb >= max_lsb / 2 {
+                prev_msb + max_lsb
+            } else if lsb > prev_lsb && lsb - prev_lsb > max_lsb / 2 {
+                prev_msb - max_lsb
+            } else {
+                prev_msb
+            }
+        };
+        let poc = msb + lsb;
+
+        // RADL, RASL and sub-layer non-reference pictures don't count
+        let sub_layer_non_reference = nal_unit_type <= 14 && nal_unit_type.is_multiple_of(2);
+        let leading = (NAL_RADL_N..=NAL_RASL_R).contains(&nal_unit_type);
+        if temporal_id == 0 && !leading && !sub_layer_non_reference {
+            self.prev_tid0_pic_order_cnt = poc;
+        }
+        poc
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RecoveryPoint {
+    pub recovery_poc_cnt: i32,
+    pub exact_match_flag: bool,
+    pub broken_link_flag: bool,
+}
+
+impl RecoveryPoint {
+    pub fn parse(payload: &[u8]) -> Result<RecoveryPoint, &'static str> {
+        let mut r = BitReader::new(payload);
+        Ok(RecoveryPoint {
+            recovery_poc_cnt: r.se()?,
+            exact_match_flag: r.bit()?,
+            broken_link_flag: r.bit()?,
+        })
+    }
+}
+
+impl fmt::Display for RecoveryPoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "recovery_poc_cnt {}{}{}",
+               self.recovery_poc_cnt,
+               if self.exact_match_flag { ", exact match" } else { "" },
+               if self.broken_link_flag { ", broken link" } else { "" })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BufferingPeriod {
+    pub bp_seq_parameter_set_id: u32,
+    // cpb_delay_offset and dpb_delay_offset with irap_cpb_params_present_flag
+    pub irap_cpb_params: Option<(u32, u32)>,
+    pub concatenation_flag: bool,
+    pub au_cpb_removal_delay_delta: u32,
+    // initial_cpb_removal_delay and initial_cpb_removal_offset of each
+    // schedule, the alternative ones aren't kept
+    pub nal_initial_cpb_removal: Vec<(u32, u32)>,
+    pub vcl_initial_cpb_removal: Vec<(u32, u32)>,
+}
+
+impl BufferingPeriod {
+    pub fn parse(payload: &[u8], sps: &HashMap<u32, Sps>) -> Result<BufferingPeriod, &'static str> {
+        let mut r = BitReader::new(payload);
+        let mut bp = BufferingPeriod {
+            bp_seq_parameter_set_id: r.ue()?,
+            ..BufferingPeriod::default()
+        };
+        let hrd = sps.get(&bp.bp_seq_parameter_set_id)
+            .ok_or("buffering period refers to a missing SPS")?
+            .vui
+            .as_ref()
+            .and_then(|vui| vui.hrd.as_ref())
+            .ok_or("buffering period without HRD parameters")?;
+        let irap_cpb_params_present_flag = !hrd.sub_pic_hrd_params_present_flag && r.bit()?;
+        if irap_cpb_params_present_flag {
+            bp.irap_cpb_params = Some((r.bits(hrd.au_cpb_removal_delay_length)?,
+                                       r.bits(hrd.dpb_output_delay_length)?));
+        }
+        bp.concatenation_flag = r.bit()?;
+        bp.au_cpb_removal_delay_delta = r.bits(hrd.au_cpb_removal_delay_length)? + 1;
+        let sub_layer = hrd.sub_layers.last().ok_or("HRD parameters without sub-layers")?;
+        for (schedules, delays) in [(&sub_layer.nal_schedules, &mut bp.nal_initial_cpb_removal),
+                                    (&sub_layer.vcl_schedules, &mut bp.vcl_initial_cpb_removal)] {
+            for _ in 0..schedules.len() {
+                let length = hrd.initial_cpb_removal_delay_length;
+                delays.push((r.bits(length)?, r.bits(length)?));
+                if hrd.sub_pic_hrd_params_present_flag || irap_cpb_params_present_flag {
+                    r.skip(2 * length as usize)?;
+                }
+            }
+        }
+        Ok(bp)
+    }
+}
+
+impl fmt::Display for BufferingPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SPS {}", self.bp_seq_parameter_set_id)?;
+        if self.concatenation_flag {
+            write!(f, ", concatenation")?;
+        }
+        for (name, delays) in [("NAL", &self.nal_initial_cpb_removal),
+                               ("VCL", &self.vcl_initial_cpb_removal)] {
+            for &(delay, offset) in delays.iter() {
+                write!(f, ", {} initial cpb removal delay {} offset {}", name, delay, offset)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PicTiming {
+    // pic_struct, source_scan_type and duplicate_flag
+    pub frame_field_info: Option<(u32, u32, bool)>,
+    pub au_cpb_removal_delay: Option<u32>,
+    pub pic_dpb_output_delay: Option<u32>,
+}
+
+impl PicTiming {
+    // takes the active SPS
+    pub fn parse(payload: &[u8], sps: &Sps) -> Result<PicTiming, &'static str> {
+        let mut r = BitReader::new(payload);
+        let mut pt = PicTiming::default();
+        let vui = sps.vui.as_ref().ok_or("picture timing without VUI")?;
+        if vui.frame_field_info_present_flag {
+            pt.frame_field_info = Some((r.bits(4)?, r.bits(2)?, r.bit()?));
+        }
+        if let Some(ref hrd) = vui.hrd {
+            if hrd.nal_hrd_parameters_present_flag || hrd.vcl_hrd_parameters_present_flag {
+                pt.au_cpb_removal_delay = Some(r.bits(hrd.au_cpb_removal_delay_length)? + 1);
+                pt.pic_dpb_output_delay = Some(r.bits(hrd.dpb_output_delay_length)?);
+            }
+        }
+        Ok(pt)
+    }
+}
+
+impl fmt::Display for PicTiming {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some((pic_struct, source_scan_type, duplicate_flag)) = self.frame_field_info {
+            parts.push(format!("pic_struct {}, source_scan_type {}{}",
+                               pic_struct,
+                               source_scan_type,
+                               if duplicate_flag { ", duplicate" } else { "" }));
+        }
+        if let (Some(cpb), Some(dpb)) = (self.au_cpb_removal_delay, self.pic_dpb_output_delay) {
+            parts.push(format!("cpb removal delay {}, dpb output delay {}", cpb, dpb));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HashType {
+    Md5,
+    Crc,
+    Checksum,
+}
+
+impl HashType {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            HashType::Md5 => "MD5",
+            HashType::Crc => "CRC",
+            HashType::Checksum => "checksum",
+        }
+    }
+
+    // bytes of the hash of one plane
+    pub fn size(&self) -> usize {
+        match *self {
+            HashType::Md5 => 16,
+            HashType::Crc => 2,
+            HashType::Checksum => 4,
+        }
+    }
+
+    // the hash of a plane of width samples per row (D.3.19), samples above 8
+    // bits being two bytes little endian as in the pictureData of the spec
+    pub fn compute(&self, plane: &[u8], width: usize, bit_depth: u32) -> Vec<u8> {
+        let sample_bytes = if bit_depth > 8 { 2 } else { 1 };
+        match *self {
+            HashType::Md5 => {
+                let mut md5 = Md5::new();
+                md5.update(plane);
+                md5.digest().to_vec()
+            }
+            HashType::Crc => {
+                let mut crc = 0xffffu32;
+                let bits = plane.iter()
+                    .flat_map(|&b| (0..8).rev().map(move |i| (b >> i) as u32 & 1))
+                    .chain((0..16).map(|_| 0));
+                for bit in bits {
+                    let msb = (crc >> 15) & 1;
+                    crc = (((crc << 1) + bit) & 0xffff) ^ (msb * 0x1021);
+                }
+                (crc as u16).to_be_bytes().to_vec()
+            }
+            HashType::Checksum => {
+                let mut sum = 0u32;
+                for (i, sample) in plane.chunks(sample_bytes).enumerate() {
+                    let (x, y) = ((i % width) as u32, (i / width) as u32);
+                    let xor_mask = (x & 0xff) ^ (y & 0xff) ^ (x >> 8) ^ (y >> 8);
+                    for &b in sample {
+                        sum = sum.wrapping_add((b as u32 ^ xor_mask) & 0xff);
+                    }
+                }
+                sum.to_be_bytes().to_vec()
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DecodedPictureHash {
+    pub hash_type: HashType,
+    // the hash of each colour component, as sent
+    pub picture_hash: Vec<Vec<u8>>,
+}
+
+impl DecodedPictureHash {
+    pub fn parse(payload: &[u8]) -> Result<DecodedPictureHash, &'static str> {
+        let hash_type = match payload.first() {
+            Some(&0) => HashType::Md5,
+            Some(&1) => HashType::Crc,
+            Some(&2) => HashType::Checksum,
+            _ => return Err("unknown decoded picture hash type"),
+        };
+        // one hash for 4:0:0 and three otherwise, the payload tells which
+        let hashes = &payload[1..];
+        let planes = hashes.len() / hash_type.size();
+        if (planes != 1 && planes != 3) || !hashes.len().is_multiple_of(hash_type.size()) {
+            return Err("decoded picture hash of the wrong size");
+        }
+        Ok(DecodedPictureHash {
+            hash_type,
+            picture_hash: hashes.chunks(hash_type.size()).map(|h| h.to_vec()).collect(),
+        })
+    }
+}
+
+impl fmt::Display for DecodedPictureHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.hash_type.name())?;
+        for hash in self.picture_hash.iter() {
+            write!(f, " ")?;
+            for b in hash {
+                write!(f, "{:02x}", b)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// one line description of an SEI message, given the parameter sets and the
+// SPS active for the access unit
+pub fn describe_sei(message: &SeiMessage,
+                    ps: &ParameterSets,
+                    sps: Option<&Sps>)
+                    -> Result<String, &'static str> {
+    let payload = &message.payload;
+    Ok(match message.payload_type {
+        sei::BUFFERING_PERIOD => BufferingPeriod::parse(payload, &ps.sps)?.to_string(),
+        sei::PIC_TIMING => {
+            PicTiming::parse(payload, sps.ok_or("picture timing without an active SPS")?)?
+                .to_string()
+        }
+        sei::USER_DATA_UNREGISTERED => UserDataUnregistered::parse(payload)?.to_string(),
+        sei::RECOVERY_POINT => RecoveryPoint::parse(payload)?.to_string(),
+        sei::DECODED_PICTURE_HASH => DecodedPictureHash::parse(payload)?.to_string(),
+        sei::MASTERING_DISPLAY_COLOUR_VOLUME => {
+            MasteringDisplayColourVolume::parse(payload)?.to_string()
+        }
+        sei::CONTENT_LIGHT_LEVEL_INFO => ContentLightLevel::parse(payload)?.to_string(),
+        _ => String::new(),
+    })
+}
@@ -0,0 +1,109 @@
+// Block level distortion maps, written per frame and plane so blocking or
+// edge artifacts can be located rather than only measured.
+
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+
+const PLANE_NAMES: [&str; 3] = ["y", "u", "v"];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HeatmapFormat {
+    // grayscale image at plane resolution, brighter blocks have more distortion
+    Pgm,
+    // mse of every block, one row of blocks per line
+    Csv,
+}
+
+impl HeatmapFormat {
+    pub fn parse(name: &str) -> Result<HeatmapFormat, &'static str> {
+        match name.to_lowercase().as_ref() {
+            "pgm" => Ok(HeatmapFormat::Pgm),
+            "csv" => Ok(HeatmapFormat::Csv),
+            _ => Err("only support pgm and csv heatmap formats"),
+        }
+    }
+}
+
+pub struct Heatmap {
+    pub prefix: String,
+    pub block_size: usize,
+    pub format: HeatmapFormat,
+}
+
+impl Heatmap {
+    // block is the luma block size scaled by the plane subsampling,
+    // so the block grid is the same in every plane
+    pub fn write_plane(&self,
+                       frame: usize,
+                       plane: usize,
+                       org: &[u8],
+                       rec: &[u8],
+                       (width, height): (usize, usize),
+                       (block_w, block_h): (usize, usize))
+                       -> io::Result<()> {
+        let (cols, rows, mse) = block_mse(org, rec, width, height, block_w, block_h);
+        let extension = match self.format {
+            HeatmapFormat::Pgm => "pgm",
+            HeatmapFormat::Csv => "csv",
+        };
+        let output = format!("{}_{:04}_{}.{}",
+                             self.prefix,
+                             frame,
+                             PLANE_NAMES[plane],
+                             extension);
+        let mut fo = BufWriter::new(File::create(output)?);
+
+        match self.format {
+            HeatmapFormat::Pgm => {
+                // log scale so that both subtle and gross errors stay visible,
+                // from 0 for identical blocks up to 255 for an mse of 255^2
+                let full_scale = (1.0f64 + 255.0 * 255.0).log10();
+                let levels: Vec<u8> = mse.iter()
+                    .map(|m| (255.0 * (1.0 + m).log10() / full_scale).round().min(255.0) as u8)
+                    .collect();
+                write!(fo, "P5\n{} {}\n255\n", width, height)?;
+                for y in 0..height {
+                    let row: Vec<u8> = (0..width)
+                        .map(|x| levels[(y / block_h) * cols + x / block_w])
+                        .collect();
+                    fo.write_all(&row)?;
+                }
+            }
+            HeatmapFormat::Csv => {
+                for r in 0..rows {
+                    let cells: Vec<String> = mse[r * cols..(r + 1) * cols]
+                        .iter()
+                        .map(|m| format!("{:.3}", m))
+                        .collect();
+                    writeln!(fo, "{}", cells.join(","))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// (columns, rows, mse per block), blocks on the right and bottom edges may be partial
+pub fn block_mse(org: &[u8],
+                 rec: &[u8],
+                 width: usize,
+                 height: usize,
+                 block_w: usize,
+                 block_h: usize)
+                 -> (usize, usize, Vec<f64>) {
+    let cols = width.div_ceil(block_w);
+    let rows = height.div_ceil(block_h);
+    let mut sse = vec![0u64; cols * rows];
+    let mut count = vec![0u64; cols * rows];
+    for y in 0..height {
+        for x in 0..width {
+            let diff = org[y * width + x] as i64 - rec[y * width + x] as i64;
+            let b = (y / block_h) * cols + x / block_w;
+            sse[b] += (diff * diff) as u64;
+            count[b] += 1;
+        }
+    }
+    let mse = sse.iter().zip(count.iter()).map(|(s, n)| *s as f64 / (*n).max(1) as f64).collect();
+    (cols, rows, mse)
+}
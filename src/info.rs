@@ -0,0 +1,279 @@
+use std::fs::File;
+use std::io;
+use std::io::{Error, Seek};
+use super::bin::Bin;
+use super::h264;
+use super::h265;
+use super::Bitstream;
+
+pub struct Info {
+    pub input: String,
+    pub h265: bool,
+    // frame rate for the bitrate summary
+    pub fps: f64,
+}
+
+// what is printed for an access unit, from its NAL units and first slice
+struct AccessUnit {
+    offset: u64,
+    size: u32,
+    nal_unit_types: Vec<&'static str>,
+    slice_types: Vec<&'static str>,
+    // IDR, or the IRAP type for H.265
+    random_access: Option<&'static str>,
+    temporal_id: u8,
+    frame_num: Option<u32>,
+    poc: Option<i32>,
+}
+
+impl AccessUnit {
+    fn new(offset: u64, bs: &Bitstream) -> AccessUnit {
+        AccessUnit {
+            offset: offset + bs.frame_location[0] as u64,
+            size: bs.frame_location[bs.nal_size] - bs.frame_location[0],
+            nal_unit_types: Vec::new(),
+            slice_types: Vec::new(),
+            random_access: None,
+            temporal_id: 0,
+            frame_num: None,
+            poc: None,
+        }
+    }
+
+    // I, P or B for the picture, from the slice with the most prediction
+    fn picture_type(&self) -> &'static str {
+        if self.slice_types.contains(&"B") {
+            "B"
+        } else if self.slice_types.iter().any(|t| *t == "P" || *t == "SP") {
+            "P"
+        } else if self.slice_types.is_empty() {
+            "-"
+        } else {
+            "I"
+        }
+    }
+}
+
+impl Info {
+    pub fn helper() {
+        println!("Usage: rsplit info input.bin h264|h265 [--fps=30]")
+    }
+
+    pub fn new(args: &[String]) -> Result<Info, &'static str> {
+        let mut fps = 30.0;
+        let mut positional: Vec<String> = Vec::new();
+        for arg in args {
+            if let Some(n) = arg.strip_prefix("--fps=") {
+                fps = n.parse::<f64>().map_err(|_| "can't parse fps as f64")?;
+                if fps <= 0.0 {
+                    return Err("fps must be positive");
+                }
+            } else if arg.starts_with("--") {
+                return Err("unknown option for rsplit info mode");
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        let args = &positional[..];
+
+        if args.len() < 4 {
+            return Err("too less arguments for rsplit info mode");
+        }
+
+        let h265 = match args[3].to_lowercase().as_ref() {
+            "h265" => true,
+            "h264" => false,
+            _ => {
+                return Err("only support h264 and h265");
+            }
+        };
+
+        Ok(Info {
+            input: args[2].clone(),
+            h265,
+            fps,
+        })
+    }
+
+    pub fn run(&self) -> io::Result<()> {
+        println!("rsplit info H26{} {}", 4 + (self.h265 as i32), self.input);
+        println!("{:>6} {:>10} {:>8} {:>10} {:>3} {:>9} {:>6} {:>6} nal units",
+                 "au",
+                 "offset",
+                 "size",
+                 "random",
+                 "tid",
+                 "frame_num",
+                 "poc",
+                 "slices");
+        let units = if self.h265 {
+            self.h265_units()?
+        } else {
+            self.h264_units()?
+        };
+        self.summary(&units);
+        Ok(())
+    }
+
+    fn print(&self, index: usize, au: &AccessUnit) {
+        let frame_num = au.frame_num.map_or("-".to_string(), |n| n.to_string());
+        let poc = au.poc.map_or("-".to_string(), |n| n.to_string());
+        println!("{:6} {:10} {:8} {:>10} {:3} {:>9} {:>6} {:>6} {}",
+                 index,
+                 au.offset,
+                 au.size,
+                 au.random_access.unwrap_or("-"),
+                 au.temporal_id,
+                 frame_num,
+                 poc,
+                 au.slice_types.concat(),
+                 au.nal_unit_types.join(","));
+    }
+
+    fn h264_units(&self) -> io::Result<Vec<AccessUnit>> {
+        let bin = Bin::scanner(&self.input, false);
+        let mut fi = File::open(&self.input)?;
+        let mut ps = h264::ParameterSets::new();
+        let mut poc = h264::PicOrderCount::new();
+        let mut units = Vec::new();
+        loop {
+            let offset = fi.stream_position()?;
+            let (eof, bs) = bin.find_h264_nal_units(&mut fi);
+            let bs = bs?;
+            if bs.nal_size > 0 {
+                let mut au = AccessUnit::new(offset, &bs);
+                for i in 0..bs.nal_size {
+                    let nal = bs.nal_unit(i);
+                    let nal_unit_type = h264::nal_unit_type(nal);
+                    au.nal_unit_types.push(h264::nal_unit_type_name(nal_unit_type));
+                    if let Some(temporal_id) = h264::prefix_temporal_id(nal) {
+                        au.temporal_id = temporal_id;
+                    }
+                    if !(1..=5).contains(&nal_unit_type) {
+                        ps.update(nal).map_err(|e| au_error(units.len(), e))?;
+                        continue;
+                    }
+                    if nal_unit_type == 3 || nal_unit_type == 4 {
+                        // partitions B and C have no slice header
+                        continue;
+                    }
+                    let slice = ps.slice_header(nal).map_err(|e| au_error(units.len(), e))?;
+                    au.slice_types.push(h264::slice_type_name(slice.slice_type));
+                    if au.frame_num.is_none() {
+                        let idr = nal_unit_type == h264::NAL_SLICE_IDR;
+                        let sps = ps.sps_of(&slice).map_err(|e| au_error(units.len(), e))?;
+                        au.frame_num = Some(slice.frame_num);
+                        au.poc = Some(poc.next(sps, &slice, h264::nal_ref_idc(nal), idr));
+                        if idr {
+                            au.random_access = Some("IDR");
+                        }
+                    }
+                }
+                self.print(units.len(), &au);
+                units.push(au);
+            }
+            if eof {
+                break;
+            }
+        }
+        Ok(units)
+    }
+
+    fn h265_units(&self) -> io::Result<Vec<AccessUnit>> {
+        let bin = Bin::scanner(&self.input, true);
+        let mut fi = File::open(&self.input)?;
+        let mut ps = h265::ParameterSets::new();
+        let mut poc = h265::PicOrderCount::new();
+        let mut units = Vec::new();
+        loop {
+            let offset = fi.stream_position()?;
+            let (eof, bs) = bin.find_h265_nal_units(&mut fi);
+            let bs = bs?;
+            if bs.nal_size > 0 {
+                let mut au = AccessUnit::new(offset, &bs);
+                for i in 0..bs.nal_size {
+                    let nal = bs.nal_unit(i);
+                    let nal_unit_type = h265::nal_unit_type(nal);
+                    au.nal_unit_types.push(h265::nal_unit_type_name(nal_unit_type));
+                    if nal_unit_type == h265::NAL_EOS {
+                        poc.end_of_sequence();
+                    }
+                    if nal_unit_type > 31 {
+                        ps.update(nal).map_err(|e| au_error(units.len(), e))?;
+                        continue;
+                    }
+                    if nal_unit_type > 23 || h265::nuh_layer_id(nal) != 0 {
+                        continue;
+                    }
+                    let slice = ps.slice_header(nal).map_err(|e| au_error(units.len(), e))?;
+                    if let Some(slice_type) = slice.slice_type {
+                        au.slice_types.push(h265::slice_type_name(slice_type));
+                    }
+                    if slice.first_slice_segment_in_pic_flag {
+                        let (_, sps) = ps.parameter_sets_of(slice.slice_pic_parameter_set_id)
+                            .map_err(|e| au_error(units.len(), e))?;
+                        au.temporal_id = h265::temporal_id(nal);
+                        au.poc = Some(poc.next(sps, nal_unit_type, au.temporal_id, &slice));
+                        if h265::is_irap(nal_unit_type) {
+                            au.random_access = Some(h265::nal_unit_type_name(nal_unit_type));
+                        }
+                    }
+                }
+                self.print(units.len(), &au);
+                units.push(au);
+            }
+            if eof {
+                break;
+            }
+        }
+        Ok(units)
+    }
+
+    fn summary(&self, units: &[AccessUnit]) {
+        let total: u64 = units.iter().map(|au| au.size as u64).sum();
+        println!("{} access units, {} bytes", units.len(), total);
+        if units.is_empty() {
+            return;
+        }
+
+        let count = |t: &str| units.iter().filter(|au| au.picture_type() == t).count();
+        println!("pictures: I {} P {} B {}", count("I"), count("P"), count("B"));
+
+        // a GOP runs from one random access point to the next, anything before
+        // the first one counts into the first GOP
+        let mut gops: Vec<usize> = Vec::new();
+        for (i, au) in units.iter().enumerate() {
+            if gops.is_empty() || (au.random_access.is_some() && i > 0) {
+                gops.push(0);
+            }
+            *gops.last_mut().unwrap() += 1;
+        }
+        let first_gop: String = units[..gops[0]].iter().map(|au| au.picture_type()).collect();
+        println!("GOPs: {}, length min {} max {} average {:.1}, first {}",
+                 gops.len(),
+                 gops.iter().min().unwrap(),
+                 gops.iter().max().unwrap(),
+                 units.len() as f64 / gops.len() as f64,
+                 first_gop);
+
+        // peak over a one second window of access units
+        let window = (self.fps.round() as usize).clamp(1, units.len());
+        let mut bytes: u64 = units[..window].iter().map(|au| au.size as u64).sum();
+        let mut peak = bytes;
+        for i in window..units.len() {
+            bytes = bytes + units[i].size as u64 - units[i - window].size as u64;
+            peak = peak.max(bytes);
+        }
+        let kbps = |bytes: u64, frames: usize| {
+            bytes as f64 * 8.0 * self.fps / frames as f64 / 1000.0
+        };
+        println!("bitrate at {} fps: average {:.2} kbps, peak {:.2} kbps",
+                 self.fps,
+                 kbps(total, units.len()),
+                 kbps(peak, window));
+    }
+}
+
+fn au_error(au: usize, err: &str) -> Error {
+    Error::other(format!("access unit {}: {}", au, err))
+}
@@ -2,6 +2,8 @@ use std::fs::File;
 use std::io;
 use std::io::{Read, Write};
 use std::io::{Error, ErrorKind};
+use super::cut::{parse_bytes, Cut, FrameInfo};
+use super::svc::{Extractor, Layers};
 use super::Bitstream;
 
 pub struct Ivf {
@@ -9,14 +11,58 @@ pub struct Ivf {
     pub output: String,
     pub frame_num: usize,
     pub vp9: bool,
+    // the segments to write instead of splitting every frame_num frames
+    pub cut: Option<Cut>,
+    // also split at the next key frame once a segment holds this many bytes
+    pub max_bytes: Option<usize>,
+    // the VP9 SVC layers to keep of every frame
+    pub layers: Option<Layers>,
 }
 
 impl Ivf {
+    // an Ivf that only reads the frames of a stream, for the modes reading
+    // IVF files
+    pub fn reader(vp9: bool) -> Ivf {
+        Ivf {
+            input: String::new(),
+            output: String::new(),
+            frame_num: 0,
+            vp9,
+            cut: None,
+            max_bytes: None,
+            layers: None,
+        }
+    }
+
     pub fn helper() {
-        println!("Usage: rsplit ivf input.ivf output frame_num vp8|vp9")
+        println!("Usage: rsplit ivf input.ivf output frame_num vp8|vp9 [--max-bytes=N[k|m]] {} {}",
+                 Cut::usage(),
+                 Layers::usage())
     }
 
     pub fn new(args: &[String]) -> Result<Ivf, &'static str> {
+        let mut cut = None;
+        let mut max_bytes = None;
+        let mut layers = None;
+        let mut positional: Vec<String> = Vec::new();
+        for arg in args {
+            if let Some(parsed) = Cut::parse(arg) {
+                cut = Some(parsed?);
+            } else if let Some(size) = arg.strip_prefix("--max-bytes=") {
+                max_bytes = Some(parse_bytes(size)?);
+            } else if Layers::parse(&mut layers, arg)? {
+                continue;
+            } else if arg.starts_with("--") {
+                return Err("unknown option for rsplit ivf mode");
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        let args = &positional[..];
+        if cut.is_some() && max_bytes.is_some() {
+            return Err("--max-bytes doesn't go with a cut list");
+        }
+
         let l = args.len() as usize;
         if l < 6 {
             return Err("too less arguments for rsplit ivf mode");
@@ -38,12 +84,18 @@ impl Ivf {
                 return Err("only support vp8 and vp9");
             }
         };
+        if layers.is_some() && !vp9 {
+            return Err("only vp9 has svc layers to extract");
+        }
 
         Ok(Ivf {
             input: input,
             output: output,
             frame_num: frame_num,
             vp9: vp9,
+            cut,
+            max_bytes,
+            layers,
         })
     }
 
@@ -52,6 +104,11 @@ impl Ivf {
                  8 + (self.vp9 as i32),
                  self.input,
                  self.output);
+        // the segments of the cut list, from a first pass over the file
+        let plan = match self.cut {
+            Some(ref cut) => Some(cut.plan(&self.frame_infos()?).map_err(Error::other)?),
+            None => None,
+        };
         let mut fi = try!(File::open(self.input.clone()));
 
         let mut ivf_seq_buffer = [0u8; 32];
@@ -77,13 +134,35 @@ impl Ivf {
 
         let mut bs_container: Vec<Bitstream> = Vec::new();
         let mut pre_frame_no = 0;
+        let mut extractor = self.layers.as_ref().map(Extractor::new);
+        // frames written so far, which the split rule and the segment names
+        // count rather than the input frames when layers are dropped
+        let mut frame_no: i32 = 0;
         for cur_frame_no in 0..(total_frame_num as i32) {
-            let bs = match self.find_au_nal_units(&mut fi) {
+            let mut bs = match self.find_au_nal_units(&mut fi) {
                 Ok(bs) => bs,
                 Err(_) => {
                     break;
                 }
             };
+            if let Some(ref mut extractor) = extractor {
+                let data = extractor.extract(&bs.frame_data)
+                    .map_err(|e| Error::other(format!("frame {}: {}", cur_frame_no, e)))?;
+                match data {
+                    Some(data) => {
+                        bs.buf_size = data.len() as u32;
+                        bs.frame_header[0..4].copy_from_slice(&bs.buf_size.to_le_bytes());
+                        bs.idr_flag = is_key_frame(self.vp9, &data);
+                        bs.frame_data = data;
+                    }
+                    None => continue,
+                }
+                //bytes 12-15  width and height of the kept layers
+                if let Some((width, height)) = extractor.size {
+                    ivf_seq_buffer[12..14].copy_from_slice(&(width as u16).to_le_bytes());
+                    ivf_seq_buffer[14..16].copy_from_slice(&(height as u16).to_le_bytes());
+                }
+            }
 
             if bs.idr_flag {
                 print!("IDR");
@@ -91,9 +170,34 @@ impl Ivf {
                 print!(".");
             }
 
-            if bs.idr_flag && cur_frame_no - pre_frame_no >= (self.frame_num as i32) {
+            let frame = frame_no as usize;
+            let (starts_segment, in_segment) = match plan {
+                Some(ref plan) => {
+                    (plan.iter().any(|&(first, _)| first == frame),
+                     plan.iter().any(|&(first, end)| first <= frame && frame < end))
+                }
+                None => {
+                    let bytes: usize = bs_container.iter().map(|b| b.buf_size as usize).sum();
+                    (bs.idr_flag &&
+                     (frame_no - pre_frame_no >= (self.frame_num as i32) ||
+                      self.max_bytes.is_some_and(|max_bytes| bytes >= max_bytes)),
+                     true)
+                }
+            };
+            if plan.is_some() && (starts_segment || !in_segment) {
+                // the previous segment ends here, frames outside of the cut
+                // list are dropped
+                if !bs_container.is_empty() {
+                    self.write_to_file(&mut pre_frame_no,
+                                       frame_no,
+                                       &mut bs_container,
+                                       &mut ivf_seq_buffer)?;
+                    bs_container.clear();
+                }
+                pre_frame_no = frame_no;
+            } else if starts_segment {
                 if let Err(e) = self.write_to_file(&mut pre_frame_no,
-                                                   cur_frame_no,
+                                                   frame_no,
                                                    &mut bs_container,
                                                    &mut ivf_seq_buffer) {
                     return Err(e);
@@ -101,11 +205,35 @@ impl Ivf {
                 bs_container.clear();
             }
 
-            bs_container.push(bs);
+            if in_segment {
+                bs_container.push(bs);
+            }
+            frame_no += 1;
+        }
+
+        if let Some(extractor) = extractor {
+            println!("\n{} of {} layer frames kept, {} kept frames refer to slots of dropped \
+                      frames, {} shown frames lost",
+                     extractor.kept_frames,
+                     extractor.frames,
+                     extractor.stale_references,
+                     extractor.lost_pictures);
         }
 
+        if plan.is_some() {
+            if !bs_container.is_empty() {
+                let end = pre_frame_no + bs_container.len() as i32;
+                self.write_to_file(&mut pre_frame_no,
+                                   end,
+                                   &mut bs_container,
+                                   &mut ivf_seq_buffer)?;
+            }
+            return Ok(());
+        }
+
+        let end = if self.layers.is_some() { frame_no } else { total_frame_num as i32 };
         if let Err(e) = self.write_to_file(&mut pre_frame_no,
-                                           (total_frame_num as i32),
+                                           end,
                                            &mut bs_container,
                                            &mut ivf_seq_buffer) {
             return Err(e);
@@ -114,7 +242,7 @@ impl Ivf {
         Ok(())
     }
 
-    fn find_au_nal_units(&self, fp_bs: &mut io::Read) -> io::Result<Bitstream> {
+    pub fn find_au_nal_units(&self, fp_bs: &mut io::Read) -> io::Result<Bitstream> {
         let mut bs = Bitstream {
             frame_header: vec![0u8; 12],
             frame_location: vec![0u32; 0],
@@ -140,29 +268,44 @@ impl Ivf {
             return Err(Error::new(ErrorKind::Other, "bytes read buf size is not expected ..."));
         }
 
-        if !self.vp9 {
-            //VP8
-            let key_frame = bs.frame_data[0] & 0x1;
-            if key_frame == 0 {
-                bs.idr_flag = true;
-            } else {
-                bs.idr_flag = false;
-            }
-        } else {
-            //VP9
-            let show_existing_frame = bs.frame_data[0] & 0x8;
-            let key_frame = bs.frame_data[0] & 0x4;
-            if key_frame == 0 && show_existing_frame == 0 {
-                bs.idr_flag = true;
-            } else {
-                bs.idr_flag = false;
-            }
-        }
+        bs.idr_flag = is_key_frame(self.vp9, &bs.frame_data);
 
         Ok(bs)
     }
 
-    fn write_to_file(&self,
+    // the key frames, sizes and times of every frame
+    fn frame_infos(&self) -> io::Result<Vec<FrameInfo>> {
+        let mut fi = File::open(&self.input)?;
+        let mut header = [0u8; 32];
+        fi.read_exact(&mut header)?;
+        //bytes 16-23  time base of the frame timestamps, rate and scale
+        let rate = u32::from_le_bytes([header[16], header[17], header[18], header[19]]);
+        let scale = u32::from_le_bytes([header[20], header[21], header[22], header[23]]);
+        let time_base = if rate == 0 { 1.0 / 30.0 } else { scale as f64 / rate as f64 };
+
+        // the frames of the kept layers, as run writes them
+        let mut extractor = self.layers.as_ref().map(Extractor::new);
+        let mut frames = Vec::new();
+        while let Ok(mut bs) = self.find_au_nal_units(&mut fi) {
+            if let Some(ref mut extractor) = extractor {
+                match extractor.extract(&bs.frame_data).map_err(Error::other)? {
+                    Some(data) => bs.frame_data = data,
+                    None => continue,
+                }
+            }
+            //bytes 4-11   timestamp of the frame
+            let mut pts = [0u8; 8];
+            pts.copy_from_slice(&bs.frame_header[4..12]);
+            frames.push(FrameInfo {
+                key: is_key_frame(self.vp9, &bs.frame_data),
+                size: bs.frame_data.len(),
+                time: u64::from_le_bytes(pts) as f64 * time_base,
+            });
+        }
+        Ok(frames)
+    }
+
+    pub fn write_to_file(&self,
                      pre_frame_no: &mut i32,
                      cur_frame_no: i32,
                      bs_container: &mut [Bitstream],
@@ -180,7 +323,7 @@ impl Ivf {
 
         let mut fo = try!(File::create(output_ivf));
 
-        let frame_num = cur_frame_no - *pre_frame_no;
+        let frame_num = bs_container.len();
         ivf_seq_buffer[24] = ((frame_num >> 0) & 0xFF) as u8;
         ivf_seq_buffer[25] = ((frame_num >> 8) & 0xFF) as u8;
         ivf_seq_buffer[26] = ((frame_num >> 16) & 0xFF) as u8;
@@ -207,3 +350,16 @@ impl Ivf {
         Ok(())
     }
 }
+
+pub fn is_key_frame(vp9: bool, frame_data: &[u8]) -> bool {
+    if !vp9 {
+        //VP8
+        let key_frame = frame_data[0] & 0x1;
+        key_frame == 0
+    } else {
+        //VP9
+        let show_existing_frame = frame_data[0] & 0x8;
+        let key_frame = frame_data[0] & 0x4;
+        key_frame == 0 && show_existing_frame == 0
+    }
+}
@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Error, Read, Seek, SeekFrom, Write};
+use super::bin::Bin;
+use super::bits::{unescape, BitReader};
+use super::h264;
+use super::h265;
+use super::ivf::Ivf;
+use super::sizes::Codec;
+
+pub struct Join {
+    pub inputs: Vec<String>,
+    pub output: String,
+    pub codec: Codec,
+    // continue the IVF timestamps of each input from the one before
+    pub retime: bool,
+}
+
+// a VPS, SPS or PPS by NAL unit type and id, with the format an SPS gives
+struct ParameterSet {
+    key: (u8, u32),
+    format: Option<Format>,
+}
+
+// what the inputs must agree on: picture size, chroma_format_idc and bit
+// depth of the first SPS
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Format {
+    size: (u32, u32),
+    chroma_format_idc: u32,
+    bit_depth: u32,
+}
+
+impl Join {
+    pub fn helper() {
+        println!("Usage: rsplit join input1,input2,... output vp8|vp9|av1|h264|h265 [--retime]")
+    }
+
+    pub fn new(args: &[String]) -> Result<Join, &'static str> {
+        let mut retime = false;
+        let mut positional: Vec<String> = Vec::new();
+        for arg in args {
+            if arg == "--retime" {
+                retime = true;
+            } else if arg.starts_with("--") {
+                return Err("unknown option for rsplit join mode");
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        let args = &positional[..];
+
+        if args.len() < 5 {
+            return Err("too less arguments for rsplit join mode");
+        }
+
+        let codec = Codec::parse(&args[4])?;
+        if retime && (codec == Codec::H264 || codec == Codec::H265) {
+            return Err("only ivf inputs have timestamps to retime");
+        }
+
+        Ok(Join {
+            inputs: args[2].split(',').map(|s| s.to_string()).collect(),
+            output: args[3].clone(),
+            codec,
+            retime,
+        })
+    }
+
+    pub fn run(&self) -> io::Result<()> {
+        println!("rsplit join {} inputs into {}", self.inputs.len(), self.output);
+        // nothing is written unless all the inputs go together
+        match self.codec {
+            Codec::H264 | Codec::H265 => self.check_bin()?,
+            _ => self.check_ivf()?,
+        }
+        match self.codec {
+            Codec::H264 | Codec::H265 => self.join_bin(),
+            _ => self.join_ivf(),
+        }
+    }
+
+    // Annex-B segments one after the other, leaving out the parameter sets
+    // at the start of a segment that repeat the ones already written
+    fn join_bin(&self) -> io::Result<()> {
+        let h265 = self.codec == Codec::H265;
+        let bin = Bin::scanner("", h265);
+        let mut fo = BufWriter::new(File::create(&self.output)?);
+        // the last parameter set written for each NAL unit type and id
+        let mut written: HashMap<(u8, u32), Vec<u8>> = HashMap::new();
+        for input in &self.inputs {
+            let mut fi = File::open(input)?;
+            let mut leading = true;
+            let (mut au, mut repeated) = (0, 0);
+            loop {
+                let (eof, bs) = if h265 {
+                    bin.find_h265_nal_units(&mut fi)
+                } else {
+                    bin.find_h264_nal_units(&mut fi)
+                };
+                let bs = bs?;
+                for i in 0..bs.nal_size {
+                    let nal = bs.nal_unit(i);
+                    let err = |e| Error::other(format!("{}: access unit {}: {}", input, au, e));
+                    let parameter_set = if h265 {
+                        h265_parameter_set(nal)
+                    } else {
+                        h264_parameter_set(nal)
+                    };
+                    match parameter_set.map_err(err)? {
+                        Some(set) => {
+                            if leading && written.get(&set.key).is_some_and(|w| w[..] == *nal) {
+                                repeated += 1;
+                                continue;
+                            }
+                            written.insert(set.key, nal.to_vec());
+                        }
+                        None => {
+                            let nal_unit_type = if h265 {
+                                h265::nal_unit_type(nal)
+                            } else {
+                                h264::nal_unit_type(nal)
+                            };
+                            if (h265 && nal_unit_type < 32) ||
+                               (!h265 && (1..=5).contains(&nal_unit_type)) {
+                                leading = false;
+                            }
+                        }
+                    }
+                    let range = bs.frame_location[i] as usize..bs.frame_location[i + 1] as usize;
+                    fo.write_all(&bs.frame_data[range])?;
+                }
+                if bs.nal_size > 0 {
+                    au += 1;
+                }
+                if eof {
+                    break;
+                }
+            }
+            println!("{}: {} access units, {} repeated parameter sets left out",
+                     input,
+                     au,
+                     repeated);
+        }
+        fo.flush()?;
+        Ok(())
+    }
+
+    // the format of the first SPS of every input, which must come before any
+    // slice and be the same for all of them
+    fn check_bin(&self) -> io::Result<()> {
+        let h265 = self.codec == Codec::H265;
+        let bin = Bin::scanner("", h265);
+        let mut first_format: Option<Format> = None;
+        for input in &self.inputs {
+            let mut fi = File::open(input)?;
+            let mut format = None;
+            let mut slice = false;
+            while format.is_none() && !slice {
+                let (eof, bs) = if h265 {
+                    bin.find_h265_nal_units(&mut fi)
+                } else {
+                    bin.find_h264_nal_units(&mut fi)
+                };
+                let bs = bs?;
+                for i in 0..bs.nal_size {
+                    let nal = bs.nal_unit(i);
+                    let err = |e| Error::other(format!("{}: {}", input, e));
+                    let (parameter_set, vcl) = if h265 {
+                        (h265_parameter_set(nal), nal.len() >= 2 && h265::nal_unit_type(nal) < 32)
+                    } else {
+                        (h264_parameter_set(nal), (1..=5).contains(&h264::nal_unit_type(nal)))
+                    };
+                    match parameter_set.map_err(err)? {
+                        Some(set) => format = set.format,
+                        None => slice = vcl,
+                    }
+                    if format.is_some() || slice {
+                        break;
+                    }
+                }
+                if eof {
+                    break;
+                }
+            }
+            match format {
+                Some(format) => self.check_format(input, format, &mut first_format)?,
+                None => {
+                    return Err(Error::other(format!("{}: no SPS before the first slice, is it \
+                                                     H.26{}?",
+                                                    input,
+                                                    4 + h265 as i32)))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_format(&self,
+                    input: &str,
+                    format: Format,
+                    first_format: &mut Option<Format>)
+                    -> io::Result<()> {
+        let first = *first_format.get_or_insert(format);
+        if format != first {
+            let describe = |f: Format| {
+                format!("{}x{} chroma_format_idc {} {}-bit",
+                        f.size.0,
+                        f.size.1,
+                        f.chroma_format_idc,
+                        f.bit_depth)
+            };
+            return Err(Error::other(format!("{}: {} doesn't match {} of {}",
+                                            input,
+                                            describe(format),
+                                            describe(first),
+                                            self.inputs[0])));
+        }
+        Ok(())
+    }
+
+    // the headers of every input, which must have the same fourcc and size,
+    // and the same time base to retime them
+    fn check_ivf(&self) -> io::Result<()> {
+        let fourcc: &[u8] = match self.codec {
+            Codec::Vp8 => b"VP80",
+            Codec::Vp9 => b"VP90",
+            _ => b"AV01",
+        };
+        let mut first_header: Option<[u8; 32]> = None;
+        for input in &self.inputs {
+            let mut fi = File::open(input)?;
+            let mut header = [0u8; 32];
+            fi.read_exact(&mut header)?;
+            if &header[0..4] != b"DKIF" {
+                return Err(Error::other(format!("{}: Not supported IVF format ...", input)));
+            }
+            if &header[8..12] != fourcc {
+                return Err(Error::other(format!("{}: fourcc {} isn't {}",
+                                                input,
+                                                String::from_utf8_lossy(&header[8..12]),
+                                                String::from_utf8_lossy(fourcc))));
+            }
+            let first = *first_header.get_or_insert(header);
+            //bytes 12-15  width and height, 0 when unknown
+            let size = |h: &[u8; 32]| {
+                (u16::from_le_bytes([h[12], h[13]]), u16::from_le_bytes([h[14], h[15]]))
+            };
+            let (width, height) = size(&header);
+            if width != 0 && size(&first).0 != 0 && (width, height) != size(&first) {
+                return Err(Error::other(format!("{}: {}x{} doesn't match {}x{} of {}",
+                                                input,
+                                                width,
+                                                height,
+                                                size(&first).0,
+                                                size(&first).1,
+                                                self.inputs[0])));
+            }
+            if self.retime && header[16..24] != first[16..24] {
+                return Err(Error::other(format!("{}: time base doesn't match {}",
+                                                input,
+                                                self.inputs[0])));
+            }
+
+            // the frames must end with the file, a short one means the input
+            // is truncated or corrupt
+            let length = fi.metadata()?.len();
+            let (mut position, mut frames) = (32, 0);
+            while position < length {
+                let mut frame_header = [0u8; 12];
+                if position + 12 > length || fi.read_exact(&mut frame_header).is_err() {
+                    return Err(Error::other(format!("{}: frame {} header is cut short",
+                                                    input,
+                                                    frames)));
+                }
+                //bytes 0-3    size of frame in bytes (not including the 12-byte header)
+                let size = u32::from_le_bytes([frame_header[0],
+                                               frame_header[1],
+                                               frame_header[2],
+                                               frame_header[3]]);
+                position += 12 + size as u64;
+                if position > length {
+                    return Err(Error::other(format!("{}: frame {} is cut short", input, frames)));
+                }
+                fi.seek(SeekFrom::Start(position))?;
+                frames += 1;
+            }
+        }
+        Ok(())
+    }
+
+    // IVF segments under the header of the first one, with the frame count
+    // of them all
+    fn join_ivf(&self) -> io::Result<()> {
+        let ivf = Ivf::reader(self.codec == Codec::Vp9);
+        let mut fo = BufWriter::new(File::create(&self.output)?);
+        let mut frame_count: u32 = 0;
+        // the timestamp the next input starts at
+        let mut next_pts: Option<u64> = None;
+        for (n, input) in self.inputs.iter().enumerate() {
+            let mut fi = File::open(input)?;
+            let mut header = [0u8; 32];
+            fi.read_exact(&mut header)?;
+            if n == 0 {
+                fo.write_all(&header)?;
+            }
+
+            let mut offset: Option<i64> = None;
+            let (mut last_pts, mut step) = (None, 1);
+            let mut frames = 0;
+            let length = fi.metadata()?.len();
+            while fi.stream_position()? < length {
+                let mut bs = ivf.find_au_nal_units(&mut fi)
+                    .map_err(|e| Error::other(format!("{}: frame {}: {}", input, frames, e)))?;
+                //bytes 4-11   timestamp of the frame
+                let mut pts = [0u8; 8];
+                pts.copy_from_slice(&bs.frame_header[4..12]);
+                let mut pts = u64::from_le_bytes(pts);
+                if self.retime {
+                    let offset =
+                        *offset.get_or_insert(next_pts.map_or(0, |n| n as i64 - pts as i64));
+                    pts = (pts as i64 + offset).max(0) as u64;
+                    bs.frame_header[4..12].copy_from_slice(&pts.to_le_bytes());
+                }
+                if let Some(last) = last_pts {
+                    if pts > last {
+                        step = pts - last;
+                    }
+                }
+                last_pts = Some(pts);
+                fo.write_all(&bs.frame_header)?;
+                fo.write_all(&bs.frame_data)?;
+                frames += 1;
+            }
+            next_pts = last_pts.map(|last| last + step);
+            frame_count += frames;
+            println!("{}: {} frames", input, frames);
+        }
+
+        //bytes 24-27  number of frames in file
+        fo.seek(SeekFrom::Start(24))?;
+        fo.write_all(&frame_count.to_le_bytes())?;
+        fo.flush()?;
+        Ok(())
+    }
+}
+
+// the parameter set of a NAL unit, None for other NAL units
+fn h264_parameter_set(nal: &[u8]) -> Result<Option<ParameterSet>, &'static str> {
+    let nal_unit_type = h264::nal_unit_type(nal);
+    match nal_unit_type {
+        h264::NAL_SPS => {
+            let sps = h264::Sps::parse(&unescape(&nal[1..]))?;
+            let format = Format {
+                size: sps.size(),
+                chroma_format_idc: sps.chroma_format_idc,
+                bit_depth: sps.bit_depth_luma,
+            };
+            Ok(Some(ParameterSet {
+                key: (nal_unit_type, sps.seq_parameter_set_id),
+                format: Some(format),
+            }))
+        }
+        h264::NAL_PPS => {
+            let rbsp = unescape(&nal[1..]);
+            let pic_parameter_set_id = BitReader::new(&rbsp).ue()?;
+            Ok(Some(ParameterSet {
+                key: (nal_unit_type, pic_parameter_set_id),
+                format: None,
+            }))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn h265_parameter_set(nal: &[u8]) -> Result<Option<ParameterSet>, &'static str> {
+    if nal.len() < 2 || h265::nuh_layer_id(nal) != 0 {
+        return Ok(None);
+    }
+    let nal_unit_type = h265::nal_unit_type(nal);
+    let rbsp = unescape(&nal[2..]);
+    match nal_unit_type {
+        h265::NAL_VPS => {
+            let vps = h265::Vps::parse(&rbsp)?;
+            Ok(Some(ParameterSet {
+                key: (nal_unit_type, vps.vps_video_parameter_set_id),
+                format: None,
+            }))
+        }
+        h265::NAL_SPS => {
+            let sps = h265::Sps::parse(&rbsp)?;
+            let format = Format {
+                size: sps.size(),
+                chroma_format_idc: sps.chroma_format_idc,
+                bit_depth: sps.bit_depth_luma,
+            };
+            Ok(Some(ParameterSet {
+                key: (nal_unit_type, sps.sps_seq_parameter_set_id),
+                format: Some(format),
+            }))
+        }
+        h265::NAL_PPS => {
+            let pps_pic_parameter_set_id = BitReader::new(&rbsp).ue()?;
+            Ok(Some(ParameterSet {
+                key: (nal_unit_type, pps_pic_parameter_set_id),
+                format: None,
+            }))
+        }
+        _ => Ok(None),
+    }
+}
@@ -0,0 +1,202 @@
+// Minimal JSON reader, just enough for the model files consumed by vmaf.rs.
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, &'static str> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            pos: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos != parser.bytes.len() {
+            return Err("trailing characters after json value");
+        }
+        Ok(value)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref members) => members.iter().find(|m| m.0 == key).map(|m| &m.1),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Json::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Json::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match *self {
+            Json::Array(ref a) => Some(a),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.bytes.len() &&
+              (self.bytes[self.pos] == b' ' || self.bytes[self.pos] == b'\t' ||
+               self.bytes[self.pos] == b'\n' || self.bytes[self.pos] == b'\r') {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.bytes.get(self.pos).cloned()
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), &'static str> {
+        if self.bytes[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            Ok(())
+        } else {
+            Err("unexpected json token")
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, &'static str> {
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b't') => self.expect("true").map(|_| Json::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some(b'n') => self.expect("null").map(|_| Json::Null),
+            Some(_) => self.number(),
+            None => Err("unexpected end of json"),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, &'static str> {
+        self.pos += 1;
+        let mut members = Vec::new();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            if self.peek() != Some(b'"') {
+                return Err("json object key must be a string");
+            }
+            let key = self.string()?;
+            if self.peek() != Some(b':') {
+                return Err("missing ':' in json object");
+            }
+            self.pos += 1;
+            members.push((key, self.value()?));
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(members));
+                }
+                _ => return Err("missing ',' or '}' in json object"),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, &'static str> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err("missing ',' or ']' in json array"),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, &'static str> {
+        self.pos += 1;
+        let mut out: Vec<u8> = Vec::new();
+        while self.pos < self.bytes.len() {
+            let c = self.bytes[self.pos];
+            self.pos += 1;
+            match c {
+                b'"' => return String::from_utf8(out).map_err(|_| "invalid utf-8 in json string"),
+                b'\\' => {
+                    let e = match self.bytes.get(self.pos) {
+                        Some(&e) => e,
+                        None => return Err("unexpected end of json string"),
+                    };
+                    self.pos += 1;
+                    match e {
+                        b'n' => out.push(b'\n'),
+                        b't' => out.push(b'\t'),
+                        b'r' => out.push(b'\r'),
+                        b'b' => out.push(0x08),
+                        b'f' => out.push(0x0C),
+                        b'u' => {
+                            let hex = match self.bytes.get(self.pos..self.pos + 4) {
+                                Some(hex) => hex,
+                                None => return Err("invalid json unicode escape"),
+                            };
+                            let code = ::std::str::from_utf8(hex)
+                                .ok()
+                                .and_then(|h| u32::from_str_radix(h, 16).ok())
+                                .and_then(::std::char::from_u32)
+                                .unwrap_or('\u{FFFD}');
+                            self.pos += 4;
+                            let mut utf8 = [0u8; 4];
+                            out.extend_from_slice(code.encode_utf8(&mut utf8).as_bytes());
+                        }
+                        _ => out.push(e),
+                    }
+                }
+                _ => out.push(c),
+            }
+        }
+        Err("unterminated json string")
+    }
+
+    fn number(&mut self) -> Result<Json, &'static str> {
+        let start = self.pos;
+        while self.pos < self.bytes.len() &&
+              (self.bytes[self.pos].is_ascii_digit() || self.bytes[self.pos] == b'-' ||
+               self.bytes[self.pos] == b'+' || self.bytes[self.pos] == b'.' ||
+               self.bytes[self.pos] == b'e' || self.bytes[self.pos] == b'E') {
+            self.pos += 1;
+        }
+        ::std::str::from_utf8(&self.bytes[start..self.pos])
+            .ok()
+            .and_then(|n| n.parse::<f64>().ok())
+            .map(Json::Number)
+            .ok_or("invalid json number")
+    }
+}
@@ -1,6 +1,31 @@
+pub mod av1;
 pub mod bin;
+pub mod bits;
+pub mod convert;
+pub mod cut;
+pub mod diff;
+pub mod explode;
+pub mod extract;
+pub mod frame;
+pub mod h264;
+pub mod h265;
+pub mod heatmap;
+pub mod info;
 pub mod ivf;
+pub mod join;
+pub mod json;
+pub mod md5;
+pub mod params;
 pub mod psnr;
+pub mod report;
+pub mod sei;
+pub mod sizes;
+pub mod ssim;
+pub mod svc;
+pub mod vmaf;
+pub mod verify;
+pub mod vpx;
+pub mod vui;
 pub mod webm;
 pub mod yuv;
 
@@ -12,3 +37,41 @@ pub struct Bitstream {
     buf_size: u32,
     idr_flag: bool,
 }
+
+impl Bitstream {
+    // NAL unit i of an Annex-B access unit, without its start code
+    pub fn nal_unit(&self, i: usize) -> &[u8] {
+        let nal = &self.frame_data[self.frame_location[i] as usize..
+                                   self.frame_location[i + 1] as usize];
+        let start = nal.iter().position(|&b| b != 0).map_or(nal.len(), |p| p + 1);
+        &nal[start..]
+    }
+
+    // rebuilds the access unit from what rewrite returns for each NAL unit
+    // (without its start code), None drops the NAL unit
+    pub fn rewrite_nal_units<F>(&mut self, mut rewrite: F) -> Result<(), &'static str>
+        where F: FnMut(&[u8]) -> Result<Option<Vec<u8>>, &'static str>
+    {
+        let mut data = self.frame_data[..self.frame_location[0] as usize].to_vec();
+        let mut units = Vec::new();
+        for i in 0..self.nal_size {
+            let nal = self.nal_unit(i);
+            if let Some(nal) = rewrite(nal)? {
+                let start = self.frame_location[i] as usize;
+                let prefix = self.frame_location[i + 1] as usize - start - self.nal_unit(i).len();
+                units.push((data.len() as u32, self.frame_header[i]));
+                data.extend_from_slice(&self.frame_data[start..start + prefix]);
+                data.extend_from_slice(&nal);
+            }
+        }
+        for (i, &(location, header)) in units.iter().enumerate() {
+            self.frame_location[i] = location;
+            self.frame_header[i] = header;
+        }
+        self.nal_size = units.len();
+        self.frame_location[self.nal_size] = data.len() as u32;
+        self.buf_size = self.frame_location[self.nal_size] - self.frame_location[0];
+        self.frame_data = data;
+        Ok(())
+    }
+}
@@ -4,15 +4,27 @@ use std::env;
 use std::process;
 use rsplit::yuv::Yuv;
 use rsplit::ivf::Ivf;
+use rsplit::join::Join;
 use rsplit::webm::Webm;
 use rsplit::psnr::Psnr;
 use rsplit::bin::Bin;
+use rsplit::diff::Diff;
+use rsplit::extract::Extract;
+use rsplit::explode::Explode;
+use rsplit::convert::Convert;
+use rsplit::sizes::Sizes;
+use rsplit::info::Info;
+use rsplit::params::Params;
+use rsplit::verify::Verify;
+
+const USAGE: &str = "Usage: rsplit bin|convert|diff|explode|extract|info|ivf|join|params|psnr|\
+                     sizes|verify|webm|yuv ...";
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
         println!("too less arguments: {}", args.len());
-        println!("Usage: rsplit bin|ivf|psnr|webm|yuv ...");
+        println!("{}", USAGE);
     } else {
         if args[1] == "yuv" {
             let yuv = Yuv::new(&args).unwrap_or_else(|err| {
@@ -64,9 +76,101 @@ fn main() {
             if let Err(err) = webm.run() {
                 println!("{}", err);
             }
+        } else if args[1] == "diff" {
+            let diff = Diff::new(&args).unwrap_or_else(|err| {
+                println!("Problem parsing arguments: {}", err);
+                Diff::helper();
+                process::exit(1);
+            });
+
+            if let Err(err) = diff.run() {
+                println!("{}", err);
+            }
+        } else if args[1] == "convert" {
+            let convert = Convert::new(&args).unwrap_or_else(|err| {
+                println!("Problem parsing arguments: {}", err);
+                Convert::helper();
+                process::exit(1);
+            });
+
+            if let Err(err) = convert.run() {
+                println!("{}", err);
+            }
+        } else if args[1] == "sizes" {
+            let sizes = Sizes::new(&args).unwrap_or_else(|err| {
+                println!("Problem parsing arguments: {}", err);
+                Sizes::helper();
+                process::exit(1);
+            });
+
+            if let Err(err) = sizes.run() {
+                println!("{}", err);
+            }
+        } else if args[1] == "info" || args[1] == "probe" {
+            let info = Info::new(&args).unwrap_or_else(|err| {
+                println!("Problem parsing arguments: {}", err);
+                Info::helper();
+                process::exit(1);
+            });
+
+            if let Err(err) = info.run() {
+                println!("{}", err);
+            }
+        } else if args[1] == "params" {
+            let params = Params::new(&args).unwrap_or_else(|err| {
+                println!("Problem parsing arguments: {}", err);
+                Params::helper();
+                process::exit(1);
+            });
+
+            if let Err(err) = params.run() {
+                println!("{}", err);
+            }
+        } else if args[1] == "verify" {
+            let verify = Verify::new(&args).unwrap_or_else(|err| {
+                println!("Problem parsing arguments: {}", err);
+                Verify::helper();
+                process::exit(1);
+            });
+
+            // the exit status tells scripts whether the decode conforms
+            if let Err(err) = verify.run() {
+                println!("{}", err);
+                process::exit(1);
+            }
+        } else if args[1] == "extract" {
+            let extract = Extract::new(&args).unwrap_or_else(|err| {
+                println!("Problem parsing arguments: {}", err);
+                Extract::helper();
+                process::exit(1);
+            });
+
+            if let Err(err) = extract.run() {
+                println!("{}", err);
+            }
+        } else if args[1] == "join" {
+            let join = Join::new(&args).unwrap_or_else(|err| {
+                println!("Problem parsing arguments: {}", err);
+                Join::helper();
+                process::exit(1);
+            });
+
+            if let Err(err) = join.run() {
+                println!("{}", err);
+            }
+        } else if args[1] == "explode" {
+            let explode = Explode::new(&args).unwrap_or_else(|err| {
+                println!("Problem parsing arguments: {}", err);
+                Explode::helper();
+                process::exit(1);
+            });
+
+            if let Err(err) = explode.run() {
+                println!("{}", err);
+            }
         } else {
             println!("unsupported split {} mode", args[1]);
-            println!("Usage: rsplit bin|ivf|psnr|webm|yuv ...");
+            println!("{}", USAGE);
         }
     }
 }
@@ -43,6 +43,7 @@ struct input_ctx {
   unsigned int    chunk;
   unsigned int    chunks;
   unsigned int    video_track;
+  UInt64          tstamp;
 
   bool                 m_is_ivf;
   bool                 m_is_webm;
@@ -189,6 +190,7 @@ unsigned int get_one_frame_from_ivf(unsigned char **buf, struct input_ctx *input
 
   // Dec IVF header
   fread(&sIvfFrmHdr, sizeof(IVF_FRAME_HEADER), 1, input->infile);
+  input->tstamp = sIvfFrmHdr.timeStamp;
 
   if(input->pkt!=NULL){
     nestegg_free_packet(input->pkt);
@@ -236,6 +238,10 @@ unsigned int get_one_frame_from_webm(unsigned char **buf, struct input_ctx *inpu
 
     } while (track != input->video_track);
 
+    if (nestegg_packet_tstamp(input->pkt, &input->tstamp)){
+      input->tstamp = 0;
+    }
+
     if (nestegg_packet_count(input->pkt, &input->chunks)){
       if(!feof(input->infile)){
         printf("Read Buffer Failed!");
@@ -404,6 +410,12 @@ const unsigned char* vpx_read(void *userdata, unsigned int *nBufSize)
   }
 }
 
+unsigned long long vpx_tstamp(void *userdata)
+{
+  struct input_ctx *input = (struct input_ctx *)userdata;
+  return input != NULL ? input->tstamp : 0;
+}
+
 void vpx_destroy(void *userdata)
 {
   if(userdata!=NULL){
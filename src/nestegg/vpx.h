@@ -9,6 +9,10 @@ void* vpx_init(const char* filename);
 
 const unsigned char* vpx_read(void *input, unsigned int *length);
 
+/* timestamp of the packet the frame last read came from, in nanoseconds for
+   webm and in the time base of the file for ivf */
+unsigned long long vpx_tstamp(void *input);
+
 void vpx_destroy(void *input);
 
 #ifdef __cplusplus
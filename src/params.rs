@@ -0,0 +1,144 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Error;
+use super::bin::Bin;
+use super::bits::unescape;
+use super::h264;
+use super::h265;
+
+pub struct Params {
+    pub input: String,
+    pub h265: bool,
+    // every syntax element instead of the summary
+    pub all: bool,
+}
+
+impl Params {
+    pub fn helper() {
+        println!("Usage: rsplit params input.bin h264|h265 [--all]")
+    }
+
+    pub fn new(args: &[String]) -> Result<Params, &'static str> {
+        let mut all = false;
+        let mut positional: Vec<String> = Vec::new();
+        for arg in args {
+            if arg == "--all" {
+                all = true;
+            } else if arg.starts_with("--") {
+                return Err("unknown option for rsplit params mode");
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        let args = &positional[..];
+
+        if args.len() < 4 {
+            return Err("too less arguments for rsplit params mode");
+        }
+
+        let h265 = match args[3].to_lowercase().as_ref() {
+            "h265" => true,
+            "h264" => false,
+            _ => {
+                return Err("only support h264 and h265");
+            }
+        };
+
+        Ok(Params {
+            input: args[2].clone(),
+            h265,
+            all,
+        })
+    }
+
+    pub fn run(&self) -> io::Result<()> {
+        println!("rsplit params H26{} {}", 4 + (self.h265 as i32), self.input);
+        let bin = Bin::scanner(&self.input, self.h265);
+        let mut fi = File::open(&self.input)?;
+        // the PPS syntax of H.264 depends on its SPS
+        let mut h264_ps = h264::ParameterSets::new();
+        // parameter sets are repeated at every random access point, only the
+        // first copy of each is printed
+        let mut seen: HashSet<Vec<u8>> = HashSet::new();
+        let mut au = 0;
+        loop {
+            let (eof, bs) = if self.h265 {
+                bin.find_h265_nal_units(&mut fi)
+            } else {
+                bin.find_h264_nal_units(&mut fi)
+            };
+            let bs = bs?;
+            for i in 0..bs.nal_size {
+                let nal = bs.nal_unit(i);
+                if nal.len() < 2 || seen.contains(nal) {
+                    continue;
+                }
+                let printed = if self.h265 {
+                    self.print_h265(nal)
+                } else {
+                    self.print_h264(&mut h264_ps, nal)
+                };
+                if printed.map_err(|e| Error::other(format!("access unit {}: {}", au, e)))? {
+                    seen.insert(nal.to_vec());
+                }
+            }
+            if bs.nal_size > 0 {
+                au += 1;
+            }
+            if eof {
+                break;
+            }
+        }
+        println!("{} parameter sets in {} access units", seen.len(), au);
+        Ok(())
+    }
+
+    // whether the NAL unit was a parameter set
+    fn print_h264(&self, ps: &mut h264::ParameterSets, nal: &[u8]) -> Result<bool, &'static str> {
+        let rbsp = unescape(&nal[1..]);
+        match h264::nal_unit_type(nal) {
+            h264::NAL_SPS => {
+                let sps = h264::Sps::parse(&rbsp)?;
+                self.print(&sps);
+                ps.sps.insert(sps.seq_parameter_set_id, sps);
+            }
+            h264::NAL_PPS => {
+                let pps = h264::Pps::parse(&rbsp, &ps.sps)?;
+                self.print(&pps);
+                ps.pps.insert(pps.pic_parameter_set_id, pps);
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn print_h265(&self, nal: &[u8]) -> Result<bool, &'static str> {
+        let rbsp = unescape(&nal[2..]);
+        match h265::nal_unit_type(nal) {
+            h265::NAL_VPS => {
+                let vps = h265::Vps::parse(&rbsp)?;
+                self.print(&vps);
+            }
+            h265::NAL_SPS => {
+                let sps = h265::Sps::parse(&rbsp)?;
+                self.print(&sps);
+            }
+            h265::NAL_PPS => {
+                let pps = h265::Pps::parse(&rbsp)?;
+                self.print(&pps);
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn print<T: fmt::Display + fmt::Debug>(&self, set: &T) {
+        if self.all {
+            println!("{:#?}", set);
+        } else {
+            print!("{}", set);
+        }
+    }
+}
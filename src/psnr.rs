@@ -1,23 +1,215 @@
 use std::fs::File;
 use std::io;
-use std::io::Read;
-use std::io::{Error, ErrorKind};
+use std::io::Error;
+use std::mem;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use super::frame;
+use super::frame::{ChromaFormat, FrameReader};
+use super::heatmap::{Heatmap, HeatmapFormat};
+use super::ssim;
+use super::ssim::SsimWindow;
+use super::report::{Report, ReportFormat};
+use super::vmaf::{VmafExtractor, VmafFeatures, VmafFrame, VmafModel};
+
+const PSNR_COLUMNS: [&str; 4] = ["psnr_y", "psnr_u", "psnr_v", "psnr"];
+const MSE_COLUMNS: [&str; 3] = ["mse_y", "mse_u", "mse_v"];
+const SSIM_COLUMNS: [&str; 4] = ["ssim_y", "ssim_u", "ssim_v", "ssim"];
+const MS_SSIM_COLUMNS: [&str; 4] = ["ms_ssim_y", "ms_ssim_u", "ms_ssim_v", "ms_ssim"];
+const VIF_COLUMNS: [&str; 4] = ["vif_scale0", "vif_scale1", "vif_scale2", "vif_scale3"];
+
+// how per-plane values are combined into the overall one
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Weighting {
+    // weighted average of the per-plane values, e.g. 4:1:1, 6:1:1 or 1:0:0
+    Planes(f64, f64, f64),
+    // psnr of the mse over all samples of the frame
+    Mse,
+}
+
+impl Weighting {
+    pub fn parse(name: &str) -> Result<Weighting, &'static str> {
+        match name.to_lowercase().as_ref() {
+            "y" => return Ok(Weighting::Planes(1.0, 0.0, 0.0)),
+            "mse" => return Ok(Weighting::Mse),
+            _ => {}
+        }
+        let weights: Vec<f64> = match name.split(':').map(|w| w.parse::<f64>()).collect() {
+            Ok(weights) => weights,
+            Err(_) => return Err("invalid psnr weighting"),
+        };
+        if weights.len() != 3 || weights.iter().any(|w| *w < 0.0) ||
+           weights.iter().sum::<f64>() <= 0.0 {
+            return Err("psnr weighting must be y, mse or wy:wu:wv");
+        }
+        Ok(Weighting::Planes(weights[0], weights[1], weights[2]))
+    }
+}
+
+// search window, in frames, to match frames of input2 against input1
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Alignment {
+    // every frame is matched on its own, to detect dropped and repeated frames
+    PerFrame(usize),
+    // a single offset found on the first frames is used for the whole sequence
+    Global(usize),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Metric {
+    Psnr,
+    Ssim,
+    MsSsim,
+    Vmaf,
+}
 
 pub struct Psnr {
     pub input1_yuv: String,
     pub input2_yuv: String,
     pub frame_num: usize,
     pub frame_size: Vec<(i32, i32)>,
+    pub metrics: Vec<Metric>,
+    pub ssim_window: SsimWindow,
+    pub vmaf_model: Option<String>,
+    pub psnr_cap: f64,
+    pub report_format: Option<ReportFormat>,
+    pub report_file: Option<String>,
+    pub chroma_format: ChromaFormat,
+    // None is 4:1:1 for 4:2:0 and follows the plane sample counts otherwise
+    pub weighting: Option<Weighting>,
+    // first frame compared in each input, to skip encoder delay
+    pub offset: (usize, usize),
+    pub step: usize,
+    // (x, y, width, height) window of the luma plane to compare
+    pub crop: Option<(usize, usize, usize, usize)>,
+    pub align: Option<Alignment>,
+    pub heatmap: Option<Heatmap>,
+    pub threads: usize,
 }
 
 impl Psnr {
     pub fn helper() {
-        println!("Usage: rsplit psnr input1.yuv input2.yuv frame_num frame_size1 [...|frame_size2 \
-                  ...]")
+        println!("Usage: rsplit psnr input1.yuv|- input2.yuv|- frame_num frame_size1 \
+                  [...|frame_size2 ...]|@sizes.txt [--metric=psnr,ssim,msssim,vmaf|all] \
+                  [--ssim-window=8x8|gaussian] [--vmaf-model=model.json] [--psnr-cap=100] \
+                  [--report=csv|json] [--report-file=report.csv] \
+                  [--chroma=420|422|444] [--weighting=4:1:1|6:1:1|y|mse] \
+                  [--offset1=N] [--offset2=N] [--step=N] [--crop=WxH+X+Y] \
+                  [--align=N|--align-global=N] [--heatmap=prefix] [--heatmap-block=16] \
+                  [--heatmap-format=pgm|csv] [--threads=N]")
     }
 
     pub fn new(args: &[String]) -> Result<Psnr, &'static str> {
-        let l = args.len() as usize;
+        let mut metrics = vec![Metric::Psnr];
+        let mut ssim_window = SsimWindow::Block8x8;
+        let mut vmaf_model = None;
+        let mut psnr_cap = 100.0f64;
+        let mut report_format = None;
+        let mut report_file = None;
+        let mut chroma_format = ChromaFormat::Yuv420;
+        let mut weighting = None;
+        let mut offset = (0, 0);
+        let mut step = 1;
+        let mut crop = None;
+        let mut align = None;
+        let mut heatmap_prefix = None;
+        let mut heatmap_block = 16;
+        let mut heatmap_format = HeatmapFormat::Pgm;
+        let mut threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let mut positional: Vec<String> = Vec::new();
+        for arg in args {
+            if let Some(names) = arg.strip_prefix("--metric=") {
+                metrics.clear();
+                for name in names.split(',') {
+                    match name.to_lowercase().as_ref() {
+                        "psnr" => metrics.push(Metric::Psnr),
+                        "ssim" => metrics.push(Metric::Ssim),
+                        "msssim" | "ms-ssim" => metrics.push(Metric::MsSsim),
+                        "vmaf" => metrics.push(Metric::Vmaf),
+                        "all" => {
+                            metrics.extend_from_slice(&[Metric::Psnr, Metric::Ssim,
+                                                        Metric::MsSsim, Metric::Vmaf])
+                        }
+                        _ => {
+                            return Err("only support psnr, ssim, msssim, vmaf and all metrics");
+                        }
+                    }
+                }
+            } else if let Some(window) = arg.strip_prefix("--ssim-window=") {
+                ssim_window = match window.to_lowercase().as_ref() {
+                    "8x8" => SsimWindow::Block8x8,
+                    "gaussian" => SsimWindow::Gaussian,
+                    _ => {
+                        return Err("only support 8x8 and gaussian ssim windows");
+                    }
+                };
+            } else if let Some(model) = arg.strip_prefix("--vmaf-model=") {
+                vmaf_model = Some(model.to_string());
+            } else if let Some(cap) = arg.strip_prefix("--psnr-cap=") {
+                psnr_cap = match cap.parse::<f64>() {
+                    Ok(cap) if cap > 0.0 => cap,
+                    _ => {
+                        return Err("can't parse psnr cap as positive dB value");
+                    }
+                };
+            } else if let Some(format) = arg.strip_prefix("--report=") {
+                report_format = Some(ReportFormat::parse(format)?);
+            } else if let Some(file) = arg.strip_prefix("--report-file=") {
+                report_file = Some(file.to_string());
+            } else if let Some(chroma) = arg.strip_prefix("--chroma=") {
+                chroma_format = ChromaFormat::parse(chroma)?;
+            } else if let Some(weights) = arg.strip_prefix("--weighting=") {
+                weighting = Some(Weighting::parse(weights)?);
+            } else if let Some(n) = arg.strip_prefix("--offset1=") {
+                offset.0 = n.parse::<usize>().map_err(|_| "can't parse offset1 as usize")?;
+            } else if let Some(n) = arg.strip_prefix("--offset2=") {
+                offset.1 = n.parse::<usize>().map_err(|_| "can't parse offset2 as usize")?;
+            } else if let Some(n) = arg.strip_prefix("--step=") {
+                step = match n.parse::<usize>() {
+                    Ok(step) if step > 0 => step,
+                    _ => {
+                        return Err("can't parse step as positive usize");
+                    }
+                };
+            } else if let Some(window) = arg.strip_prefix("--crop=") {
+                crop = Some(parse_crop(window)?);
+            } else if let Some(n) = arg.strip_prefix("--align=") {
+                align = Some(Alignment::PerFrame(n.parse::<usize>()
+                    .map_err(|_| "can't parse align window as usize")?));
+            } else if let Some(n) = arg.strip_prefix("--align-global=") {
+                align = Some(Alignment::Global(n.parse::<usize>()
+                    .map_err(|_| "can't parse align window as usize")?));
+            } else if let Some(prefix) = arg.strip_prefix("--heatmap=") {
+                heatmap_prefix = Some(prefix.to_string());
+            } else if let Some(n) = arg.strip_prefix("--heatmap-block=") {
+                heatmap_block = match n.parse::<usize>() {
+                    Ok(block) if block > 0 => block,
+                    _ => {
+                        return Err("can't parse heatmap block size as positive usize");
+                    }
+                };
+            } else if let Some(format) = arg.strip_prefix("--heatmap-format=") {
+                heatmap_format = HeatmapFormat::parse(format)?;
+            } else if let Some(n) = arg.strip_prefix("--threads=") {
+                threads = match n.parse::<usize>() {
+                    Ok(threads) if threads > 0 => threads,
+                    _ => {
+                        return Err("can't parse threads as positive usize");
+                    }
+                };
+            } else if arg.starts_with("--") {
+                return Err("unknown option for rsplit psnr mode");
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        let args = &positional[..];
+        if vmaf_model.is_some() && !metrics.contains(&Metric::Vmaf) {
+            return Err("--vmaf-model needs --metric=vmaf or --metric=all");
+        }
+
+        let l = args.len();
         if l < 6 {
             return Err("too less arguments for rsplit psnr mode");
         }
@@ -32,133 +224,836 @@ impl Psnr {
             }
         };
 
-        let mut frame_size: Vec<(i32, i32)> = Vec::new();
-        for i in 0..frame_num {
-            if 5 + i >= l {
-                return Err("too less arguments for rsplit psnr mode");
-            } else if args[5 + i] == "..." {
-                if i == 0 {
-                    return Err("... can't be the first frame size");
-                } else {
-                    let (width, height) = frame_size[i - 1];
-                    for _ in i..frame_num {
-                        frame_size.push((width, height));
+        // the frames read past frame_num with the offsets and step, and past those
+        // as an aligned frame can drift by the window on every frame
+        let frames_read = match frame_num {
+            0 => 0,
+            _ => offset.0.max(offset.1) + (frame_num - 1) * step + 1,
+        };
+        let window = match align {
+            Some(Alignment::PerFrame(window)) | Some(Alignment::Global(window)) => window,
+            None => 0,
+        };
+        let frame_size = if args[5].starts_with('@') || args[5..].iter().any(|arg| arg == "...") {
+            frame::parse_frame_sizes(args, 5, frames_read + window * (frame_num + 1))?
+        } else if l - 5 < frames_read && frames_read > frame_num {
+            return Err("with --offset1, --offset2 or --step the frame sizes must cover every \
+                        frame read, offset + frame_num * step, or end with ...");
+        } else {
+            frame::parse_frame_sizes(args, 5, frames_read)?
+        };
+
+        if input1_yuv == "-" && input2_yuv == "-" {
+            return Err("only one input can be read from stdin");
+        }
+        if let Some(Alignment::Global(_)) = align {
+            if input1_yuv == "-" || input2_yuv == "-" {
+                return Err("--align-global reads the inputs twice, so they can't be stdin");
+            }
+            if frame_num == 0 {
+                return Err("--align-global needs frames to align, frame_num is 0");
+            }
+        }
+
+        Ok(Psnr {
+            input1_yuv,
+            input2_yuv,
+            frame_num,
+            frame_size,
+            metrics,
+            ssim_window,
+            vmaf_model,
+            psnr_cap,
+            report_format,
+            report_file,
+            chroma_format,
+            weighting,
+            offset,
+            step,
+            crop,
+            align,
+            heatmap: heatmap_prefix.map(|prefix| {
+                Heatmap {
+                    prefix,
+                    block_size: heatmap_block,
+                    format: heatmap_format,
+                }
+            }),
+            threads,
+        })
+    }
+
+    pub fn run(&self) -> io::Result<()> {
+        // a report written to stdout replaces the human readable lines
+        let verbose = self.report_format.is_none() || self.report_file.is_some();
+        if verbose {
+            println!("psnr {} vs {}", self.input1_yuv, self.input2_yuv);
+        }
+        let model = match self.vmaf_model {
+            Some(ref path) => Some(VmafModel::load(path)?),
+            None => None,
+        };
+
+        let offsets = match self.align {
+            Some(Alignment::Global(window)) => {
+                let (offset1, offset2) = self.global_alignment(window)?;
+                if verbose {
+                    println!("Aligned frame {:04} of {} with frame {:04} of {}",
+                             offset1,
+                             self.input1_yuv,
+                             offset2,
+                             self.input2_yuv);
+                }
+                (offset1, offset2)
+            }
+            _ => self.offset,
+        };
+        let window = match self.align {
+            Some(Alignment::PerFrame(window)) => window,
+            _ => 0,
+        };
+
+        // one thread reads and aligns the frames, the workers measure them in
+        // any order and this thread reports them in frame order
+        let (job_tx, job_rx) = mpsc::sync_channel::<Job>(2 * self.threads);
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let (result_tx, result_rx) = mpsc::channel();
+        // frame buffers go back to the reader once measured
+        let (recycle_tx, recycle_rx) = mpsc::channel();
+        thread::scope(|scope| {
+            let reader = scope.spawn(move || {
+                self.read_frames(offsets, window, job_tx, recycle_rx)
+            });
+            for _ in 0..self.threads {
+                let job_rx = Arc::clone(&job_rx);
+                let result_tx = result_tx.clone();
+                let recycle_tx = recycle_tx.clone();
+                scope.spawn(move || loop {
+                    let job = match job_rx.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        Err(_) => break,
+                    };
+                    let measured = self.measure(&job);
+                    let _ = recycle_tx.send(job.input1_buf);
+                    let _ = recycle_tx.send(job.input2_buf);
+                    if result_tx.send(measured).is_err() {
+                        break;
+                    }
+                });
+            }
+            // the workers hold the only other ends, so both channels close once
+            // the reader is done or the results are no longer wanted
+            drop(job_rx);
+            drop(result_tx);
+            drop(recycle_tx);
+
+            let collected = self.collect(result_rx, model, window, verbose);
+            match reader.join() {
+                Ok(Ok(())) => collected,
+                Ok(Err(e)) => Err(e),
+                Err(_) => Err(Error::other("psnr reader thread panicked")),
+            }
+        })
+    }
+
+    fn read_frames(&self,
+                   (offset1, offset2): (usize, usize),
+                   window: usize,
+                   jobs: mpsc::SyncSender<Job>,
+                   recycled: mpsc::Receiver<Vec<u8>>)
+                   -> io::Result<()> {
+        let mut f1 = FrameReader::open(&self.input1_yuv)?;
+        let mut f2 = FrameReader::open(&self.input2_yuv)?;
+        let mut cache1 = FrameCache::new(2 * window + self.step);
+        let mut next2 = 0;
+        let mut last_match: Option<usize> = None;
+
+        for i in 0..self.frame_num {
+            let index2 = offset2 + i * self.step;
+            self.skip_frames(&mut f2, next2, index2)?;
+            next2 = index2 + 1;
+
+            let (width, height) = self.size_of(index2)?;
+            let buf_size = self.chroma_format.frame_bytes(width, height);
+            let mut input2_buf = recycled.try_recv().unwrap_or_default();
+            if !f2.read_frame(&mut input2_buf, buf_size)? {
+                return Err(f2.end_of_input());
+            }
+
+            let expected = match last_match {
+                Some(m) => m + self.step,
+                None => offset1,
+            };
+            let index1 = if window > 0 {
+                // a repeated frame matches the last one again, so the search starts there
+                let first = expected.saturating_sub(window).max(last_match.unwrap_or(0));
+                let mut best: Option<(f64, usize)> = None;
+                for candidate in first..expected + window + 1 {
+                    // frames past the sizes aren't candidates
+                    if self.size_of(candidate).ok() != Some((width, height)) {
+                        continue;
+                    }
+                    let mse = match cache1.get(self, &mut f1, candidate)? {
+                        Some(buf) => luma_mse(buf, &input2_buf, width * height),
+                        None => break,
+                    };
+                    let closer = match best {
+                        Some((best_mse, best_index)) => {
+                            mse < best_mse ||
+                            (mse == best_mse &&
+                             candidate.abs_diff(expected) < best_index.abs_diff(expected))
+                        }
+                        None => true,
+                    };
+                    if closer {
+                        best = Some((mse, candidate));
+                    }
+                }
+                match best {
+                    Some((_, index1)) => index1,
+                    None => {
+                        return Err(Error::other(format!("no frame of {} to align with frame {} \
+                                                         of {}",
+                                                        self.input1_yuv,
+                                                        index2,
+                                                        self.input2_yuv)))
                     }
-                    break;
                 }
             } else {
-                let frame_size_str = args[5 + i].clone();
-                let nums: Vec<&str> = frame_size_str.split("x").collect();
-                if nums.len() != 2 {
-                    return Err("invalid frame size");
+                expected
+            };
+
+            if self.size_of(index1)? != (width, height) {
+                return Err(Error::other(format!("frame {} of {} and frame {} of {} differ in size",
+                                                index1,
+                                                self.input1_yuv,
+                                                index2,
+                                                self.input2_yuv)));
+            }
+            let mut input1_buf = recycled.try_recv().unwrap_or_default();
+            match cache1.get(self, &mut f1, index1)? {
+                Some(buf) => {
+                    input1_buf.clear();
+                    input1_buf.extend_from_slice(buf);
                 }
-                if let Ok(width) = nums[0].parse::<i32>() {
-                    if let Ok(height) = nums[1].parse::<i32>() {
-                        frame_size.push((width, height));
-                    } else {
-                        return Err("invalid frame height");
-                    }
+                None => return Err(f1.end_of_input()),
+            }
+            last_match = Some(index1);
+
+            let job = Job {
+                frame: i,
+                aligned: if window > 0 { Some(index1) } else { None },
+                width,
+                height,
+                input1_buf,
+                input2_buf,
+            };
+            // the workers only stop early on an error, which is reported instead
+            if jobs.send(job).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn measure(&self, job: &Job) -> io::Result<Measurement> {
+        let (frame, aligned) = (job.frame, job.aligned);
+        let cropped;
+        let (input1_buf, input2_buf, width, height) = match self.crop {
+            Some(window) => {
+                cropped = (self.crop_frame(&job.input1_buf, job.width, job.height, window)?,
+                           self.crop_frame(&job.input2_buf, job.width, job.height, window)?);
+                (&cropped.0[..], &cropped.1[..], window.2, window.3)
+            }
+            None => (&job.input1_buf[..], &job.input2_buf[..], job.width, job.height),
+        };
+        let planes = self.chroma_format.planes(width, height);
+        let mut m = Measurement {
+            frame,
+            aligned,
+            planes,
+            sse: [0; 3],
+            ssim: [0.0; 4],
+            ms_ssim: [0.0; 4],
+            vmaf: None,
+        };
+
+        if self.metrics.contains(&Metric::Psnr) {
+            for (p, &(offset, width, height)) in planes.iter().enumerate() {
+                let size = width * height;
+                m.sse[p] = sse(&input1_buf[offset..offset + size],
+                               &input2_buf[offset..offset + size]);
+            }
+        }
+
+        if let Some(ref heatmap) = self.heatmap {
+            let (sub_x, sub_y) = self.chroma_format.subsampling();
+            for (p, &(offset, width, height)) in planes.iter().enumerate() {
+                let block = if p == 0 {
+                    (heatmap.block_size, heatmap.block_size)
                 } else {
-                    return Err("invalid frame width");
+                    ((heatmap.block_size / sub_x).max(1), (heatmap.block_size / sub_y).max(1))
+                };
+                heatmap.write_plane(frame,
+                                    p,
+                                    &input1_buf[offset..],
+                                    &input2_buf[offset..],
+                                    (width, height),
+                                    block)?;
+            }
+        }
+
+        if self.metrics.contains(&Metric::Ssim) {
+            for (p, &(offset, width, height)) in planes.iter().enumerate() {
+                m.ssim[p] = ssim::ssim(&input1_buf[offset..],
+                                       &input2_buf[offset..],
+                                       width,
+                                       height,
+                                       self.ssim_window);
+            }
+            m.ssim[3] = self.combine(&m.ssim, &planes);
+        }
+
+        if self.metrics.contains(&Metric::MsSsim) {
+            for (p, &(offset, width, height)) in planes.iter().enumerate() {
+                m.ms_ssim[p] = ssim::ms_ssim(&input1_buf[offset..],
+                                             &input2_buf[offset..],
+                                             width,
+                                             height);
+            }
+            m.ms_ssim[3] = self.combine(&m.ms_ssim, &planes);
+        }
+
+        if self.metrics.contains(&Metric::Vmaf) {
+            m.vmaf = Some(VmafFrame::new(input1_buf, input2_buf, width, height));
+        }
+
+        Ok(m)
+    }
+
+    fn collect(&self,
+               results: mpsc::Receiver<io::Result<Measurement>>,
+               model: Option<VmafModel>,
+               window: usize,
+               verbose: bool)
+               -> io::Result<()> {
+        let mut total_psnr = [0.0f64; 4];
+        let mut total_sse = [0u64; 3];
+        let mut total_samples = [0usize; 3];
+        let mut total_ssim = [0.0f64; 4];
+        let mut total_ms_ssim = [0.0f64; 4];
+        let mut vmaf = VmafExtractor::new();
+        let mut report = Report::new();
+        let mut last_match: Option<usize> = None;
+        let mut dropped = 0;
+        let mut duplicated = 0;
+
+        // results arrive in the order the workers finish them
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        for result in results {
+            let m = result?;
+            pending.insert(m.frame, m);
+            while let Some(m) = pending.remove(&next) {
+                let i = next;
+                next += 1;
+
+                if let Some(index1) = m.aligned {
+                    report.set(i, "aligned_frame", index1 as f64);
+                    if let Some(last) = last_match {
+                        if index1 == last {
+                            duplicated += 1;
+                            if verbose {
+                                println!("Frame {:04}: repeats frame {:04} of {}",
+                                         i,
+                                         index1,
+                                         self.input1_yuv);
+                            }
+                        } else if index1 > last + self.step {
+                            dropped += index1 - last - self.step;
+                            if verbose {
+                                println!("Frame {:04}: dropped frames {:04}-{:04} of {}",
+                                         i,
+                                         last + self.step,
+                                         index1 - 1,
+                                         self.input1_yuv);
+                            }
+                        }
+                    }
+                    last_match = Some(index1);
+                }
+                let planes = m.planes;
+
+                if self.metrics.contains(&Metric::Psnr) {
+                    let mut psnr = [0.0f64; 4];
+                    let mut mse = [0.0f64; 3];
+                    for (p, &(_, width, height)) in planes.iter().enumerate() {
+                        let size = width * height;
+                        total_sse[p] += m.sse[p];
+                        total_samples[p] += size;
+                        mse[p] = m.sse[p] as f64 / size as f64;
+                        psnr[p] = self.psnr_from_mse(mse[p]);
+                    }
+                    psnr[3] = match self.weighting {
+                        Some(Weighting::Mse) => {
+                            let samples: f64 = (0..3).map(|p| sample_count(&planes, p)).sum();
+                            let sse: f64 = (0..3).map(|p| mse[p] * sample_count(&planes, p)).sum();
+                            self.psnr_from_mse(sse / samples)
+                        }
+                        _ => self.combine(&psnr, &planes),
+                    };
+                    accumulate(&mut total_psnr, &psnr);
+                    record(&mut report, i, &PSNR_COLUMNS, &psnr);
+                    record(&mut report, i, &MSE_COLUMNS, &mse);
+
+                    if verbose {
+                        println!("Frame {:04}: PSNR_Y:{:2.2}, PSNR_U:{:2.2}, PSNR_V:{:2.2}, \
+                                  PSNR:{:2.2}",
+                                 i,
+                                 psnr[0],
+                                 psnr[1],
+                                 psnr[2],
+                                 psnr[3]);
+                    }
+                }
+
+                if self.metrics.contains(&Metric::Ssim) {
+                    let ssim = m.ssim;
+                    accumulate(&mut total_ssim, &ssim);
+                    record(&mut report, i, &SSIM_COLUMNS, &ssim);
+
+                    if verbose {
+                        println!("Frame {:04}: SSIM_Y:{:1.4}, SSIM_U:{:1.4}, SSIM_V:{:1.4}, \
+                                  SSIM:{:1.4}",
+                                 i,
+                                 ssim[0],
+                                 ssim[1],
+                                 ssim[2],
+                                 ssim[3]);
+                    }
+                }
+
+                if self.metrics.contains(&Metric::MsSsim) {
+                    let ms_ssim = m.ms_ssim;
+                    accumulate(&mut total_ms_ssim, &ms_ssim);
+                    record(&mut report, i, &MS_SSIM_COLUMNS, &ms_ssim);
+
+                    if verbose {
+                        println!("Frame {:04}: MS-SSIM_Y:{:1.4}, MS-SSIM_U:{:1.4}, \
+                                  MS-SSIM_V:{:1.4}, MS-SSIM:{:1.4}",
+                                 i,
+                                 ms_ssim[0],
+                                 ms_ssim[1],
+                                 ms_ssim[2],
+                                 ms_ssim[3]);
+                    }
+                }
+
+                if let Some(frame) = m.vmaf {
+                    // motion compares against the previous frame, so it is only
+                    // known here where the frames are back in order
+                    let f = vmaf.push(frame);
+                    record(&mut report, i, &VIF_COLUMNS, &f.vif);
+                    report.set(i, "adm2", f.adm2);
+                    report.set(i, "motion", f.motion);
+
+                    if verbose {
+                        println!("Frame {:04}: VIF:{:1.4}/{:1.4}/{:1.4}/{:1.4}, ADM2:{:1.4}, \
+                                  MOTION:{:2.4}",
+                                 i,
+                                 f.vif[0],
+                                 f.vif[1],
+                                 f.vif[2],
+                                 f.vif[3],
+                                 f.adm2,
+                                 f.motion);
+                    }
                 }
             }
         }
+        // the reader stopped early, its error is reported instead
+        if next < self.frame_num {
+            return Err(Error::other("frames are missing from the psnr workers"));
+        }
 
-        Ok(Psnr {
-            input1_yuv: input1_yuv,
-            input2_yuv: input2_yuv,
-            frame_num: frame_num,
-            frame_size: frame_size,
-        })
+        // the model needs motion2, which looks one frame ahead
+        let mut vmaf_scores = Vec::new();
+        for (i, f) in vmaf.frames.iter().enumerate() {
+            report.set(i, "motion2", f.motion2);
+            if let Some(ref model) = model {
+                let score = model.predict(f);
+                report.set(i, "vmaf", score);
+                if verbose {
+                    println!("Frame {:04}: VMAF:{:2.4}", i, score);
+                }
+                vmaf_scores.push(score);
+            }
+        }
+
+        for total in [&mut total_psnr, &mut total_ssim, &mut total_ms_ssim].iter_mut() {
+            for t in total.iter_mut() {
+                *t /= self.frame_num as f64;
+            }
+        }
+
+        if verbose {
+            println!("=================================================================");
+            if window > 0 {
+                println!("Aligned {:04}: {} dropped, {} repeated frames", self.frame_num, dropped,
+                         duplicated);
+            }
+        }
+        if self.metrics.contains(&Metric::Psnr) {
+            // global psnr from the mse over all frames, as reference encoders report it
+            let mut global_psnr = [0.0f64; 4];
+            for p in 0..3 {
+                let samples = total_samples[p].max(1) as f64;
+                global_psnr[p] = self.psnr_from_mse(total_sse[p] as f64 / samples);
+            }
+            global_psnr[3] = match self.weighting {
+                Some(Weighting::Mse) => {
+                    self.psnr_from_mse(total_sse.iter().sum::<u64>() as f64 /
+                                       total_samples.iter().sum::<usize>().max(1) as f64)
+                }
+                _ => {
+                    let weights = self.weights(&total_samples.map(|n| n as f64));
+                    (0..3).map(|p| weights[p] * global_psnr[p]).sum::<f64>() /
+                    weights.iter().sum::<f64>()
+                }
+            };
+            for (column, value) in PSNR_COLUMNS.iter().zip(global_psnr.iter()) {
+                report.aggregate("global", column, *value);
+            }
+            if verbose {
+                println!("Total {:04}: PSNR_Y:{:2.2}, PSNR_U:{:2.2}, PSNR_V:{:2.2}, PSNR:{:2.2}",
+                         self.frame_num,
+                         total_psnr[0],
+                         total_psnr[1],
+                         total_psnr[2],
+                         total_psnr[3]);
+                println!("Global {:03}: PSNR_Y:{:2.2}, PSNR_U:{:2.2}, PSNR_V:{:2.2}, PSNR:{:2.2}",
+                         self.frame_num,
+                         global_psnr[0],
+                         global_psnr[1],
+                         global_psnr[2],
+                         global_psnr[3]);
+            }
+        }
+        if verbose && self.metrics.contains(&Metric::Ssim) {
+            println!("Total {:04}: SSIM_Y:{:1.4}, SSIM_U:{:1.4}, SSIM_V:{:1.4}, SSIM:{:1.4}",
+                     self.frame_num,
+                     total_ssim[0],
+                     total_ssim[1],
+                     total_ssim[2],
+                     total_ssim[3]);
+        }
+        if verbose && self.metrics.contains(&Metric::MsSsim) {
+            println!("Total {:04}: MS-SSIM_Y:{:1.4}, MS-SSIM_U:{:1.4}, MS-SSIM_V:{:1.4}, \
+                      MS-SSIM:{:1.4}",
+                     self.frame_num,
+                     total_ms_ssim[0],
+                     total_ms_ssim[1],
+                     total_ms_ssim[2],
+                     total_ms_ssim[3]);
+        }
+        if verbose && self.metrics.contains(&Metric::Vmaf) && !vmaf.frames.is_empty() {
+            let n = vmaf.frames.len() as f64;
+            let mean = |value: &dyn Fn(&VmafFeatures) -> f64| -> f64 {
+                vmaf.frames.iter().map(value).sum::<f64>() / n
+            };
+            println!("Total {:04}: VIF:{:1.4}/{:1.4}/{:1.4}/{:1.4}, ADM2:{:1.4}, MOTION2:{:2.4}",
+                     self.frame_num,
+                     mean(&|f| f.vif[0]),
+                     mean(&|f| f.vif[1]),
+                     mean(&|f| f.vif[2]),
+                     mean(&|f| f.vif[3]),
+                     mean(&|f| f.adm2),
+                     mean(&|f| f.motion2));
+        }
+        if !vmaf_scores.is_empty() {
+            let n = vmaf_scores.len() as f64;
+            let mean = vmaf_scores.iter().sum::<f64>() / n;
+            let harmonic = n / vmaf_scores.iter().map(|s| 1.0 / (s + 1.0)).sum::<f64>() - 1.0;
+            let min = vmaf_scores.iter().cloned().fold(f64::INFINITY, f64::min);
+            report.aggregate("hmean", "vmaf", harmonic);
+            if verbose {
+                println!("Total {:04}: VMAF:{:2.4}, VMAF_HMEAN:{:2.4}, VMAF_MIN:{:2.4}",
+                         self.frame_num,
+                         mean,
+                         harmonic,
+                         min);
+            }
+        }
+        if verbose {
+            println!();
+        }
+
+        if let Some(format) = self.report_format {
+            match self.report_file {
+                Some(ref path) => report.write(&mut File::create(path)?, format)?,
+                None => report.write(&mut io::stdout(), format)?,
+            }
+        }
+
+        Ok(())
     }
 
-    pub fn run(&self) -> io::Result<()> {
-        println!("psnr {} vs {}", self.input1_yuv, self.input2_yuv);
-        let mut f1 = try!(File::open(self.input1_yuv.clone()));
-        let mut f2 = try!(File::open(self.input2_yuv.clone()));
+    // (offset1, offset2) within +/- window frames of the requested offsets
+    // with the lowest luma mse over the first few frames
+    fn global_alignment(&self, window: usize) -> io::Result<(usize, usize)> {
+        const ALIGNMENT_FRAMES: usize = 5;
+        let frames = self.frame_num.clamp(1, ALIGNMENT_FRAMES);
+        let mut f1 = FrameReader::open(&self.input1_yuv)?;
+        let mut f2 = FrameReader::open(&self.input2_yuv)?;
+        let mut cache1 = FrameCache::new(2 * window + frames * self.step + 1);
+        let mut cache2 = FrameCache::new(2 * window + frames * self.step + 1);
 
-        let mut total_psnr_y = 0.0;
-        let mut total_psnr_u = 0.0;
-        let mut total_psnr_v = 0.0;
-        let mut total_psnr = 0.0;
+        let mut best: Option<(f64, (usize, usize))> = None;
+        for shift in 0..2 * window + 1 {
+            let (offset1, offset2) = if self.offset.0 + shift >= window {
+                (self.offset.0 + shift - window, self.offset.1)
+            } else {
+                (0, self.offset.1 + window - shift - self.offset.0)
+            };
 
-        for i in 0..self.frame_num {
-            let buf_size = (self.frame_size[i].0 * self.frame_size[i].1 +
-                            ((self.frame_size[i].0 + 1) / 2) *
-                            ((self.frame_size[i].1 + 1) / 2) * 2) as
-                           usize;
-            let mut input1_buf = vec![0u8; buf_size];
-            let mut input2_buf = vec![0u8; buf_size];
-
-            let bytes_read1 = f1.read(&mut input1_buf).unwrap();
-            if bytes_read1 != buf_size {
-                return Err(Error::new(ErrorKind::Other, "bytes read1 is not expected ..."));
-            }
-            let bytes_read2 = f2.read(&mut input2_buf).unwrap();
-            if bytes_read2 != buf_size {
-                return Err(Error::new(ErrorKind::Other, "bytes read2 is not expected ..."));
-            }
-
-            let mut mse_y = 0.0f64;
-            let mut mse_u = 0.0f64;
-            let mut mse_v = 0.0f64;
-
-            for j in 0..(self.frame_size[i].0 * self.frame_size[i].1) {
-                let org = input1_buf[j as usize] as f64;
-                let rec = input2_buf[j as usize] as f64;
-                mse_y += (org - rec) * (org - rec);
-            }
-            for j in (self.frame_size[i].0 * self.frame_size[i].1)..
-                     (self.frame_size[i].0 * self.frame_size[i].1 +
-                      ((self.frame_size[i].0 + 1) / 2) * ((self.frame_size[i].1 + 1) / 2)) {
-                let org = input1_buf[j as usize] as f64;
-                let rec = input2_buf[j as usize] as f64;
-                mse_u += (org - rec) * (org - rec);
-            }
-            for j in (self.frame_size[i].0 * self.frame_size[i].1 +
-                      ((self.frame_size[i].0 + 1) / 2) * ((self.frame_size[i].1 + 1) / 2))..
-                     (self.frame_size[i].0 * self.frame_size[i].1 +
-                      ((self.frame_size[i].0 + 1) / 2) * ((self.frame_size[i].1 + 1) / 2) * 2) {
-                let org = input1_buf[j as usize] as f64;
-                let rec = input2_buf[j as usize] as f64;
-                mse_v += (org - rec) * (org - rec);
-            }
-
-            mse_y /= (self.frame_size[i].0 * self.frame_size[i].1) as f64;
-            mse_u /= (((self.frame_size[i].0 + 1) / 2) * ((self.frame_size[i].1 + 1) / 2)) as f64;
-            mse_v /= (((self.frame_size[i].0 + 1) / 2) * ((self.frame_size[i].1 + 1) / 2)) as f64;
-
-            let psnr_y = 10.0f64 * ((255.0f64 * 255.0f64) / mse_y).log10();
-            let psnr_u = 10.0f64 * ((255.0f64 * 255.0f64) / mse_u).log10();
-            let psnr_v = 10.0f64 * ((255.0f64 * 255.0f64) / mse_v).log10();
-            let psnr = (4.0f64 * psnr_y + psnr_u + psnr_v) / 6.0f64;
-
-            total_psnr_y += psnr_y;
-            total_psnr_u += psnr_u;
-            total_psnr_v += psnr_v;
-            total_psnr += psnr;
-
-            println!("Frame {:04}: PSNR_Y:{:2.2}, PSNR_U:{:2.2}, PSNR_V:{:2.2}, PSNR:{:2.2}",
-                     i,
-                     psnr_y,
-                     psnr_u,
-                     psnr_v,
-                     psnr);
-        }
-
-        total_psnr_y /= self.frame_num as f64;
-        total_psnr_u /= self.frame_num as f64;
-        total_psnr_v /= self.frame_num as f64;
-        total_psnr /= self.frame_num as f64;
-
-        println!("=================================================================");
-        println!("Total {:04}: PSNR_Y:{:2.2}, PSNR_U:{:2.2}, PSNR_V:{:2.2}, PSNR:{:2.2}\n",
-                 self.frame_num,
-                 total_psnr_y,
-                 total_psnr_u,
-                 total_psnr_v,
-                 total_psnr);
+            let mut total = 0.0;
+            let mut count = 0;
+            for k in 0..frames {
+                let (index1, index2) = (offset1 + k * self.step, offset2 + k * self.step);
+                let (width, height) = match self.size_of(index2) {
+                    Ok(size) => size,
+                    Err(_) => break,
+                };
+                if self.size_of(index1).ok() != Some((width, height)) {
+                    continue;
+                }
+                let buf2 = match cache2.get(self, &mut f2, index2)? {
+                    Some(buf) => buf.to_vec(),
+                    None => break,
+                };
+                if let Some(buf1) = cache1.get(self, &mut f1, index1)? {
+                    total += luma_mse(buf1, &buf2, width * height);
+                    count += 1;
+                }
+            }
+            if count > 0 {
+                let mse = total / count as f64;
+                if best.is_none_or(|(best_mse, _)| mse < best_mse) {
+                    best = Some((mse, (offset1, offset2)));
+                }
+            }
+        }
 
+        match best {
+            Some((_, offsets)) => Ok(offsets),
+            None => Err(Error::other("no frames to align in the search window")),
+        }
+    }
+
+    fn size_of(&self, index: usize) -> io::Result<(usize, usize)> {
+        match self.frame_size.get(index) {
+            Some(&(width, height)) => Ok((width as usize, height as usize)),
+            None => Err(Error::other(format!("no frame size for frame {}", index))),
+        }
+    }
+
+    fn skip_frames(&self, f: &mut FrameReader, from: usize, to: usize) -> io::Result<()> {
+        for index in from..to {
+            let (width, height) = self.size_of(index)?;
+            if !f.skip_frame(self.chroma_format.frame_bytes(width, height))? {
+                return Err(f.end_of_input());
+            }
+        }
         Ok(())
     }
+
+    fn crop_frame(&self,
+                  buf: &[u8],
+                  width: usize,
+                  height: usize,
+                  window: (usize, usize, usize, usize))
+                  -> io::Result<Vec<u8>> {
+        let (x, y, crop_width, crop_height) = window;
+        if x + crop_width > width || y + crop_height > height {
+            return Err(Error::other("crop window is outside of the frame"));
+        }
+
+        let (sub_x, sub_y) = self.chroma_format.subsampling();
+        if x % sub_x != 0 || y % sub_y != 0 {
+            return Err(Error::other("crop window must start on a chroma sample"));
+        }
+
+        let mut out = Vec::with_capacity(self.chroma_format.frame_bytes(crop_width, crop_height));
+        let src = self.chroma_format.planes(width, height);
+        let dst = self.chroma_format.planes(crop_width, crop_height);
+        for p in 0..3 {
+            let (offset, stride, _) = src[p];
+            let (_, w, h) = dst[p];
+            let (px, py) = if p == 0 { (x, y) } else { (x / sub_x, y / sub_y) };
+            for row in py..py + h {
+                let start = offset + row * stride + px;
+                out.extend_from_slice(&buf[start..start + w]);
+            }
+        }
+        Ok(out)
+    }
+
+    fn weights(&self, samples: &[f64; 3]) -> [f64; 3] {
+        match self.weighting {
+            Some(Weighting::Planes(y, u, v)) => [y, u, v],
+            _ if self.chroma_format == ChromaFormat::Yuv420 => [4.0, 1.0, 1.0],
+            _ => *samples,
+        }
+    }
+
+    fn combine(&self, values: &[f64; 4], planes: &[(usize, usize, usize); 3]) -> f64 {
+        let samples = [sample_count(planes, 0), sample_count(planes, 1), sample_count(planes, 2)];
+        let weights = self.weights(&samples);
+        (0..3).map(|p| weights[p] * values[p]).sum::<f64>() / weights.iter().sum::<f64>()
+    }
+
+    // identical planes have zero mse, which is reported as the cap instead of inf
+    fn psnr_from_mse(&self, mse: f64) -> f64 {
+        if mse <= 0.0 {
+            self.psnr_cap
+        } else {
+            (10.0f64 * ((255.0f64 * 255.0f64) / mse).log10()).min(self.psnr_cap)
+        }
+    }
+}
+
+// a pair of frames handed from the reader to the workers
+struct Job {
+    frame: usize,
+    // frame of input1 picked by the per-frame alignment
+    aligned: Option<usize>,
+    width: usize,
+    height: usize,
+    input1_buf: Vec<u8>,
+    input2_buf: Vec<u8>,
+}
+
+// what a worker measured on a pair of frames, reported in frame order
+struct Measurement {
+    frame: usize,
+    aligned: Option<usize>,
+    planes: [(usize, usize, usize); 3],
+    sse: [u64; 3],
+    ssim: [f64; 4],
+    ms_ssim: [f64; 4],
+    vmaf: Option<VmafFrame>,
+}
+
+// frames of one input read ahead or kept back for the alignment search
+struct FrameCache {
+    next: usize,
+    capacity: usize,
+    frames: VecDeque<(usize, Vec<u8>)>,
+    // the last evicted frame, read into again
+    spare: Vec<u8>,
+}
+
+impl FrameCache {
+    fn new(capacity: usize) -> FrameCache {
+        FrameCache {
+            next: 0,
+            capacity: capacity.max(1),
+            frames: VecDeque::new(),
+            spare: Vec::new(),
+        }
+    }
+
+    // None once the input ends before the frame
+    fn get(&mut self,
+           psnr: &Psnr,
+           f: &mut FrameReader,
+           index: usize)
+           -> io::Result<Option<&[u8]>> {
+        if index < self.next {
+            return match self.frames.iter().position(|frame| frame.0 == index) {
+                Some(pos) => Ok(Some(&self.frames[pos].1)),
+                None => Err(Error::other("frame is no longer in the alignment window")),
+            };
+        }
+
+        // frames too far before the requested one would be evicted right away
+        let first = self.next.max((index + 1).saturating_sub(self.capacity));
+        psnr.skip_frames(f, self.next, first)?;
+        self.next = first;
+        while self.next <= index {
+            let (width, height) = psnr.size_of(self.next)?;
+            let mut buf = mem::take(&mut self.spare);
+            if !f.read_frame(&mut buf, psnr.chroma_format.frame_bytes(width, height))? {
+                self.spare = buf;
+                return Ok(None);
+            }
+            if self.frames.len() == self.capacity {
+                if let Some((_, evicted)) = self.frames.pop_front() {
+                    self.spare = evicted;
+                }
+            }
+            self.frames.push_back((self.next, buf));
+            self.next += 1;
+        }
+        Ok(self.frames.back().map(|frame| &frame.1[..]))
+    }
+}
+
+fn luma_mse(org: &[u8], rec: &[u8], size: usize) -> f64 {
+    sse(&org[..size], &rec[..size]) as f64 / size as f64
+}
+
+// sum of squared differences in integers, so the inner loop vectorizes; runs
+// are summed in u32, which can't overflow for 4096 samples of 255^2
+fn sse(org: &[u8], rec: &[u8]) -> u64 {
+    const RUN: usize = 4096;
+    org.chunks(RUN)
+        .zip(rec.chunks(RUN))
+        .map(|(a, b)| {
+            a.iter()
+                .zip(b.iter())
+                .map(|(&a, &b)| {
+                    let diff = (a as i32 - b as i32).unsigned_abs();
+                    diff * diff
+                })
+                .sum::<u32>() as u64
+        })
+        .sum()
+}
+
+// WxH+X+Y, e.g. 1920x1080+0+0 to drop the padding of a 1920x1088 frame
+fn parse_crop(window: &str) -> Result<(usize, usize, usize, usize), &'static str> {
+    let parts: Vec<&str> = window.split('+').collect();
+    if parts.len() != 3 {
+        return Err("crop window must be WxH+X+Y");
+    }
+    let size: Vec<&str> = parts[0].split('x').collect();
+    if size.len() != 2 {
+        return Err("crop window must be WxH+X+Y");
+    }
+    match (size[0].parse::<usize>(),
+           size[1].parse::<usize>(),
+           parts[1].parse::<usize>(),
+           parts[2].parse::<usize>()) {
+        (Ok(width), Ok(height), Ok(x), Ok(y)) if width > 0 && height > 0 => {
+            Ok((x, y, width, height))
+        }
+        _ => Err("invalid crop window"),
+    }
+}
+
+fn sample_count(planes: &[(usize, usize, usize); 3], p: usize) -> f64 {
+    (planes[p].1 * planes[p].2) as f64
+}
+
+fn record(report: &mut Report, frame: usize, columns: &[&str], values: &[f64]) {
+    for (column, value) in columns.iter().zip(values.iter()) {
+        report.set(frame, column, *value);
+    }
+}
+
+fn accumulate(total: &mut [f64; 4], frame: &[f64; 4]) {
+    for (t, f) in total.iter_mut().zip(frame.iter()) {
+        *t += *f;
+    }
 }
@@ -0,0 +1,172 @@
+// Per-frame metric table with summary statistics, written as CSV or JSON so
+// regression scripts don't have to scrape the human readable output.
+
+use std::io;
+use std::io::Write;
+
+const STATISTICS: [&str; 6] = ["mean", "min", "max", "p5", "p50", "p95"];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ReportFormat {
+    Csv,
+    Json,
+}
+
+impl ReportFormat {
+    pub fn parse(name: &str) -> Result<ReportFormat, &'static str> {
+        match name.to_lowercase().as_ref() {
+            "csv" => Ok(ReportFormat::Csv),
+            "json" => Ok(ReportFormat::Json),
+            _ => Err("only support csv and json output formats"),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Report {
+    columns: Vec<String>,
+    rows: Vec<(usize, Vec<Option<f64>>)>,
+    // extra aggregates that aren't derived from the rows, e.g. global psnr
+    aggregates: Vec<(String, String, f64)>,
+}
+
+impl Report {
+    pub fn new() -> Report {
+        Report::default()
+    }
+
+    pub fn set(&mut self, frame: usize, column: &str, value: f64) {
+        let c = match self.columns.iter().position(|n| n == column) {
+            Some(c) => c,
+            None => {
+                self.columns.push(column.to_string());
+                self.columns.len() - 1
+            }
+        };
+        let r = match self.rows.iter().rposition(|row| row.0 == frame) {
+            Some(r) => r,
+            None => {
+                self.rows.push((frame, Vec::new()));
+                self.rows.len() - 1
+            }
+        };
+        let values = &mut self.rows[r].1;
+        if values.len() <= c {
+            values.resize(c + 1, None);
+        }
+        values[c] = Some(value);
+    }
+
+    pub fn aggregate(&mut self, statistic: &str, column: &str, value: f64) {
+        if !self.columns.iter().any(|n| n == column) {
+            self.columns.push(column.to_string());
+        }
+        self.aggregates.push((statistic.to_string(), column.to_string(), value));
+    }
+
+    fn column(&self, c: usize) -> Vec<f64> {
+        self.rows.iter().filter_map(|row| row.1.get(c).cloned().unwrap_or(None)).collect()
+    }
+
+    fn statistic(values: &[f64], statistic: &str) -> Option<f64> {
+        if values.is_empty() {
+            return None;
+        }
+        let mut sorted = values.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal));
+        match statistic {
+            "mean" => Some(values.iter().sum::<f64>() / values.len() as f64),
+            "min" => Some(sorted[0]),
+            "max" => Some(sorted[sorted.len() - 1]),
+            _ => {
+                // linear interpolation between the closest ranks
+                let p = statistic[1..].parse::<f64>().unwrap_or(50.0) / 100.0;
+                let rank = p * (sorted.len() - 1) as f64;
+                let lo = rank.floor() as usize;
+                let hi = rank.ceil() as usize;
+                Some(sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f64))
+            }
+        }
+    }
+
+    // (statistic, value per column) for the derived statistics and the aggregates
+    fn summary(&self) -> Vec<(String, Vec<Option<f64>>)> {
+        let mut summary = Vec::new();
+        let columns: Vec<Vec<f64>> = (0..self.columns.len()).map(|c| self.column(c)).collect();
+        for statistic in STATISTICS.iter() {
+            summary.push((statistic.to_string(),
+                          columns.iter().map(|v| Report::statistic(v, statistic)).collect()));
+        }
+        for &(ref statistic, ref column, value) in &self.aggregates {
+            let c = self.columns.iter().position(|n| n == column).unwrap_or(0);
+            let r = match summary.iter().position(|s| s.0 == *statistic) {
+                Some(r) => r,
+                None => {
+                    summary.push((statistic.clone(), vec![None; self.columns.len()]));
+                    summary.len() - 1
+                }
+            };
+            summary[r].1[c] = Some(value);
+        }
+        summary
+    }
+
+    pub fn write(&self, out: &mut dyn Write, format: ReportFormat) -> io::Result<()> {
+        match format {
+            ReportFormat::Csv => self.write_csv(out),
+            ReportFormat::Json => self.write_json(out),
+        }
+    }
+
+    fn write_csv(&self, out: &mut dyn Write) -> io::Result<()> {
+        let csv_value = |v: Option<f64>| v.map(|v| format!("{:.6}", v)).unwrap_or_default();
+
+        writeln!(out, "frame,{}", self.columns.join(","))?;
+        for &(frame, ref values) in &self.rows {
+            let cells: Vec<String> = (0..self.columns.len())
+                .map(|c| csv_value(values.get(c).cloned().unwrap_or(None)))
+                .collect();
+            writeln!(out, "{},{}", frame, cells.join(","))?;
+        }
+        for (statistic, values) in self.summary() {
+            let cells: Vec<String> = values.into_iter().map(csv_value).collect();
+            writeln!(out, "{},{}", statistic, cells.join(","))?;
+        }
+        Ok(())
+    }
+
+    fn write_json(&self, out: &mut dyn Write) -> io::Result<()> {
+        let json_value = |v: Option<f64>| match v {
+            Some(v) if v.is_finite() => format!("{}", v),
+            _ => "null".to_string(),
+        };
+
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"frames\": [")?;
+        for (r, &(frame, ref values)) in self.rows.iter().enumerate() {
+            let mut fields = vec![format!("\"frame\": {}", frame)];
+            for (c, name) in self.columns.iter().enumerate() {
+                if let Some(v) = values.get(c).cloned().unwrap_or(None) {
+                    fields.push(format!("\"{}\": {}", name, json_value(Some(v))));
+                }
+            }
+            let comma = if r + 1 < self.rows.len() { "," } else { "" };
+            writeln!(out, "    {{{}}}{}", fields.join(", "), comma)?;
+        }
+        writeln!(out, "  ],")?;
+
+        let summary = self.summary();
+        writeln!(out, "  \"summary\": {{")?;
+        for (c, name) in self.columns.iter().enumerate() {
+            let fields: Vec<String> = summary.iter()
+                .filter(|s| s.1[c].is_some())
+                .map(|s| format!("\"{}\": {}", s.0, json_value(s.1[c])))
+                .collect();
+            let comma = if c + 1 < self.columns.len() { "," } else { "" };
+            writeln!(out, "    \"{}\": {{{}}}{}", name, fields.join(", "), comma)?;
+        }
+        writeln!(out, "  }}")?;
+        writeln!(out, "}}")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+// SEI messages of H.264 and H.265: the sei_message() framing both share and
+// the payloads that are coded the same way in both.
+
+use std::fmt;
+use super::bits::BitReader;
+
+pub const BUFFERING_PERIOD: u32 = 0;
+pub const PIC_TIMING: u32 = 1;
+pub const USER_DATA_UNREGISTERED: u32 = 5;
+pub const RECOVERY_POINT: u32 = 6;
+pub const DECODING_UNIT_INFO: u32 = 130;
+pub const DECODED_PICTURE_HASH: u32 = 132;
+pub const MASTERING_DISPLAY_COLOUR_VOLUME: u32 = 137;
+pub const CONTENT_LIGHT_LEVEL_INFO: u32 = 144;
+
+pub fn payload_type_name(payload_type: u32) -> &'static str {
+    match payload_type {
+        0 => "buffering_period",
+        1 => "pic_timing",
+        2 => "pan_scan_rect",
+        3 => "filler_payload",
+        4 => "user_data_registered_itu_t_t35",
+        5 => "user_data_unregistered",
+        6 => "recovery_point",
+        45 => "frame_packing_arrangement",
+        47 => "display_orientation",
+        129 => "active_parameter_sets",
+        130 => "decoding_unit_info",
+        132 => "decoded_picture_hash",
+        136 => "time_code",
+        137 => "mastering_display_colour_volume",
+        144 => "content_light_level_info",
+        147 => "alternative_transfer_characteristics",
+        _ => "unknown",
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SeiMessage {
+    pub payload_type: u32,
+    pub payload: Vec<u8>,
+}
+
+// the sei_message()s of an SEI RBSP
+pub fn sei_messages(rbsp: &[u8]) -> Result<Vec<SeiMessage>, &'static str> {
+    let mut messages = Vec::new();
+    let mut pos = 0;
+    while more_rbsp_data(&rbsp[pos..]) {
+        let payload_type = ff_coded(rbsp, &mut pos)?;
+        let payload_size = ff_coded(rbsp, &mut pos)? as usize;
+        let payload = rbsp.get(pos..pos + payload_size).ok_or("SEI payload ends early")?;
+        messages.push(SeiMessage {
+            payload_type,
+            payload: payload.to_vec(),
+        });
+        pos += payload_size;
+    }
+    Ok(messages)
+}
+
+// the SEI RBSP of the messages, with its trailing bits
+pub fn sei_rbsp(messages: &[SeiMessage]) -> Vec<u8> {
+    let mut rbsp = Vec::new();
+    for message in messages {
+        for &value in [message.payload_type, message.payload.len() as u32].iter() {
+            rbsp.extend(vec![0xff; value as usize / 255]);
+            rbsp.push((value % 255) as u8);
+        }
+        rbsp.extend_from_slice(&message.payload);
+    }
+    rbsp.push(0x80);
+    rbsp
+}
+
+// anything but the stop bit and zero bytes left
+fn more_rbsp_data(rest: &[u8]) -> bool {
+    match rest.split_first() {
+        Some((&0x80, tail)) => tail.iter().any(|&b| b != 0),
+        Some(_) => true,
+        None => false,
+    }
+}
+
+// payloadType and payloadSize, a run of 0xff bytes and the last byte added up
+fn ff_coded(rbsp: &[u8], pos: &mut usize) -> Result<u32, &'static str> {
+    let mut value = 0;
+    loop {
+        let b = *rbsp.get(*pos).ok_or("SEI message ends early")?;
+        *pos += 1;
+        value += b as u32;
+        if b != 0xff {
+            return Ok(value);
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct UserDataUnregistered {
+    pub uuid_iso_iec_11578: [u8; 16],
+    pub user_data: Vec<u8>,
+}
+
+impl UserDataUnregistered {
+    pub fn parse(payload: &[u8]) -> Result<UserDataUnregistered, &'static str> {
+        if payload.len() < 16 {
+            return Err("user_data_unregistered shorter than its uuid");
+        }
+        let mut uuid_iso_iec_11578 = [0u8; 16];
+        uuid_iso_iec_11578.copy_from_slice(&payload[..16]);
+        Ok(UserDataUnregistered {
+            uuid_iso_iec_11578,
+            user_data: payload[16..].to_vec(),
+        })
+    }
+}
+
+impl fmt::Display for UserDataUnregistered {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, b) in self.uuid_iso_iec_11578.iter().enumerate() {
+            if i == 4 || i == 6 || i == 8 || i == 10 {
+                write!(f, "-")?;
+            }
+            write!(f, "{:02x}", b)?;
+        }
+        // encoders put their name and settings here as text
+        let text = match self.user_data.iter().position(|&b| b == 0) {
+            Some(end) => &self.user_data[..end],
+            None => &self.user_data[..],
+        };
+        if !text.is_empty() && text.iter().all(|&b| (0x20..0x7f).contains(&b)) {
+            write!(f, " \"{}\"", String::from_utf8_lossy(text))
+        } else {
+            write!(f, " {} bytes", self.user_data.len())
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MasteringDisplayColourVolume {
+    // x and y in 0.00002 units, in the order G, B, R
+    pub display_primaries: [(u16, u16); 3],
+    pub white_point: (u16, u16),
+    // in 0.0001 cd/m2
+    pub max_display_mastering_luminance: u32,
+    pub min_display_mastering_luminance: u32,
+}
+
+impl MasteringDisplayColourVolume {
+    pub fn parse(payload: &[u8]) -> Result<MasteringDisplayColourVolume, &'static str> {
+        let mut r = BitReader::new(payload);
+        let mut mdcv = MasteringDisplayColourVolume::default();
+        for primary in mdcv.display_primaries.iter_mut() {
+            *primary = (r.bits(16)? as u16, r.bits(16)? as u16);
+        }
+        mdcv.white_point = (r.bits(16)? as u16, r.bits(16)? as u16);
+        mdcv.max_display_mastering_luminance = r.bits(32)?;
+        mdcv.min_display_mastering_luminance = r.bits(32)?;
+        Ok(mdcv)
+    }
+}
+
+impl fmt::Display for MasteringDisplayColourVolume {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let xy = |(x, y): (u16, u16)| {
+            format!("({:.4},{:.4})", x as f64 / 50000.0, y as f64 / 50000.0)
+        };
+        write!(f,
+               "R{} G{} B{} WP{} L({},{}) cd/m2",
+               xy(self.display_primaries[2]),
+               xy(self.display_primaries[0]),
+               xy(self.display_primaries[1]),
+               xy(self.white_point),
+               self.max_display_mastering_luminance as f64 / 10000.0,
+               self.min_display_mastering_luminance as f64 / 10000.0)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ContentLightLevel {
+    pub max_content_light_level: u16,
+    pub max_pic_average_light_level: u16,
+}
+
+impl ContentLightLevel {
+    pub fn parse(payload: &[u8]) -> Result<ContentLightLevel, &'static str> {
+        let mut r = BitReader::new(payload);
+        Ok(ContentLightLevel {
+            max_content_light_level: r.bits(16)? as u16,
+            max_pic_average_light_level: r.bits(16)? as u16,
+        })
+    }
+}
+
+impl fmt::Display for ContentLightLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "MaxCLL {} MaxFALL {} cd/m2",
+               self.max_content_light_level,
+               self.max_pic_average_light_level)
+    }
+}
@@ -0,0 +1,294 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Error, Read, Write};
+use std::slice;
+use super::av1::Av1Sizes;
+use super::bin::Bin;
+use super::h264;
+use super::h265;
+use super::ivf::Ivf;
+use super::vpx::{split_superframe, Vp8Sizes, Vp9Sizes};
+use super::webm::{vpx_destroy, vpx_open, vpx_read};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Codec {
+    Vp8,
+    Vp9,
+    Av1,
+    H264,
+    H265,
+}
+
+impl Codec {
+    pub fn parse(name: &str) -> Result<Codec, &'static str> {
+        match name.to_lowercase().as_ref() {
+            "vp8" => Ok(Codec::Vp8),
+            "vp9" => Ok(Codec::Vp9),
+            "av1" => Ok(Codec::Av1),
+            "h264" => Ok(Codec::H264),
+            "h265" => Ok(Codec::H265),
+            _ => Err("only support vp8, vp9, av1, h264 and h265"),
+        }
+    }
+}
+
+pub struct Sizes {
+    pub input: String,
+    pub output: String,
+    pub codec: Codec,
+    // one WxH line per shown frame instead of a line per size change
+    pub per_frame: bool,
+}
+
+impl Sizes {
+    pub fn helper() {
+        println!("Usage: rsplit sizes input.ivf|webm|bin output.txt|- vp8|vp9|av1|h264|h265 \
+                  [--per-frame]")
+    }
+
+    pub fn new(args: &[String]) -> Result<Sizes, &'static str> {
+        let mut per_frame = false;
+        let mut positional: Vec<String> = Vec::new();
+        for arg in args {
+            if arg == "--per-frame" {
+                per_frame = true;
+            } else if arg.starts_with("--") {
+                return Err("unknown option for rsplit sizes mode");
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        let args = &positional[..];
+
+        if args.len() < 5 {
+            return Err("too less arguments for rsplit sizes mode");
+        }
+
+        let codec = Codec::parse(&args[4])?;
+
+        Ok(Sizes {
+            input: args[2].clone(),
+            output: args[3].clone(),
+            codec,
+            per_frame,
+        })
+    }
+
+    pub fn run(&self) -> io::Result<()> {
+        // the schedule itself goes to stdout for "-"
+        let verbose = self.output != "-";
+        if verbose {
+            println!("rsplit sizes of {} into {}", self.input, self.output);
+        }
+
+        let sizes = match self.codec {
+            Codec::H264 => self.h264_sizes()?,
+            Codec::H265 => self.h265_sizes()?,
+            _ if self.input.to_lowercase().ends_with(".webm") => self.webm_sizes()?,
+            _ => self.ivf_sizes()?,
+        };
+
+        let out: Box<dyn Write> = if self.output == "-" {
+            Box::new(io::stdout())
+        } else {
+            Box::new(File::create(&self.output)?)
+        };
+        let mut out = BufWriter::new(out);
+        writeln!(out, "# {}: {} shown frames", self.input, sizes.len())?;
+        for (i, &(width, height)) in sizes.iter().enumerate() {
+            if self.per_frame {
+                writeln!(out, "{}x{}", width, height)?;
+            } else if i == 0 || sizes[i - 1] != (width, height) {
+                writeln!(out, "{} {}x{}", i, width, height)?;
+            }
+        }
+        out.flush()?;
+
+        if verbose {
+            println!("{} shown frames", sizes.len());
+        }
+        Ok(())
+    }
+
+    fn ivf_sizes(&self) -> io::Result<Vec<(u32, u32)>> {
+        let mut fi = File::open(&self.input)?;
+        let mut ivf_seq_buffer = [0u8; 32];
+        fi.read_exact(&mut ivf_seq_buffer)?;
+        if &ivf_seq_buffer[0..4] != b"DKIF" {
+            return Err(Error::other("Not supported IVF format ..."));
+        }
+
+        let ivf = Ivf::reader(self.codec == Codec::Vp9);
+        let mut frames = Frames::new(self.codec);
+        // a missing or truncated frame ends the stream, as in ivf mode
+        while let Ok(bs) = ivf.find_au_nal_units(&mut fi) {
+            frames.push(&bs.frame_data)?;
+        }
+        Ok(frames.sizes)
+    }
+
+    fn webm_sizes(&self) -> io::Result<Vec<(u32, u32)>> {
+        if self.codec == Codec::Av1 {
+            return Err(Error::other("only vp8 and vp9 webm inputs are supported"));
+        }
+        let input_ctx = vpx_open(&self.input)?;
+
+        let mut frames = Frames::new(self.codec);
+        let mut result = Ok(());
+        loop {
+            let mut len = 0;
+            let ptr = unsafe { vpx_read(input_ctx, &mut len) };
+            if len == 0 {
+                break;
+            }
+            let buffer = unsafe { slice::from_raw_parts(ptr, len as usize) };
+            result = frames.push(buffer);
+            if result.is_err() {
+                break;
+            }
+        }
+        unsafe { vpx_destroy(input_ctx) };
+        result.map(|_| frames.sizes)
+    }
+
+    fn h264_sizes(&self) -> io::Result<Vec<(u32, u32)>> {
+        let bin = Bin::scanner(&self.input, false);
+        let mut fi = File::open(&self.input)?;
+        let mut ps = h264::ParameterSets::new();
+        let mut sizes = Vec::new();
+        // (frame_num, bottom_field_flag) of a field still waiting for its pair
+        let mut first_field: Option<(u32, bool)> = None;
+        loop {
+            let (eof, bs) = bin.find_h264_nal_units(&mut fi);
+            let bs = bs?;
+            for i in 0..bs.nal_size {
+                let nal = bs.nal_unit(i);
+                let nal_unit_type = h264::nal_unit_type(nal);
+                if nal_unit_type != h264::NAL_SLICE && nal_unit_type != h264::NAL_SLICE_IDR {
+                    ps.update(nal).map_err(|e| frame_error(sizes.len(), e))?;
+                    continue;
+                }
+                // the scanner starts an access unit on every first slice, so
+                // only the first one matters
+                let slice = ps.slice_header(nal).map_err(|e| frame_error(sizes.len(), e))?;
+                let size = ps.sps_of(&slice).map_err(|e| frame_error(sizes.len(), e))?.size();
+                if slice.field_pic_flag {
+                    let field = (slice.frame_num, slice.bottom_field_flag);
+                    match first_field {
+                        Some((frame_num, bottom))
+                            if frame_num == field.0 && bottom != field.1 => {
+                            first_field = None;
+                            break;
+                        }
+                        _ => first_field = Some(field),
+                    }
+                } else {
+                    first_field = None;
+                }
+                sizes.push(size);
+                break;
+            }
+            if eof {
+                break;
+            }
+        }
+        Ok(sizes)
+    }
+
+    fn h265_sizes(&self) -> io::Result<Vec<(u32, u32)>> {
+        let bin = Bin::scanner(&self.input, true);
+        let mut fi = File::open(&self.input)?;
+        let mut ps = h265::ParameterSets::new();
+        let mut sizes = Vec::new();
+        let mut poc = h265::PicOrderCount::new();
+        loop {
+            let (eof, bs) = bin.find_h265_nal_units(&mut fi);
+            let bs = bs?;
+            for i in 0..bs.nal_size {
+                let nal = bs.nal_unit(i);
+                if nal.len() < 2 || h265::nuh_layer_id(nal) != 0 {
+                    // only the base layer is decoded
+                    continue;
+                }
+                let nal_unit_type = h265::nal_unit_type(nal);
+                if nal_unit_type == h265::NAL_EOS {
+                    poc.end_of_sequence();
+                    continue;
+                }
+                if nal_unit_type > 23 {
+                    ps.update(nal).map_err(|e| frame_error(sizes.len(), e))?;
+                    continue;
+                }
+                let slice = ps.slice_header(nal).map_err(|e| frame_error(sizes.len(), e))?;
+                if !slice.first_slice_segment_in_pic_flag {
+                    continue;
+                }
+                let (_, sps) = ps.parameter_sets_of(slice.slice_pic_parameter_set_id)
+                    .map_err(|e| frame_error(sizes.len(), e))?;
+                poc.next(sps, nal_unit_type, h265::temporal_id(nal), &slice);
+                // RASL pictures of a BLA or of a CRA that starts the stream
+                // aren't output
+                let rasl = nal_unit_type == h265::NAL_RASL_N || nal_unit_type == h265::NAL_RASL_R;
+                if slice.pic_output_flag && !(rasl && poc.no_rasl_output_flag) {
+                    sizes.push(sps.size());
+                }
+                break;
+            }
+            if eof {
+                break;
+            }
+        }
+        Ok(sizes)
+    }
+}
+
+// shown frame sizes of VP8, VP9 or AV1 frames as they come out of the container
+struct Frames {
+    codec: Codec,
+    vp8: Vp8Sizes,
+    vp9: Vp9Sizes,
+    av1: Av1Sizes,
+    sizes: Vec<(u32, u32)>,
+    frames: usize,
+}
+
+impl Frames {
+    fn new(codec: Codec) -> Frames {
+        Frames {
+            codec,
+            vp8: Vp8Sizes::new(),
+            vp9: Vp9Sizes::new(),
+            av1: Av1Sizes::new(),
+            sizes: Vec::new(),
+            frames: 0,
+        }
+    }
+
+    fn push(&mut self, data: &[u8]) -> io::Result<()> {
+        let frame = self.frames;
+        self.frames += 1;
+        match self.codec {
+            Codec::Vp8 => {
+                if let Some(size) = self.vp8.push(data).map_err(|e| frame_error(frame, e))? {
+                    self.sizes.push(size);
+                }
+            }
+            Codec::Vp9 => {
+                for data in split_superframe(data) {
+                    if let Some(size) = self.vp9.push(data).map_err(|e| frame_error(frame, e))? {
+                        self.sizes.push(size);
+                    }
+                }
+            }
+            _ => {
+                let shown = self.av1.push(data).map_err(|e| frame_error(frame, e))?;
+                self.sizes.extend(shown);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn frame_error(frame: usize, err: &str) -> Error {
+    Error::other(format!("frame {}: {}", frame, err))
+}
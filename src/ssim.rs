@@ -0,0 +1,220 @@
+// SSIM and MS-SSIM for 8-bit planes, following Wang et al. with the usual
+// constants K1 = 0.01, K2 = 0.03 and L = 255.
+
+const C1: f64 = 6.5025; // (0.01 * 255)^2
+const C2: f64 = 58.5225; // (0.03 * 255)^2
+
+const GAUSSIAN_SIZE: usize = 11;
+const GAUSSIAN_SIGMA: f64 = 1.5;
+
+const MS_SSIM_WEIGHTS: [f64; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SsimWindow {
+    // 8x8 windows stepped by 4 samples, as x264/libvpx do
+    Block8x8,
+    // 11x11 gaussian window with sigma 1.5, as the reference implementation does
+    Gaussian,
+}
+
+struct Plane {
+    width: usize,
+    height: usize,
+    data: Vec<f64>,
+}
+
+impl Plane {
+    fn new(buf: &[u8], width: usize, height: usize) -> Plane {
+        Plane {
+            width,
+            height,
+            data: buf[0..width * height].iter().map(|&s| s as f64).collect(),
+        }
+    }
+
+    fn downsample(&self) -> Plane {
+        let width = self.width / 2;
+        let height = self.height / 2;
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let top = (2 * y) * self.width + 2 * x;
+                let bottom = top + self.width;
+                data.push((self.data[top] + self.data[top + 1] + self.data[bottom] +
+                           self.data[bottom + 1]) / 4.0);
+            }
+        }
+        Plane {
+            width,
+            height,
+            data,
+        }
+    }
+}
+
+// returns (ssim, cs) of one window from its means, variances and covariance
+fn ssim_stats(mx: f64, my: f64, vxx: f64, vyy: f64, vxy: f64) -> (f64, f64) {
+    let l = (2.0 * mx * my + C1) / (mx * mx + my * my + C1);
+    let cs = (2.0 * vxy + C2) / (vxx + vyy + C2);
+    (l * cs, cs)
+}
+
+fn block_ssim(org: &Plane, rec: &Plane) -> (f64, f64) {
+    let win_w = org.width.min(8);
+    let win_h = org.height.min(8);
+    let n = (win_w * win_h) as f64;
+
+    let mut sum_ssim = 0.0;
+    let mut sum_cs = 0.0;
+    let mut count = 0;
+
+    let mut y0 = 0;
+    while y0 + win_h <= org.height {
+        let mut x0 = 0;
+        while x0 + win_w <= org.width {
+            let (mut sx, mut sy, mut sxx, mut syy, mut sxy) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in y0..y0 + win_h {
+                for x in x0..x0 + win_w {
+                    let a = org.data[y * org.width + x];
+                    let b = rec.data[y * rec.width + x];
+                    sx += a;
+                    sy += b;
+                    sxx += a * a;
+                    syy += b * b;
+                    sxy += a * b;
+                }
+            }
+            let mx = sx / n;
+            let my = sy / n;
+            let (s, cs) = ssim_stats(mx,
+                                     my,
+                                     sxx / n - mx * mx,
+                                     syy / n - my * my,
+                                     sxy / n - mx * my);
+            sum_ssim += s;
+            sum_cs += cs;
+            count += 1;
+            x0 += 4;
+        }
+        y0 += 4;
+    }
+
+    if count == 0 {
+        return (1.0, 1.0);
+    }
+    (sum_ssim / count as f64, sum_cs / count as f64)
+}
+
+fn gaussian_kernel() -> [f64; GAUSSIAN_SIZE] {
+    let mut kernel = [0.0f64; GAUSSIAN_SIZE];
+    let center = (GAUSSIAN_SIZE / 2) as f64;
+    let mut sum = 0.0;
+    for (i, k) in kernel.iter_mut().enumerate() {
+        let d = i as f64 - center;
+        *k = (-(d * d) / (2.0 * GAUSSIAN_SIGMA * GAUSSIAN_SIGMA)).exp();
+        sum += *k;
+    }
+    for k in kernel.iter_mut() {
+        *k /= sum;
+    }
+    kernel
+}
+
+// separable filtering keeping only the samples where the window fits entirely
+fn filter_valid(data: &[f64], width: usize, height: usize, kernel: &[f64]) -> Vec<f64> {
+    let taps = kernel.len();
+    let out_w = width + 1 - taps;
+    let out_h = height + 1 - taps;
+
+    let mut horizontal = vec![0.0f64; out_w * height];
+    for y in 0..height {
+        let row = &data[y * width..(y + 1) * width];
+        for x in 0..out_w {
+            horizontal[y * out_w + x] = kernel.iter()
+                .enumerate()
+                .map(|(k, w)| w * row[x + k])
+                .sum();
+        }
+    }
+
+    let mut out = vec![0.0f64; out_w * out_h];
+    for y in 0..out_h {
+        for x in 0..out_w {
+            out[y * out_w + x] = kernel.iter()
+                .enumerate()
+                .map(|(k, w)| w * horizontal[(y + k) * out_w + x])
+                .sum();
+        }
+    }
+    out
+}
+
+fn gaussian_ssim(org: &Plane, rec: &Plane) -> (f64, f64) {
+    if org.width < GAUSSIAN_SIZE || org.height < GAUSSIAN_SIZE {
+        return block_ssim(org, rec);
+    }
+
+    let kernel = gaussian_kernel();
+    let (w, h) = (org.width, org.height);
+    let xx: Vec<f64> = org.data.iter().map(|a| a * a).collect();
+    let yy: Vec<f64> = rec.data.iter().map(|b| b * b).collect();
+    let xy: Vec<f64> = org.data.iter().zip(rec.data.iter()).map(|(a, b)| a * b).collect();
+
+    let mu_x = filter_valid(&org.data, w, h, &kernel);
+    let mu_y = filter_valid(&rec.data, w, h, &kernel);
+    let e_xx = filter_valid(&xx, w, h, &kernel);
+    let e_yy = filter_valid(&yy, w, h, &kernel);
+    let e_xy = filter_valid(&xy, w, h, &kernel);
+
+    let mut sum_ssim = 0.0;
+    let mut sum_cs = 0.0;
+    for i in 0..mu_x.len() {
+        let (mx, my) = (mu_x[i], mu_y[i]);
+        let (s, cs) = ssim_stats(mx,
+                                 my,
+                                 e_xx[i] - mx * mx,
+                                 e_yy[i] - my * my,
+                                 e_xy[i] - mx * my);
+        sum_ssim += s;
+        sum_cs += cs;
+    }
+    let n = mu_x.len() as f64;
+    (sum_ssim / n, sum_cs / n)
+}
+
+pub fn ssim(org: &[u8], rec: &[u8], width: usize, height: usize, window: SsimWindow) -> f64 {
+    let org = Plane::new(org, width, height);
+    let rec = Plane::new(rec, width, height);
+    match window {
+        SsimWindow::Block8x8 => block_ssim(&org, &rec).0,
+        SsimWindow::Gaussian => gaussian_ssim(&org, &rec).0,
+    }
+}
+
+pub fn ms_ssim(org: &[u8], rec: &[u8], width: usize, height: usize) -> f64 {
+    let mut org = Plane::new(org, width, height);
+    let mut rec = Plane::new(rec, width, height);
+
+    // scales that no longer fit the gaussian window are dropped and the
+    // remaining weights renormalized, so small chroma planes still get a value
+    let mut stats: Vec<(f64, f64)> = Vec::with_capacity(MS_SSIM_WEIGHTS.len());
+    for scale in 0..MS_SSIM_WEIGHTS.len() {
+        if scale > 0 {
+            if org.width / 2 < GAUSSIAN_SIZE || org.height / 2 < GAUSSIAN_SIZE {
+                break;
+            }
+            org = org.downsample();
+            rec = rec.downsample();
+        }
+        stats.push(gaussian_ssim(&org, &rec));
+    }
+
+    let weight_sum: f64 = MS_SSIM_WEIGHTS[0..stats.len()].iter().sum();
+    let last = stats.len() - 1;
+    let mut result = 1.0;
+    for (scale, &(s, cs)) in stats.iter().enumerate() {
+        let value = if scale == last { s } else { cs };
+        result *= value.max(0.0).powf(MS_SSIM_WEIGHTS[scale] / weight_sum);
+    }
+    result
+}
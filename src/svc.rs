@@ -0,0 +1,174 @@
+// VP9 SVC layer extraction. The spatial layers of a picture are the frames
+// of its superframe, lowest first. Temporal layers aren't coded in the
+// bitstream, they follow the layering pattern the encoder was set up with.
+
+use super::vpx::{join_superframe, split_superframe, Vp9Header, Vp9Sizes};
+
+#[derive(Clone, Debug)]
+pub struct Layers {
+    // the highest spatial and temporal layer kept
+    pub spatial: Option<usize>,
+    pub temporal: Option<u8>,
+    // the temporal layer of each picture, repeating from every key frame
+    pub pattern: Vec<u8>,
+}
+
+impl Layers {
+    pub fn usage() -> &'static str {
+        "[--spatial-layer=N] [--temporal-layer=N [--temporal-pattern=0,2,1,2]]"
+    }
+
+    // applies a --spatial-layer, --temporal-layer or --temporal-pattern
+    // option, false for any other argument
+    pub fn parse(layers: &mut Option<Layers>, arg: &str) -> Result<bool, &'static str> {
+        let (option, value) = match arg.split_once('=') {
+            Some((option @ "--spatial-layer", value)) |
+            Some((option @ "--temporal-layer", value)) |
+            Some((option @ "--temporal-pattern", value)) => (option, value),
+            _ => return Ok(false),
+        };
+        let layers = layers.get_or_insert(Layers {
+            spatial: None,
+            temporal: None,
+            pattern: vec![0, 2, 1, 2],
+        });
+        match option {
+            "--spatial-layer" => {
+                layers.spatial = Some(value.parse::<usize>()
+                    .map_err(|_| "can't parse spatial-layer as usize")?);
+            }
+            "--temporal-layer" => {
+                layers.temporal = Some(value.parse::<u8>()
+                    .map_err(|_| "can't parse temporal-layer as u8")?);
+            }
+            _ => {
+                layers.pattern = value.split(',')
+                    .map(|t| t.parse::<u8>())
+                    .collect::<Result<Vec<u8>, _>>()
+                    .map_err(|_| "can't parse temporal-pattern as a list of temporal layers")?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+pub struct Extractor {
+    layers: Layers,
+    // pictures since the last key frame
+    position: usize,
+    // reference slots last written by a dropped frame
+    dropped_slots: [bool; 8],
+    sizes: Vp9Sizes,
+    // the size of the first picture shown
+    pub size: Option<(u32, u32)>,
+    pub frames: usize,
+    pub kept_frames: usize,
+    // kept frames predicting from a slot a dropped frame wrote
+    pub stale_references: usize,
+    // pictures whose shown frame was dropped with nothing kept to show
+    pub lost_pictures: usize,
+}
+
+impl Extractor {
+    pub fn new(layers: &Layers) -> Extractor {
+        Extractor {
+            layers: layers.clone(),
+            position: 0,
+            dropped_slots: [false; 8],
+            sizes: Vp9Sizes::new(),
+            size: None,
+            frames: 0,
+            kept_frames: 0,
+            stale_references: 0,
+            lost_pictures: 0,
+        }
+    }
+
+    // the picture with the layers kept, None when it is dropped altogether
+    pub fn extract(&mut self, data: &[u8]) -> Result<Option<Vec<u8>>, &'static str> {
+        let frames = split_superframe(data);
+        let headers = frames.iter()
+            .map(|frame| Vp9Header::parse(frame))
+            .collect::<Result<Vec<Vp9Header>, _>>()?;
+        if headers.first().is_some_and(|h| h.key_frame) {
+            self.position = 0;
+        }
+        let temporal = self.layers.pattern[self.position % self.layers.pattern.len()];
+        self.position += 1;
+        let picture_kept = self.layers.temporal.is_none_or(|t| temporal <= t);
+
+        let mut kept: Vec<Vec<u8>> = Vec::new();
+        let mut last_kept: Option<&Vp9Header> = None;
+        let mut kept_shown = false;
+        for (layer, (frame, header)) in frames.iter().zip(headers.iter()).enumerate() {
+            self.frames += 1;
+            if !picture_kept || self.layers.spatial.is_some_and(|s| layer > s) {
+                for slot in 0..8 {
+                    if header.refresh_frame_flags & (1 << slot) != 0 {
+                        self.dropped_slots[slot] = true;
+                    }
+                }
+                continue;
+            }
+            let references = match header.show_existing_frame {
+                Some(slot) => vec![slot],
+                None => header.ref_frame_idx.clone(),
+            };
+            if references.iter().any(|&slot| self.dropped_slots[slot]) {
+                self.stale_references += 1;
+            }
+            for slot in 0..8 {
+                if header.refresh_frame_flags & (1 << slot) != 0 {
+                    self.dropped_slots[slot] = false;
+                }
+            }
+            self.kept_frames += 1;
+            kept.push(frame.to_vec());
+            last_kept = Some(header);
+            kept_shown |= header.show_frame || header.show_existing_frame.is_some();
+        }
+
+        // a picture shown by a dropped layer shows the kept one instead
+        let shown = headers.iter().any(|h| h.show_frame || h.show_existing_frame.is_some());
+        if let Some(header) = last_kept {
+            if shown && !kept_shown {
+                match (0..8).find(|slot| header.refresh_frame_flags & (1 << slot) != 0) {
+                    Some(slot) => kept.push(show_existing_frame(header.profile, slot)),
+                    None => self.lost_pictures += 1,
+                }
+            }
+        } else if shown && picture_kept {
+            self.lost_pictures += 1;
+        }
+        if kept.is_empty() {
+            return Ok(None);
+        }
+
+        for frame in kept.iter() {
+            // a stale reference can leave a slot without a size
+            if let Ok(Some(size)) = self.sizes.push(frame) {
+                self.size.get_or_insert(size);
+            }
+        }
+        let kept: Vec<&[u8]> = kept.iter().map(|frame| &frame[..]).collect();
+        Ok(Some(join_superframe(&kept)))
+    }
+}
+
+// a header that shows the reference slot
+fn show_existing_frame(profile: u32, slot: usize) -> Vec<u8> {
+    // frame_marker, profile_low_bit, profile_high_bit, reserved_zero for
+    // profile 3, show_existing_frame and frame_to_show_map_idx
+    let (bits, length) = if profile == 3 {
+        (0b101101 << 3 | slot, 9)
+    } else {
+        let profile = profile as usize;
+        (0b10 << 6 | (profile & 1) << 5 | (profile >> 1) << 4 | 1 << 3 | slot, 8)
+    };
+    let aligned = bits << (16 - length);
+    let mut data = vec![(aligned >> 8) as u8];
+    if length > 8 {
+        data.push(aligned as u8);
+    }
+    data
+}
@@ -0,0 +1,256 @@
+use std::fs::File;
+use std::io;
+use std::io::Error;
+use super::bin::Bin;
+use super::bits::unescape;
+use super::frame::{ChromaFormat, FrameReader};
+use super::h265;
+use super::h265::DecodedPictureHash;
+use super::sei;
+
+pub struct Verify {
+    pub input: String,
+    pub input_yuv: String,
+    // the yuv holds the full coded pictures of a stream with a conformance
+    // window
+    pub uncropped: bool,
+}
+
+// an output picture of the stream and the hash it was sent with
+struct Picture {
+    decode_index: usize,
+    // coded video sequence and PicOrderCntVal, which give the output order
+    cvs: usize,
+    poc: i32,
+    width: usize,
+    height: usize,
+    chroma_format_idc: u32,
+    bit_depth_luma: u32,
+    bit_depth_chroma: u32,
+    // the SPS has a conformance window a decoder may crop to
+    cropped: bool,
+    hash: Option<DecodedPictureHash>,
+}
+
+impl Picture {
+    // (offset, width, height, bit depth) of each plane in a YUV frame, with
+    // 16-bit samples above 8 bits
+    fn planes(&self) -> Vec<(usize, usize, usize, u32)> {
+        let chroma = match self.chroma_format_idc {
+            0 => return vec![(0, self.width, self.height, self.bit_depth_luma)],
+            1 => ChromaFormat::Yuv420,
+            2 => ChromaFormat::Yuv422,
+            _ => ChromaFormat::Yuv444,
+        };
+        let sample_bytes = |bit_depth| if bit_depth > 8 { 2 } else { 1 };
+        let mut offset = 0;
+        let mut planes = Vec::new();
+        for (i, &(_, width, height)) in chroma.planes(self.width, self.height).iter().enumerate() {
+            let bit_depth = if i == 0 { self.bit_depth_luma } else { self.bit_depth_chroma };
+            planes.push((offset, width, height, bit_depth));
+            offset += width * height * sample_bytes(bit_depth);
+        }
+        planes
+    }
+
+    fn frame_bytes(&self) -> usize {
+        let planes = self.planes();
+        let &(offset, width, height, bit_depth) = planes.last().unwrap();
+        offset + width * height * if bit_depth > 8 { 2 } else { 1 }
+    }
+}
+
+impl Verify {
+    pub fn helper() {
+        println!("Usage: rsplit verify input.bin decoded.yuv|- [--uncropped]");
+        println!("The decoded picture hash covers the full coded picture, so a stream with a \
+                  conformance window must be decoded without cropping and checked with \
+                  --uncropped")
+    }
+
+    pub fn new(args: &[String]) -> Result<Verify, &'static str> {
+        let mut uncropped = false;
+        let mut positional: Vec<String> = Vec::new();
+        for arg in args {
+            if arg == "--uncropped" {
+                uncropped = true;
+            } else if arg.starts_with("--") {
+                return Err("unknown option for rsplit verify mode");
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        let args = &positional[..];
+
+        if args.len() < 4 {
+            return Err("too less arguments for rsplit verify mode");
+        }
+
+        Ok(Verify {
+            input: args[2].clone(),
+            input_yuv: args[3].clone(),
+            uncropped,
+        })
+    }
+
+    pub fn run(&self) -> io::Result<()> {
+        println!("rsplit verify H265 {} against {}", self.input, self.input_yuv);
+        let mut pictures = self.pictures()?;
+        // the decoder outputs every coded video sequence in POC order
+        pictures.sort_by_key(|p| (p.cvs, p.poc));
+        if !self.uncropped {
+            if let Some(picture) = pictures.iter().find(|p| p.cropped) {
+                return Err(Error::other(format!("picture {} has a conformance window, the hash \
+                                                 covers the full coded picture: decode without \
+                                                 cropping and add --uncropped",
+                                                picture.decode_index)));
+            }
+        }
+
+        let mut fi = FrameReader::open(&self.input_yuv)?;
+        let mut buf = Vec::new();
+        let (mut matched, mut mismatched, mut without_hash) = (0, 0, 0);
+        for (i, picture) in pictures.iter().enumerate() {
+            if !fi.read_frame(&mut buf, picture.frame_bytes())? {
+                return Err(fi.end_of_input());
+            }
+            let hash = match picture.hash {
+                Some(ref hash) => hash,
+                None => {
+                    without_hash += 1;
+                    continue;
+                }
+            };
+            let planes = picture.planes();
+            if hash.picture_hash.len() != planes.len() {
+                return Err(Error::other(format!("picture {} has {} hashes for {} planes",
+                                                picture.decode_index,
+                                                hash.picture_hash.len(),
+                                                planes.len())));
+            }
+            let mismatch = planes.iter().zip(hash.picture_hash.iter()).enumerate().find_map(
+                |(c, (&(offset, width, height, bit_depth), expected))| {
+                    let size = width * height * if bit_depth > 8 { 2 } else { 1 };
+                    let plane = &buf[offset..offset + size];
+                    let actual = hash.hash_type.compute(plane, width, bit_depth);
+                    if actual != *expected {
+                        Some((c, expected, actual))
+                    } else {
+                        None
+                    }
+                });
+            match mismatch {
+                Some((c, expected, actual)) => {
+                    if mismatched == 0 {
+                        println!("first mismatch: frame {} (decode order {}, poc {}) plane {}, \
+                                  {} {} expected, {} in the yuv",
+                                 i,
+                                 picture.decode_index,
+                                 picture.poc,
+                                 ["Y", "U", "V"][c],
+                                 hash.hash_type.name(),
+                                 hex(expected),
+                                 hex(&actual));
+                    }
+                    mismatched += 1;
+                }
+                None => matched += 1,
+            }
+        }
+
+        println!("{} frames: {} match, {} mismatch, {} without hash",
+                 pictures.len(),
+                 matched,
+                 mismatched,
+                 without_hash);
+        let mut rest = 0;
+        while fi.skip_frame(pictures.last().map_or(0, |p| p.frame_bytes()))? {
+            rest += 1;
+        }
+        if rest > 0 {
+            println!("{} more frames in {} than the stream outputs", rest, self.input_yuv);
+        }
+        if mismatched > 0 {
+            return Err(Error::other(format!("{} frames don't match their hash", mismatched)));
+        }
+        Ok(())
+    }
+
+    // the output pictures of the base layer in decoding order
+    fn pictures(&self) -> io::Result<Vec<Picture>> {
+        let bin = Bin::scanner(&self.input, true);
+        let mut fi = File::open(&self.input)?;
+        let mut ps = h265::ParameterSets::new();
+        let mut poc = h265::PicOrderCount::new();
+        let mut pictures: Vec<Picture> = Vec::new();
+        let mut decode_index = 0;
+        let mut cvs = 0;
+        loop {
+            let (eof, bs) = bin.find_h265_nal_units(&mut fi);
+            let bs = bs?;
+            // the picture of the access unit, if it is output
+            let mut picture: Option<Picture> = None;
+            let mut hash = None;
+            for i in 0..bs.nal_size {
+                let nal = bs.nal_unit(i);
+                if nal.len() < 2 || h265::nuh_layer_id(nal) != 0 {
+                    continue;
+                }
+                let nal_unit_type = h265::nal_unit_type(nal);
+                let err = |e| Error::other(format!("picture {}: {}", decode_index, e));
+                if nal_unit_type == h265::NAL_EOS {
+                    poc.end_of_sequence();
+                } else if nal_unit_type == h265::NAL_SUFFIX_SEI {
+                    for message in sei::sei_messages(&unescape(&nal[2..])).map_err(err)? {
+                        if message.payload_type == sei::DECODED_PICTURE_HASH {
+                            hash = Some(DecodedPictureHash::parse(&message.payload).map_err(err)?);
+                        }
+                    }
+                } else if nal_unit_type > 23 {
+                    ps.update(nal).map_err(err)?;
+                } else {
+                    let slice = ps.slice_header(nal).map_err(err)?;
+                    if !slice.first_slice_segment_in_pic_flag {
+                        continue;
+                    }
+                    let (_, sps) = ps.parameter_sets_of(slice.slice_pic_parameter_set_id)
+                        .map_err(err)?;
+                    let value = poc.next(sps, nal_unit_type, h265::temporal_id(nal), &slice);
+                    if h265::is_irap(nal_unit_type) && poc.no_rasl_output_flag {
+                        cvs += 1;
+                    }
+                    decode_index += 1;
+                    // as in sizes mode, skipped RASL pictures aren't output
+                    let rasl = nal_unit_type == h265::NAL_RASL_N ||
+                               nal_unit_type == h265::NAL_RASL_R;
+                    if slice.pic_output_flag && !(rasl && poc.no_rasl_output_flag) {
+                        picture = Some(Picture {
+                            decode_index: decode_index - 1,
+                            cvs,
+                            poc: value,
+                            width: sps.pic_width_in_luma_samples as usize,
+                            height: sps.pic_height_in_luma_samples as usize,
+                            chroma_format_idc: sps.chroma_format_idc,
+                            bit_depth_luma: sps.bit_depth_luma,
+                            bit_depth_chroma: sps.bit_depth_chroma,
+                            cropped: sps.conformance_window != (0, 0, 0, 0),
+                            hash: None,
+                        });
+                    }
+                }
+            }
+            if let Some(mut picture) = picture {
+                picture.hash = hash;
+                pictures.push(picture);
+            }
+            if eof {
+                break;
+            }
+        }
+        Ok(pictures)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
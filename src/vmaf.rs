@@ -0,0 +1,544 @@
+// Elementary VMAF features (VIF at four scales, ADM, motion) computed on the
+// luma plane, and a libsvm nu-SVR model loaded from a libvmaf style json file
+// to fuse them into a per-frame score. The feature definitions follow the
+// float implementations in libvmaf.
+
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io;
+use std::io::{Error, Read};
+use super::json::Json;
+
+const VIF_SIGMA_NSQ: f64 = 2.0;
+const VIF_EPS: f64 = 1e-10;
+
+const MOTION_FILTER: [f64; 5] = [0.054488685, 0.244201342, 0.402619947, 0.244201342, 0.054488685];
+
+const DB2_LO: [f64; 4] = [0.482962913144690, 0.836516303737469, 0.224143868041857,
+                          -0.129409522550921];
+const DB2_HI: [f64; 4] = [-0.129409522550921, -0.224143868041857, 0.836516303737469,
+                          -0.482962913144690];
+
+const ADM_BORDER_FACTOR: f64 = 0.1;
+const ADM_VIEW_DISTANCE: f64 = 3.0;
+const ADM_DISPLAY_HEIGHT: f64 = 1080.0;
+
+// watson luma model (a, k, f0, g[theta]) and 9/7 basis function amplitudes[lambda][theta]
+const WATSON_A: f64 = 0.495;
+const WATSON_K: f64 = 0.466;
+const WATSON_F0: f64 = 0.401;
+const WATSON_G: [f64; 4] = [1.501, 1.0, 0.534, 1.0];
+const BASIS_AMPLITUDES: [[f64; 4]; 4] = [[0.62171, 0.67234, 0.72709, 0.67234],
+                                         [0.34537, 0.41317, 0.49428, 0.41317],
+                                         [0.18004, 0.22727, 0.28688, 0.22727],
+                                         [0.091401, 0.11792, 0.15214, 0.11792]];
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VmafFeatures {
+    pub vif: [f64; 4],
+    pub adm: [f64; 4],
+    pub adm2: f64,
+    pub motion: f64,
+    pub motion2: f64,
+}
+
+// features of a single frame, which don't depend on the other frames and
+// can be computed in any order, and the blurred luma the motion is taken from
+pub struct VmafFrame {
+    features: VmafFeatures,
+    width: usize,
+    height: usize,
+    blur: Vec<f64>,
+}
+
+impl VmafFrame {
+    pub fn new(org: &[u8], rec: &[u8], width: usize, height: usize) -> VmafFrame {
+        let org: Vec<f64> = org[0..width * height].iter().map(|&s| s as f64).collect();
+        let rec: Vec<f64> = rec[0..width * height].iter().map(|&s| s as f64).collect();
+
+        let mut features = VmafFeatures::default();
+        for (scale, vif) in vif_scales(&org, &rec, width, height).iter().enumerate() {
+            features.vif[scale] = *vif;
+        }
+        let (adm, adm2) = adm_scales(&org, &rec, width, height);
+        features.adm = adm;
+        features.adm2 = adm2;
+
+        VmafFrame {
+            features,
+            width,
+            height,
+            blur: filter2d(&org, width, height, &MOTION_FILTER),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct VmafExtractor {
+    prev_blur: Option<(usize, usize, Vec<f64>)>,
+    pub frames: Vec<VmafFeatures>,
+}
+
+impl VmafExtractor {
+    pub fn new() -> VmafExtractor {
+        VmafExtractor::default()
+    }
+
+    // frames must be pushed in display order, motion2 of the previous frame
+    // is only final once this frame is pushed
+    pub fn push(&mut self, frame: VmafFrame) -> VmafFeatures {
+        let VmafFrame { mut features, width, height, blur } = frame;
+        features.motion = match self.prev_blur {
+            Some((w, h, ref prev)) if w == width && h == height => {
+                prev.iter().zip(blur.iter()).map(|(a, b)| (a - b).abs()).sum::<f64>() /
+                (width * height) as f64
+            }
+            _ => 0.0,
+        };
+        features.motion2 = features.motion;
+        if let Some(last) = self.frames.last_mut() {
+            last.motion2 = last.motion.min(features.motion);
+        }
+        self.prev_blur = Some((width, height, blur));
+        self.frames.push(features);
+
+        features
+    }
+}
+
+fn reflect(i: isize, n: usize) -> usize {
+    let n = n as isize;
+    let mut i = i;
+    if i < 0 {
+        i = -i;
+    }
+    if i >= n {
+        i = 2 * n - i - 2;
+    }
+    i.max(0).min(n - 1) as usize
+}
+
+// one pass of a centered filter with mirrored borders, along rows or columns
+fn convolve(data: &[f64], width: usize, height: usize, kernel: &[f64], rows: bool) -> Vec<f64> {
+    let half = (kernel.len() / 2) as isize;
+    let mut out = vec![0.0f64; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let mut acc = 0.0;
+            for (k, w) in kernel.iter().enumerate() {
+                let d = k as isize - half;
+                let sample = if rows {
+                    data[y * width + reflect(x as isize + d, width)]
+                } else {
+                    data[reflect(y as isize + d, height) * width + x]
+                };
+                acc += w * sample;
+            }
+            out[y * width + x] = acc;
+        }
+    }
+    out
+}
+
+fn filter2d(data: &[f64], width: usize, height: usize, kernel: &[f64]) -> Vec<f64> {
+    convolve(&convolve(data, width, height, kernel, true),
+             width,
+             height,
+             kernel,
+             false)
+}
+
+fn gaussian(taps: usize) -> Vec<f64> {
+    let sigma = taps as f64 / 5.0;
+    let center = (taps / 2) as f64;
+    let kernel: Vec<f64> = (0..taps)
+        .map(|i| {
+            let d = i as f64 - center;
+            (-(d * d) / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    let sum: f64 = kernel.iter().sum();
+    kernel.iter().map(|k| k / sum).collect()
+}
+
+fn decimate(data: &[f64], width: usize, height: usize) -> (Vec<f64>, usize, usize) {
+    let (w, h) = (width.div_ceil(2), height.div_ceil(2));
+    let mut out = Vec::with_capacity(w * h);
+    for y in 0..h {
+        for x in 0..w {
+            out.push(data[(2 * y) * width + 2 * x]);
+        }
+    }
+    (out, w, h)
+}
+
+fn vif_scales(org: &[f64], rec: &[f64], width: usize, height: usize) -> Vec<f64> {
+    let mut org = org.to_vec();
+    let mut rec = rec.to_vec();
+    let (mut w, mut h) = (width, height);
+    let mut scores = Vec::with_capacity(4);
+
+    for scale in 0..4 {
+        let kernel = gaussian((1 << (4 - scale)) + 1);
+        if scale > 0 {
+            let (o, nw, nh) = decimate(&filter2d(&org, w, h, &kernel), w, h);
+            let (r, _, _) = decimate(&filter2d(&rec, w, h, &kernel), w, h);
+            org = o;
+            rec = r;
+            w = nw;
+            h = nh;
+        }
+
+        let mu1 = filter2d(&org, w, h, &kernel);
+        let mu2 = filter2d(&rec, w, h, &kernel);
+        let xx: Vec<f64> = org.iter().map(|a| a * a).collect();
+        let yy: Vec<f64> = rec.iter().map(|b| b * b).collect();
+        let xy: Vec<f64> = org.iter().zip(rec.iter()).map(|(a, b)| a * b).collect();
+        let e_xx = filter2d(&xx, w, h, &kernel);
+        let e_yy = filter2d(&yy, w, h, &kernel);
+        let e_xy = filter2d(&xy, w, h, &kernel);
+
+        let mut num = 0.0;
+        let mut den = 0.0;
+        for i in 0..w * h {
+            let sigma1_sq = (e_xx[i] - mu1[i] * mu1[i]).max(0.0);
+            let sigma2_sq = (e_yy[i] - mu2[i] * mu2[i]).max(0.0);
+            let sigma12 = e_xy[i] - mu1[i] * mu2[i];
+
+            let mut g = sigma12 / (sigma1_sq + VIF_EPS);
+            let mut sv_sq = sigma2_sq - g * sigma12;
+            let mut s1 = sigma1_sq;
+            if sigma1_sq < VIF_EPS {
+                g = 0.0;
+                sv_sq = sigma2_sq;
+                s1 = 0.0;
+            }
+            if sigma2_sq < VIF_EPS {
+                g = 0.0;
+                sv_sq = 0.0;
+            }
+            if g < 0.0 {
+                sv_sq = sigma2_sq;
+                g = 0.0;
+            }
+            sv_sq = sv_sq.max(VIF_EPS);
+
+            num += (1.0 + g * g * s1 / (sv_sq + VIF_SIGMA_NSQ)).log2();
+            den += (1.0 + s1 / VIF_SIGMA_NSQ).log2();
+        }
+        scores.push(if den > 0.0 { num / den } else { 1.0 });
+    }
+    scores
+}
+
+struct Dwt {
+    width: usize,
+    height: usize,
+    a: Vec<f64>,
+    h: Vec<f64>,
+    v: Vec<f64>,
+    d: Vec<f64>,
+}
+
+fn dwt2(data: &[f64], width: usize, height: usize) -> Dwt {
+    let (w, h) = (width.div_ceil(2), height.div_ceil(2));
+    let mut lo = vec![0.0f64; w * height];
+    let mut hi = vec![0.0f64; w * height];
+    for y in 0..height {
+        for x in 0..w {
+            let (mut l, mut m) = (0.0, 0.0);
+            for k in 0..4 {
+                let s = data[y * width + reflect(2 * x as isize - 1 + k as isize, width)];
+                l += DB2_LO[k] * s;
+                m += DB2_HI[k] * s;
+            }
+            lo[y * w + x] = l;
+            hi[y * w + x] = m;
+        }
+    }
+
+    let mut dwt = Dwt {
+        width: w,
+        height: h,
+        a: vec![0.0f64; w * h],
+        h: vec![0.0f64; w * h],
+        v: vec![0.0f64; w * h],
+        d: vec![0.0f64; w * h],
+    };
+    for y in 0..h {
+        for x in 0..w {
+            let (mut a, mut bh, mut bv, mut bd) = (0.0, 0.0, 0.0, 0.0);
+            for k in 0..4 {
+                let row = reflect(2 * y as isize - 1 + k as isize, height) * w + x;
+                a += DB2_LO[k] * lo[row];
+                bv += DB2_LO[k] * hi[row];
+                bh += DB2_HI[k] * lo[row];
+                bd += DB2_HI[k] * hi[row];
+            }
+            dwt.a[y * w + x] = a;
+            dwt.h[y * w + x] = bh;
+            dwt.v[y * w + x] = bv;
+            dwt.d[y * w + x] = bd;
+        }
+    }
+    dwt
+}
+
+fn dwt_quant_step(lambda: usize, theta: usize) -> f64 {
+    let r = ADM_VIEW_DISTANCE * ADM_DISPLAY_HEIGHT * PI / 180.0;
+    let temp = (2.0f64.powi(lambda as i32 + 1) * WATSON_F0 * WATSON_G[theta] / r).log10();
+    2.0 * WATSON_A * 10.0f64.powf(WATSON_K * temp * temp) / BASIS_AMPLITUDES[lambda][theta]
+}
+
+fn adm_scales(org: &[f64], rec: &[f64], width: usize, height: usize) -> ([f64; 4], f64) {
+    let cos_1deg_sq = (PI / 180.0).cos() * (PI / 180.0).cos();
+    let mut org = org.to_vec();
+    let mut rec = rec.to_vec();
+    let (mut w, mut h) = (width, height);
+
+    let mut scores = [0.0f64; 4];
+    let mut num_total = 0.0;
+    let mut den_total = 0.0;
+
+    for (scale, score) in scores.iter_mut().enumerate() {
+        let ref_dwt = dwt2(&org, w, h);
+        let dis_dwt = dwt2(&rec, w, h);
+        let (bw, bh) = (ref_dwt.width, ref_dwt.height);
+        let n = bw * bh;
+
+        // decouple the distorted bands into restored and additive impairments
+        let mut restored = [vec![0.0f64; n], vec![0.0f64; n], vec![0.0f64; n]];
+        let mut additive = [vec![0.0f64; n], vec![0.0f64; n], vec![0.0f64; n]];
+        for i in 0..n {
+            let o = [ref_dwt.h[i], ref_dwt.v[i], ref_dwt.d[i]];
+            let t = [dis_dwt.h[i], dis_dwt.v[i], dis_dwt.d[i]];
+
+            let ot_dp = o[0] * t[0] + o[1] * t[1];
+            let o_mag_sq = o[0] * o[0] + o[1] * o[1];
+            let t_mag_sq = t[0] * t[0] + t[1] * t[1];
+            let angle_flag = ot_dp >= 0.0 && ot_dp * ot_dp >= cos_1deg_sq * o_mag_sq * t_mag_sq;
+
+            for b in 0..3 {
+                let k = (t[b] / (o[b] + 1e-30)).clamp(0.0, 1.0);
+                let r = if angle_flag { t[b] } else { k * o[b] };
+                restored[b][i] = r;
+                additive[b][i] = t[b] - r;
+            }
+        }
+
+        // contrast sensitivity weighting per orientation
+        let rfactor = [1.0 / dwt_quant_step(scale, 1),
+                       1.0 / dwt_quant_step(scale, 1),
+                       1.0 / dwt_quant_step(scale, 2)];
+        let reference = [&ref_dwt.h, &ref_dwt.v, &ref_dwt.d];
+        for b in 0..3 {
+            for i in 0..n {
+                restored[b][i] *= rfactor[b];
+                additive[b][i] = (additive[b][i] * rfactor[b]).abs();
+            }
+        }
+
+        let left = (bw as f64 * ADM_BORDER_FACTOR - 0.5).max(0.0) as usize;
+        let top = (bh as f64 * ADM_BORDER_FACTOR - 0.5).max(0.0) as usize;
+        let right = bw - left;
+        let bottom = bh - top;
+        let area_term = (((right - left) * (bottom - top)) as f64 / 32.0).cbrt();
+
+        let mut num = 0.0;
+        let mut den = 0.0;
+        for b in 0..3 {
+            let mut num_accum = 0.0;
+            let mut den_accum = 0.0;
+            for y in top..bottom {
+                for x in left..right {
+                    // masking threshold from the additive impairment around this coefficient
+                    let mut thr = 0.0;
+                    for dy in -1isize..2 {
+                        for dx in -1isize..2 {
+                            let j = reflect(y as isize + dy, bh) * bw +
+                                    reflect(x as isize + dx, bw);
+                            let weight = if dx == 0 && dy == 0 { 1.0 / 15.0 } else { 1.0 / 30.0 };
+                            thr += weight * (additive[0][j] + additive[1][j] + additive[2][j]);
+                        }
+                    }
+                    let i = y * bw + x;
+                    let masked = (restored[b][i].abs() - thr).max(0.0);
+                    num_accum += masked * masked * masked;
+                    let csf_ref = (reference[b][i] * rfactor[b]).abs();
+                    den_accum += csf_ref * csf_ref * csf_ref;
+                }
+            }
+            num += num_accum.cbrt() + area_term;
+            den += den_accum.cbrt() + area_term;
+        }
+
+        *score = if den > 0.0 { num / den } else { 1.0 };
+        num_total += num;
+        den_total += den;
+
+        org = ref_dwt.a;
+        rec = dis_dwt.a;
+        w = bw;
+        h = bh;
+    }
+
+    let adm2 = if den_total > 0.0 { num_total / den_total } else { 1.0 };
+    (scores, adm2)
+}
+
+pub struct VmafModel {
+    features: Vec<String>,
+    slopes: Vec<f64>,
+    intercepts: Vec<f64>,
+    score_clip: Option<(f64, f64)>,
+    gamma: f64,
+    rho: f64,
+    support_vectors: Vec<(f64, Vec<f64>)>,
+}
+
+impl VmafModel {
+    pub fn load(path: &str) -> io::Result<VmafModel> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        let json = Json::parse(&text).map_err(Error::other)?;
+        VmafModel::from_json(&json).map_err(Error::other)
+    }
+
+    fn from_json(json: &Json) -> Result<VmafModel, &'static str> {
+        let dict = json.get("model_dict").ok_or("missing model_dict in vmaf model")?;
+
+        match dict.get("model_type").and_then(|t| t.as_str()) {
+            Some("LIBSVMNUSVR") => {}
+            _ => return Err("only LIBSVMNUSVR vmaf models are supported"),
+        }
+        match dict.get("norm_type").and_then(|t| t.as_str()) {
+            Some("linear_rescale") | None => {}
+            _ => return Err("only linear_rescale vmaf models are supported"),
+        }
+
+        let mut features = Vec::new();
+        for name in dict.get("feature_names")
+            .and_then(|f| f.as_array())
+            .ok_or("missing feature_names in vmaf model")? {
+            let name = name.as_str().ok_or("feature name must be a string")?;
+            let short = name.rsplit("feature_").next().unwrap_or(name).trim_end_matches("_score");
+            if feature_value(&VmafFeatures::default(), short).is_none() {
+                return Err("unsupported feature in vmaf model");
+            }
+            features.push(short.to_string());
+        }
+
+        let numbers = |key: &str| -> Result<Vec<f64>, &'static str> {
+            let array = dict.get(key)
+                .and_then(|a| a.as_array())
+                .ok_or("missing slopes or intercepts")?;
+            array.iter()
+                .map(|n| n.as_f64().ok_or("slopes and intercepts must be numbers"))
+                .collect()
+        };
+        let slopes = numbers("slopes")?;
+        let intercepts = numbers("intercepts")?;
+        if slopes.len() != features.len() + 1 || intercepts.len() != features.len() + 1 {
+            return Err("slopes and intercepts don't match feature_names");
+        }
+
+        let score_clip = match dict.get("score_clip").and_then(|c| c.as_array()) {
+            Some(clip) if clip.len() == 2 => {
+                match (clip[0].as_f64(), clip[1].as_f64()) {
+                    (Some(lo), Some(hi)) => Some((lo, hi)),
+                    _ => return Err("invalid score_clip in vmaf model"),
+                }
+            }
+            _ => None,
+        };
+
+        let svm = dict.get("model").and_then(|m| m.as_str()).ok_or("missing libsvm model")?;
+        let mut gamma = None;
+        let mut rho = None;
+        let mut support_vectors = Vec::new();
+        let mut in_sv = false;
+        for line in svm.lines() {
+            let mut tokens = line.split_whitespace();
+            let first = match tokens.next() {
+                Some(first) => first,
+                None => continue,
+            };
+            if in_sv {
+                let coef = first.parse::<f64>().map_err(|_| "invalid libsvm coefficient")?;
+                let mut sv = vec![0.0f64; features.len()];
+                for token in tokens {
+                    let mut pair = token.split(':');
+                    let index = pair.next().and_then(|i| i.parse::<usize>().ok());
+                    let value = pair.next().and_then(|v| v.parse::<f64>().ok());
+                    match (index, value) {
+                        (Some(index), Some(value)) if index >= 1 && index <= sv.len() => {
+                            sv[index - 1] = value
+                        }
+                        _ => return Err("invalid libsvm support vector"),
+                    }
+                }
+                support_vectors.push((coef, sv));
+            } else {
+                match first {
+                    "kernel_type" if tokens.next() != Some("rbf") => {
+                        return Err("only rbf libsvm kernels are supported");
+                    }
+                    "gamma" => gamma = tokens.next().and_then(|g| g.parse::<f64>().ok()),
+                    "rho" => rho = tokens.next().and_then(|r| r.parse::<f64>().ok()),
+                    "SV" => in_sv = true,
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(VmafModel {
+            features,
+            slopes,
+            intercepts,
+            score_clip,
+            gamma: gamma.ok_or("missing gamma in libsvm model")?,
+            rho: rho.ok_or("missing rho in libsvm model")?,
+            support_vectors,
+        })
+    }
+
+    pub fn predict(&self, frame: &VmafFeatures) -> f64 {
+        let x: Vec<f64> = self.features
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                self.slopes[i + 1] * feature_value(frame, name).unwrap_or(0.0) +
+                self.intercepts[i + 1]
+            })
+            .collect();
+
+        let mut prediction = -self.rho;
+        for &(coef, ref sv) in &self.support_vectors {
+            let dist: f64 = sv.iter().zip(x.iter()).map(|(a, b)| (a - b) * (a - b)).sum();
+            prediction += coef * (-self.gamma * dist).exp();
+        }
+
+        let score = (prediction - self.intercepts[0]) / self.slopes[0];
+        match self.score_clip {
+            Some((lo, hi)) => score.max(lo).min(hi),
+            None => score,
+        }
+    }
+}
+
+fn feature_value(frame: &VmafFeatures, name: &str) -> Option<f64> {
+    match name {
+        "adm2" => Some(frame.adm2),
+        "motion" => Some(frame.motion),
+        "motion2" => Some(frame.motion2),
+        "vif_scale0" => Some(frame.vif[0]),
+        "vif_scale1" => Some(frame.vif[1]),
+        "vif_scale2" => Some(frame.vif[2]),
+        "vif_scale3" => Some(frame.vif[3]),
+        "adm_scale0" => Some(frame.adm[0]),
+        "adm_scale1" => Some(frame.adm[1]),
+        "adm_scale2" => Some(frame.adm[2]),
+        "adm_scale3" => Some(frame.adm[3]),
+        _ => None,
+    }
+}
@@ -0,0 +1,222 @@
+// VP8 and VP9 frame headers, as far as needed to follow the frame size of
+// every shown frame through key frames and reference scaling.
+
+use super::bits::BitReader;
+
+// the frames of a VP9 superframe, or the whole buffer if it isn't one
+pub fn split_superframe(data: &[u8]) -> Vec<&[u8]> {
+    if let Some(&marker) = data.last() {
+        if marker & 0xe0 == 0xc0 {
+            let frames = (marker & 0x7) as usize + 1;
+            let mag = ((marker >> 3) & 0x3) as usize + 1;
+            let index_size = 2 + mag * frames;
+            if data.len() >= index_size && data[data.len() - index_size] == marker {
+                let index = &data[data.len() - index_size + 1..];
+                let mut out = Vec::with_capacity(frames);
+                let mut offset = 0;
+                for i in 0..frames {
+                    let size = (0..mag).fold(0usize, |size, b| {
+                        size | (index[i * mag + b] as usize) << (8 * b)
+                    });
+                    if offset + size > data.len() - index_size {
+                        break;
+                    }
+                    out.push(&data[offset..offset + size]);
+                    offset += size;
+                }
+                return out;
+            }
+        }
+    }
+    vec![data]
+}
+
+// the frames packed into one buffer, with a superframe index when there is
+// more than one
+pub fn join_superframe(frames: &[&[u8]]) -> Vec<u8> {
+    let mut data: Vec<u8> = frames.concat();
+    if frames.len() < 2 {
+        return data;
+    }
+    let largest = frames.iter().map(|f| f.len()).max().unwrap_or(0);
+    let mag = (1..4).find(|&mag| largest < 1 << (8 * mag)).unwrap_or(4);
+    let marker = 0xc0 | ((mag - 1) << 3) as u8 | (frames.len() - 1) as u8;
+    data.push(marker);
+    for frame in frames {
+        data.extend_from_slice(&(frame.len() as u32).to_le_bytes()[..mag]);
+    }
+    data.push(marker);
+    data
+}
+
+#[derive(Default)]
+pub struct Vp8Sizes {
+    size: Option<(u32, u32)>,
+}
+
+impl Vp8Sizes {
+    pub fn new() -> Vp8Sizes {
+        Vp8Sizes::default()
+    }
+
+    // size of the frame if it is shown
+    pub fn push(&mut self, data: &[u8]) -> Result<Option<(u32, u32)>, &'static str> {
+        if data.len() < 3 {
+            return Err("vp8 frame is too short");
+        }
+        let key_frame = data[0] & 0x1 == 0;
+        let show_frame = (data[0] >> 4) & 0x1 == 1;
+        if key_frame {
+            if data.len() < 10 || data[3..6] != [0x9d, 0x01, 0x2a] {
+                return Err("invalid vp8 key frame start code");
+            }
+            let width = u16::from_le_bytes([data[6], data[7]]) as u32 & 0x3fff;
+            let height = u16::from_le_bytes([data[8], data[9]]) as u32 & 0x3fff;
+            self.size = Some((width, height));
+        }
+        match self.size {
+            Some(size) if show_frame => Ok(Some(size)),
+            Some(_) => Ok(None),
+            None => Err("vp8 stream doesn't start with a key frame"),
+        }
+    }
+}
+
+// the start of a VP9 uncompressed header, up to the frame size
+#[derive(Clone, Debug, Default)]
+pub struct Vp9Header {
+    pub profile: u32,
+    // the reference slot shown by a show_existing_frame header, which ends
+    // there
+    pub show_existing_frame: Option<usize>,
+    pub key_frame: bool,
+    pub show_frame: bool,
+    pub intra_only: bool,
+    pub refresh_frame_flags: u8,
+    // the slots an inter frame predicts from
+    pub ref_frame_idx: Vec<usize>,
+    // the coded frame size, None when an inter frame takes the size of the
+    // reference in size_from_slot
+    pub size: Option<(u32, u32)>,
+    pub size_from_slot: Option<usize>,
+}
+
+impl Vp9Header {
+    pub fn parse(data: &[u8]) -> Result<Vp9Header, &'static str> {
+        let mut r = BitReader::new(data);
+        if r.bits(2)? != 2 {
+            return Err("invalid vp9 frame marker");
+        }
+        let mut header = Vp9Header {
+            profile: r.bits(1)? | (r.bits(1)? << 1),
+            ..Vp9Header::default()
+        };
+        if header.profile == 3 {
+            r.skip(1)?;
+        }
+        if r.bit()? {
+            header.show_existing_frame = Some(r.bits(3)? as usize);
+            return Ok(header);
+        }
+        header.key_frame = !r.bit()?;
+        header.show_frame = r.bit()?;
+        let error_resilient_mode = r.bit()?;
+
+        if header.key_frame {
+            sync_code(&mut r)?;
+            color_config(&mut r, header.profile)?;
+            header.refresh_frame_flags = 0xff;
+            header.size = Some(frame_size(&mut r)?);
+            return Ok(header);
+        }
+        header.intra_only = if header.show_frame { false } else { r.bit()? };
+        if !error_resilient_mode {
+            r.skip(2)?; // reset_frame_context
+        }
+        if header.intra_only {
+            sync_code(&mut r)?;
+            if header.profile > 0 {
+                color_config(&mut r, header.profile)?;
+            }
+            header.refresh_frame_flags = r.bits(8)? as u8;
+            header.size = Some(frame_size(&mut r)?);
+        } else {
+            header.refresh_frame_flags = r.bits(8)? as u8;
+            for _ in 0..3 {
+                header.ref_frame_idx.push(r.bits(3)? as usize);
+                r.skip(1)?; // ref_frame_sign_bias
+            }
+            for &idx in header.ref_frame_idx.iter() {
+                if r.bit()? {
+                    header.size_from_slot = Some(idx);
+                    break;
+                }
+            }
+            if header.size_from_slot.is_none() {
+                header.size = Some(frame_size(&mut r)?);
+            }
+        }
+        Ok(header)
+    }
+}
+
+// sizes of the 8 reference slots, which inter frames can take their size from
+#[derive(Default)]
+pub struct Vp9Sizes {
+    refs: [Option<(u32, u32)>; 8],
+}
+
+impl Vp9Sizes {
+    pub fn new() -> Vp9Sizes {
+        Vp9Sizes::default()
+    }
+
+    // size of the frame if it is shown, for a single frame out of a superframe
+    pub fn push(&mut self, data: &[u8]) -> Result<Option<(u32, u32)>, &'static str> {
+        let header = Vp9Header::parse(data)?;
+        if let Some(slot) = header.show_existing_frame {
+            return match self.refs[slot] {
+                Some(size) => Ok(Some(size)),
+                None => Err("vp9 frame shows an empty reference slot"),
+            };
+        }
+        let size = match header.size_from_slot {
+            Some(slot) => self.refs[slot].ok_or("vp9 frame refers to an empty slot")?,
+            None => header.size.unwrap(),
+        };
+
+        for (slot, reference) in self.refs.iter_mut().enumerate() {
+            if header.refresh_frame_flags & (1 << slot) != 0 {
+                *reference = Some(size);
+            }
+        }
+        Ok(if header.show_frame { Some(size) } else { None })
+    }
+}
+
+fn sync_code(r: &mut BitReader) -> Result<(), &'static str> {
+    if r.bits(24)? != 0x498342 {
+        return Err("invalid vp9 sync code");
+    }
+    Ok(())
+}
+
+fn color_config(r: &mut BitReader, profile: u32) -> Result<(), &'static str> {
+    if profile >= 2 {
+        r.skip(1)?; // ten_or_twelve_bit
+    }
+    let color_space = r.bits(3)?;
+    if color_space != 7 {
+        r.skip(1)?; // color_range
+        if profile == 1 || profile == 3 {
+            r.skip(3)?; // subsampling_x, subsampling_y, reserved_zero
+        }
+    } else if profile == 1 || profile == 3 {
+        r.skip(1)?; // reserved_zero
+    }
+    Ok(())
+}
+
+fn frame_size(r: &mut BitReader) -> Result<(u32, u32), &'static str> {
+    Ok((r.bits(16)? + 1, r.bits(16)? + 1))
+}
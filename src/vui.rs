@@ -0,0 +1,149 @@
+// The parts of the VUI that H.264 and H.265 share, with the names of the
+// colour description code points (ITU-T H.273).
+
+use super::bits::BitReader;
+
+const EXTENDED_SAR: u32 = 255;
+
+// aspect_ratio_idc and the sample aspect ratio it stands for, (0, 0) when
+// unspecified
+pub fn aspect_ratio(r: &mut BitReader) -> Result<(u32, (u32, u32)), &'static str> {
+    let aspect_ratio_idc = r.bits(8)?;
+    let sar = match aspect_ratio_idc {
+        EXTENDED_SAR => (r.bits(16)?, r.bits(16)?),
+        1 => (1, 1),
+        2 => (12, 11),
+        3 => (10, 11),
+        4 => (16, 11),
+        5 => (40, 33),
+        6 => (24, 11),
+        7 => (20, 11),
+        8 => (32, 11),
+        9 => (80, 33),
+        10 => (18, 11),
+        11 => (15, 11),
+        12 => (64, 33),
+        13 => (160, 99),
+        14 => (4, 3),
+        15 => (3, 2),
+        16 => (2, 1),
+        _ => (0, 0),
+    };
+    Ok((aspect_ratio_idc, sar))
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VideoSignal {
+    pub video_format: u32,
+    pub video_full_range_flag: bool,
+    // colour_primaries, transfer_characteristics, matrix_coeffs
+    pub colour_description: Option<(u32, u32, u32)>,
+}
+
+impl VideoSignal {
+    pub fn parse(r: &mut BitReader) -> Result<VideoSignal, &'static str> {
+        let video_format = r.bits(3)?;
+        let video_full_range_flag = r.bit()?;
+        let colour_description = if r.bit()? {
+            Some((r.bits(8)?, r.bits(8)?, r.bits(8)?))
+        } else {
+            None
+        };
+        Ok(VideoSignal {
+            video_format,
+            video_full_range_flag,
+            colour_description,
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        let mut text = format!("{}, {} range",
+                               video_format_name(self.video_format),
+                               if self.video_full_range_flag { "full" } else { "limited" });
+        if let Some((primaries, transfer, matrix)) = self.colour_description {
+            text += &format!(", primaries {}, transfer {}, matrix {}",
+                             colour_primaries_name(primaries),
+                             transfer_characteristics_name(transfer),
+                             matrix_coeffs_name(matrix));
+        }
+        text
+    }
+}
+
+pub fn video_format_name(video_format: u32) -> &'static str {
+    match video_format {
+        0 => "component",
+        1 => "PAL",
+        2 => "NTSC",
+        3 => "SECAM",
+        4 => "MAC",
+        _ => "unspecified format",
+    }
+}
+
+pub fn colour_primaries_name(colour_primaries: u32) -> &'static str {
+    match colour_primaries {
+        1 => "BT.709",
+        4 => "BT.470M",
+        5 => "BT.601-625",
+        6 => "BT.601-525",
+        7 => "SMPTE240M",
+        8 => "generic film",
+        9 => "BT.2020",
+        10 => "XYZ",
+        11 => "DCI-P3",
+        12 => "Display P3",
+        22 => "EBU3213",
+        _ => "unspecified",
+    }
+}
+
+pub fn transfer_characteristics_name(transfer_characteristics: u32) -> &'static str {
+    match transfer_characteristics {
+        1 => "BT.709",
+        4 => "gamma 2.2",
+        5 => "gamma 2.8",
+        6 => "BT.601",
+        7 => "SMPTE240M",
+        8 => "linear",
+        9 => "log 100:1",
+        10 => "log 316:1",
+        11 => "IEC61966-2-4",
+        12 => "BT.1361",
+        13 => "sRGB",
+        14 => "BT.2020 10 bit",
+        15 => "BT.2020 12 bit",
+        16 => "PQ",
+        17 => "SMPTE428",
+        18 => "HLG",
+        _ => "unspecified",
+    }
+}
+
+pub fn matrix_coeffs_name(matrix_coeffs: u32) -> &'static str {
+    match matrix_coeffs {
+        0 => "identity",
+        1 => "BT.709",
+        4 => "FCC",
+        5 => "BT.601-625",
+        6 => "BT.601-525",
+        7 => "SMPTE240M",
+        8 => "YCgCo",
+        9 => "BT.2020 NCL",
+        10 => "BT.2020 CL",
+        11 => "SMPTE2085",
+        12 => "chroma NCL",
+        13 => "chroma CL",
+        14 => "ICtCp",
+        _ => "unspecified",
+    }
+}
+
+pub fn chroma_format_name(chroma_format_idc: u32) -> &'static str {
+    match chroma_format_idc {
+        0 => "4:0:0",
+        1 => "4:2:0",
+        2 => "4:2:2",
+        _ => "4:4:4",
+    }
+}
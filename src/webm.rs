@@ -5,12 +5,26 @@ use std::io::{Error, ErrorKind};
 use std::ffi::CString;
 use std::os::raw::c_void;
 use std::slice;
+use super::cut::{Cut, FrameInfo};
+use super::ivf::{is_key_frame, Ivf};
+use super::Bitstream;
 
 #[link(name = "nestegg")]
 extern "C" {
-    fn vpx_init(filename: *const i8) -> *mut c_void;
-    fn vpx_read(input: *mut c_void, length: *mut u32) -> *const u8;
-    fn vpx_destroy(input: *mut c_void);
+    pub fn vpx_init(filename: *const i8) -> *mut c_void;
+    pub fn vpx_read(input: *mut c_void, length: *mut u32) -> *const u8;
+    pub fn vpx_tstamp(input: *mut c_void) -> u64;
+    pub fn vpx_destroy(input: *mut c_void);
+}
+
+// opens a webm or ivf input for vpx_read, which can't take a null context
+pub fn vpx_open(input: &str) -> io::Result<*mut c_void> {
+    let c_input_string = CString::new(input).map_err(|_| Error::other("invalid input name"))?;
+    let input_ctx = unsafe { vpx_init(c_input_string.as_ptr()) };
+    if input_ctx.is_null() {
+        return Err(Error::new(ErrorKind::NotFound, format!("can't open {}", input)));
+    }
+    Ok(input_ctx)
 }
 
 pub struct Webm {
@@ -18,14 +32,30 @@ pub struct Webm {
     pub output: String,
     pub frame_num: usize,
     pub vp9: bool,
+    // segments to write instead of one file of frame_num frames
+    pub cut: Option<Cut>,
 }
 
 impl Webm {
     pub fn helper() {
-        println!("Usage: rsplit webm input.webm output.ivf frame_num vp8|vp9")
+        println!("Usage: rsplit webm input.webm output.ivf frame_num vp8|vp9 {}",
+                 Cut::usage())
     }
 
     pub fn new(args: &[String]) -> Result<Webm, &'static str> {
+        let mut cut = None;
+        let mut positional: Vec<String> = Vec::new();
+        for arg in args {
+            if let Some(parsed) = Cut::parse(arg) {
+                cut = Some(parsed?);
+            } else if arg.starts_with("--") {
+                return Err("unknown option for rsplit webm mode");
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        let args = &positional[..];
+
         let l = args.len() as usize;
         if l < 6 {
             return Err("too less arguments for rsplit webm mode");
@@ -53,19 +83,134 @@ impl Webm {
             output: output,
             frame_num: frame_num,
             vp9: vp9,
+            cut,
         })
     }
 
     pub fn run(&self) -> io::Result<()> {
         println!("Convert {} into {}", self.input, self.output);
-        let c_input_string =
-            unsafe { CString::from_vec_unchecked(self.input.clone().into_bytes()) };
-        let input_ctx = unsafe { vpx_init(c_input_string.as_ptr()) };
+        if let Some(ref cut) = self.cut {
+            return self.run_cut(cut);
+        }
+        let input_ctx = vpx_open(&self.input)?;
 
         let mut fo = try!(File::create(self.output.clone()));
-        let mut ivf_seq_header = [0u8; 32];
+        let ivf_seq_header = self.ivf_seq_header();
         let mut ivf_frame_header = [0u8; 12];
 
+        let bytes_write = fo.write(&ivf_seq_header).unwrap();
+        if bytes_write != 32 {
+            return Err(Error::new(ErrorKind::Other, "bytes write is not expected ..."));
+        }
+
+        let mut frame_no = 0;
+        while frame_no < self.frame_num {
+            let mut len = 0;
+            let ptr = unsafe { vpx_read(input_ctx, &mut len) };
+            let buffer = unsafe { slice::from_raw_parts(ptr, len as usize) };
+
+            if len != 0 {
+                println!("Frame {:04}: {:8} bytes", frame_no, len);
+            } else {
+                break;
+            }
+            frame_no += 1;
+
+            ivf_frame_header[0] = ((len >> 0) & 0xFF) as u8; //frameSize[0]
+            ivf_frame_header[1] = ((len >> 8) & 0xFF) as u8; //frameSize[1]
+            ivf_frame_header[2] = ((len >> 16) & 0xFF) as u8; //frameSize[2]
+            ivf_frame_header[3] = ((len >> 24) & 0xFF) as u8; //frameSize[3]
+            let bytes_write = fo.write(&ivf_frame_header).unwrap();
+            if bytes_write != 12 {
+                return Err(Error::new(ErrorKind::Other, "bytes write is not expected ..."));
+            }
+            let bytes_write = fo.write(&buffer[0..(len as usize)]).unwrap();
+            if bytes_write != (len as usize) {
+                return Err(Error::new(ErrorKind::Other, "bytes write is not expected ..."));
+            }
+        }
+
+        unsafe { vpx_destroy(input_ctx) };
+
+        Ok(())
+    }
+
+    // the segments of the cut list as IVF files named after the output, with
+    // the frame range like ivf mode
+    fn run_cut(&self, cut: &Cut) -> io::Result<()> {
+        let mut frames = Vec::new();
+        self.read_frames(|data, tstamp| {
+            frames.push(FrameInfo {
+                key: is_key_frame(self.vp9, data),
+                size: data.len(),
+                time: tstamp as f64 / 1e9,
+            })
+        })?;
+        let plan = cut.plan(&frames).map_err(Error::other)?;
+
+        let ivf = Ivf {
+            output: self.output.trim_end_matches(".ivf").to_string(),
+            ..Ivf::reader(self.vp9)
+        };
+        let mut ivf_seq_header = self.ivf_seq_header();
+        let mut segments = plan.iter();
+        let mut segment = segments.next();
+        let mut bs_container: Vec<Bitstream> = Vec::new();
+        let mut frame_no = 0;
+        let mut result = Ok(());
+        self.read_frames(|data, tstamp| {
+            if let Some(&(first, end)) = segment {
+                if first <= frame_no && frame_no < end {
+                    let mut frame_header = vec![0u8; 12];
+                    frame_header[0..4].copy_from_slice(&(data.len() as u32).to_le_bytes());
+                    // in milliseconds, the time base of the IVF header
+                    frame_header[4..12].copy_from_slice(&(tstamp / 1_000_000).to_le_bytes());
+                    bs_container.push(Bitstream {
+                        frame_header,
+                        frame_location: Vec::new(),
+                        nal_size: 0,
+                        frame_data: data.to_vec(),
+                        buf_size: data.len() as u32,
+                        idr_flag: is_key_frame(self.vp9, data),
+                    });
+                }
+                if frame_no + 1 == end {
+                    let mut pre_frame_no = first as i32;
+                    if result.is_ok() {
+                        result = ivf.write_to_file(&mut pre_frame_no,
+                                                   end as i32,
+                                                   &mut bs_container,
+                                                   &mut ivf_seq_header);
+                    }
+                    bs_container.clear();
+                    segment = segments.next();
+                }
+            }
+            frame_no += 1;
+        })?;
+        result
+    }
+
+    // calls back with each of the first frame_num frames and the timestamp
+    // of its packet in nanoseconds
+    fn read_frames<F: FnMut(&[u8], u64)>(&self, mut frame: F) -> io::Result<()> {
+        let input_ctx = vpx_open(&self.input)?;
+        for _ in 0..self.frame_num {
+            let mut len = 0;
+            let ptr = unsafe { vpx_read(input_ctx, &mut len) };
+            if len == 0 {
+                break;
+            }
+            frame(unsafe { slice::from_raw_parts(ptr, len as usize) },
+                  unsafe { vpx_tstamp(input_ctx) });
+        }
+        unsafe { vpx_destroy(input_ctx) };
+        Ok(())
+    }
+
+    fn ivf_seq_header(&self) -> [u8; 32] {
+        let mut ivf_seq_header = [0u8; 32];
+
         ivf_seq_header[0] = 'D' as u8;
         ivf_seq_header[1] = 'K' as u8;
         ivf_seq_header[2] = 'I' as u8;
@@ -102,41 +247,6 @@ impl Webm {
         ivf_seq_header[29] = 0; //unused[1]
         ivf_seq_header[30] = 0; //unused[2]
         ivf_seq_header[31] = 0; //unused[3]
-
-        let bytes_write = fo.write(&ivf_seq_header).unwrap();
-        if bytes_write != 32 {
-            return Err(Error::new(ErrorKind::Other, "bytes write is not expected ..."));
-        }
-
-        let mut frame_no = 0;
-        while frame_no < self.frame_num {
-            let mut len = 0;
-            let ptr = unsafe { vpx_read(input_ctx, &mut len) };
-            let buffer = unsafe { slice::from_raw_parts(ptr, len as usize) };
-
-            if len != 0 {
-                println!("Frame {:04}: {:8} bytes", frame_no, len);
-            } else {
-                break;
-            }
-            frame_no += 1;
-
-            ivf_frame_header[0] = ((len >> 0) & 0xFF) as u8; //frameSize[0]
-            ivf_frame_header[1] = ((len >> 8) & 0xFF) as u8; //frameSize[1]
-            ivf_frame_header[2] = ((len >> 16) & 0xFF) as u8; //frameSize[2]
-            ivf_frame_header[3] = ((len >> 24) & 0xFF) as u8; //frameSize[3]
-            let bytes_write = fo.write(&ivf_frame_header).unwrap();
-            if bytes_write != 12 {
-                return Err(Error::new(ErrorKind::Other, "bytes write is not expected ..."));
-            }
-            let bytes_write = fo.write(&buffer[0..(len as usize)]).unwrap();
-            if bytes_write != (len as usize) {
-                return Err(Error::new(ErrorKind::Other, "bytes write is not expected ..."));
-            }
-        }
-
-        unsafe { vpx_destroy(input_ctx) };
-
-        Ok(())
+        ivf_seq_header
     }
 }
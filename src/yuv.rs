@@ -1,28 +1,85 @@
 use std::fs::File;
 use std::io;
-use std::io::{Read, Write};
-use std::io::{Error, ErrorKind};
+use std::io::{BufWriter, Write};
+use super::frame;
+use super::frame::{ChromaFormat, FrameReader};
+
+// where the selected frames are written
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum YuvOutput {
+    // prefix_i_WxH.yuv for every frame
+    Frames,
+    // prefix.yuv with all of them
+    Single,
+    // prefix_k.yuv with N frames each
+    Chunks(usize),
+}
 
 pub struct Yuv {
-    pub input_yuv: String,
+    pub input_yuv: Vec<String>,
     pub output_prefix: String,
     pub frame_num: usize,
     pub frame_size: Vec<(i32, i32)>,
+    // first and last frame to write, the last one is open ended by default
+    pub range: (usize, Option<usize>),
+    pub every: usize,
+    pub output: YuvOutput,
+    pub chroma_format: ChromaFormat,
+    // take the frames from the inputs in turn instead of one after the other
+    pub interleave: bool,
 }
 
 impl Yuv {
     pub fn helper() {
-        println!("Usage: rsplit yuv input.yuv output_prefix frame_num frame_size1 \
-                  [...|frame_size2 ...]")
+        println!("Usage: rsplit yuv input.yuv|-[,input2.yuv ...] output_prefix frame_num \
+                  frame_size1 [...|frame_size2 ...]|@sizes.txt [--range=first-last] [--every=N] \
+                  [--single-file|--chunk=N] [--chroma=420|422|444] [--interleave]")
     }
 
     pub fn new(args: &[String]) -> Result<Yuv, &'static str> {
-        let l = args.len() as usize;
+        let mut range = (0, None);
+        let mut every = 1;
+        let mut output = YuvOutput::Frames;
+        let mut chroma_format = ChromaFormat::Yuv420;
+        let mut interleave = false;
+        let mut positional: Vec<String> = Vec::new();
+        for arg in args {
+            if let Some(frames) = arg.strip_prefix("--range=") {
+                range = parse_range(frames)?;
+            } else if let Some(n) = arg.strip_prefix("--every=") {
+                every = match n.parse::<usize>() {
+                    Ok(every) if every > 0 => every,
+                    _ => {
+                        return Err("can't parse every as positive usize");
+                    }
+                };
+            } else if arg == "--interleave" {
+                interleave = true;
+            } else if arg == "--single-file" {
+                output = YuvOutput::Single;
+            } else if let Some(n) = arg.strip_prefix("--chunk=") {
+                output = match n.parse::<usize>() {
+                    Ok(chunk) if chunk > 0 => YuvOutput::Chunks(chunk),
+                    _ => {
+                        return Err("can't parse chunk as positive usize");
+                    }
+                };
+            } else if let Some(chroma) = arg.strip_prefix("--chroma=") {
+                chroma_format = ChromaFormat::parse(chroma)?;
+            } else if arg.starts_with("--") {
+                return Err("unknown option for rsplit yuv mode");
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        let args = &positional[..];
+
+        let l = args.len();
         if l < 6 {
             return Err("too less arguments for rsplit yuv mode");
         }
 
-        let input_yuv = args[2].clone();
+        let input_yuv: Vec<String> = args[2].split(',').map(|s| s.to_string()).collect();
         let output_prefix = args[3].clone();
         let frame_num_opt = args[4].clone().parse::<usize>();
         let frame_num = match frame_num_opt {
@@ -32,85 +89,102 @@ impl Yuv {
             }
         };
 
-        let mut frame_size: Vec<(i32, i32)> = Vec::new();
-        for i in 0..frame_num {
-            if 5 + i >= l {
-                return Err("too less arguments for rsplit yuv mode");
-            } else if args[5 + i] == "..." {
-                if i == 0 {
-                    return Err("... can't be the first frame size");
-                } else {
-                    let (width, height) = frame_size[i - 1];
-                    for _ in i..frame_num {
-                        frame_size.push((width, height));
-                    }
-                    break;
-                }
-            } else {
-                let frame_size_str = args[5 + i].clone();
-                let nums: Vec<&str> = frame_size_str.split("x").collect();
-                if nums.len() != 2 {
-                    return Err("invalid frame size");
-                }
-                if let Ok(width) = nums[0].parse::<i32>() {
-                    if let Ok(height) = nums[1].parse::<i32>() {
-                        frame_size.push((width, height));
-                    } else {
-                        return Err("invalid frame height");
-                    }
-                } else {
-                    return Err("invalid frame width");
-                }
-            }
+        let frame_size = frame::parse_frame_sizes(args, 5, frame_num)?;
+
+        if frame_num > 0 && range.0 >= frame_num {
+            return Err("frame range starts after frame_num");
         }
 
         Ok(Yuv {
-            input_yuv: input_yuv,
-            output_prefix: output_prefix,
-            frame_num: frame_num,
-            frame_size: frame_size,
+            input_yuv,
+            output_prefix,
+            frame_num,
+            frame_size,
+            range,
+            every,
+            output,
+            chroma_format,
+            interleave,
         })
     }
 
     pub fn run(&self) -> io::Result<()> {
-        println!("rsplit {} into {}", self.input_yuv, self.output_prefix);
-        let mut fi = try!(File::open(self.input_yuv.clone()));
-        const BUF_SIZE: usize = 2048;
-        let mut buf = [0u8; BUF_SIZE];
+        println!("rsplit {} into {}", self.input_yuv.join(","), self.output_prefix);
+        // frame i comes from input i % n when interleaving, or from the
+        // inputs read as one sequence
+        let mut readers = if self.interleave {
+            self.input_yuv.iter().map(|path| FrameReader::open(path)).collect::<io::Result<_>>()?
+        } else {
+            vec![FrameReader::open_all(&self.input_yuv)?]
+        };
+        let mut buf = Vec::new();
+        let mut fo: Option<(String, BufWriter<File>)> = None;
+        let (first, last) = (self.range.0, self.range.1.unwrap_or(usize::MAX));
+        let mut written = 0;
+
+        for i in 0..self.frame_num.min(last.saturating_add(1)) {
+            let (width, height) = (self.frame_size[i].0 as usize, self.frame_size[i].1 as usize);
+            let bytes = self.chroma_format.frame_bytes(width, height);
+            let n = readers.len();
+            let fi: &mut FrameReader = &mut readers[i % n];
+            if i < first || (i - first) % self.every != 0 {
+                if !fi.skip_frame(bytes)? {
+                    return Err(fi.end_of_input());
+                }
+                continue;
+            }
+            if !fi.read_frame(&mut buf, bytes)? {
+                return Err(fi.end_of_input());
+            }
 
-        for i in 0..self.frame_num {
-            let output_yuv = self.output_prefix.clone() + "_" + &i.to_string() + "_" +
-                             &self.frame_size[i].0.to_string() +
-                             "x" + &self.frame_size[i].1.to_string() +
-                             ".yuv";
+            let output_yuv = match self.output {
+                YuvOutput::Frames => {
+                    self.output_prefix.clone() + "_" + &i.to_string() + "_" +
+                    &self.frame_size[i].0.to_string() + "x" +
+                    &self.frame_size[i].1.to_string() + ".yuv"
+                }
+                YuvOutput::Single => self.output_prefix.clone() + ".yuv",
+                YuvOutput::Chunks(n) => {
+                    self.output_prefix.clone() + "_" + &(written / n).to_string() + ".yuv"
+                }
+            };
             println!("Frame {} - {}x{} in {} ...",
                      i,
                      self.frame_size[i].0,
                      self.frame_size[i].1,
                      output_yuv);
-            let mut buf_size = (self.frame_size[i].0 * self.frame_size[i].1 +
-                                ((self.frame_size[i].0 + 1) / 2) *
-                                ((self.frame_size[i].1 + 1) / 2) *
-                                2) as usize;
-            let mut fo = try!(File::create(output_yuv));
-            while buf_size > 0 {
-                let bytes = if buf_size > BUF_SIZE {
-                    BUF_SIZE
-                } else {
-                    buf_size
-                };
-                buf_size -= bytes;
-                let bytes_read = fi.read(&mut buf[0..bytes]).unwrap();
-                if bytes_read != bytes {
-                    return Err(Error::new(ErrorKind::Other, "bytes read is not expected ..."));
-                }
-                let bytes_write = fo.write(&buf[0..bytes]).unwrap();
-                if bytes_write != bytes {
-                    return Err(Error::new(ErrorKind::Other, "bytes write is not expected ..."));
+            if fo.as_ref().is_none_or(|fo| fo.0 != output_yuv) {
+                if let Some((_, mut previous)) = fo.take() {
+                    previous.flush()?;
                 }
+                let file = File::create(&output_yuv)?;
+                fo = Some((output_yuv, BufWriter::new(file)));
             }
+            if let Some((_, ref mut fo)) = fo {
+                fo.write_all(&buf)?;
+            }
+            written += 1;
+        }
+
+        match fo {
+            Some((_, mut fo)) => fo.flush(),
+            None => Ok(()),
         }
+    }
+}
 
-        Ok(())
+// first-last, or first- for all frames from first on
+fn parse_range(frames: &str) -> Result<(usize, Option<usize>), &'static str> {
+    let (first, last) = match frames.split_once('-') {
+        Some(range) => range,
+        None => return Err("frame range must be first-last or first-"),
+    };
+    let first = first.parse::<usize>().map_err(|_| "can't parse first frame as usize")?;
+    if last.is_empty() {
+        return Ok((first, None));
+    }
+    match last.parse::<usize>() {
+        Ok(last) if last >= first => Ok((first, Some(last))),
+        _ => Err("last frame of the range must be a usize not before the first"),
     }
 }